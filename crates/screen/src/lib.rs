@@ -1,14 +1,21 @@
-use minifb::{Key, Key::*, Window, WindowOptions};
+mod png;
+mod saa5050;
 
-const MODE: u8 = 2;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ops::Range;
 
-// MODE 1 / 4 Physical size in pixels
-const WIDTH: usize = 320;
-const HEIGHT: usize = 256;
-const PIXEL_WIDTH: usize = get_pixel_width(MODE);
-const PIXELS_PER_BYTE: usize = get_pixels_per_byte(MODE);
-const COLUMNS: usize = WIDTH / PIXELS_PER_BYTE / PIXEL_WIDTH;
-const _ROWS: usize = WIDTH / 8; // 32
+use minifb::{Key, Key::*, KeyRepeat, Window, WindowOptions};
+
+// Physical size in pixels: 80 columns of 8 pixels (MODE 0), 256 raster lines
+// shown twice, or 25 teletext rows of 20 interlaced lines
+pub const WIDTH: usize = 640;
+pub const HEIGHT: usize = 512;
+pub const LINES: usize = HEIGHT / 2;
+
+const SCREENSHOT_KEY: Key = Key::F12;
+const SNAPSHOT_KEY: Key = Key::F11;
+const MONITOR_KEY: Key = Key::F10;
 
 // 3 bit RGB color
 #[allow(non_camel_case_types)]
@@ -18,14 +25,17 @@ type u3 = u8;
 #[allow(non_camel_case_types)]
 type u4 = u8;
 
-type Buffer =[u32; WIDTH * HEIGHT]; // 24 bits RGB
+// Logical to RGB colour, as looked up by video ULA
+pub type Palette = [u32; 16];
 
-pub struct Screen {
+type Buffer = Vec<u32>; // 24 bits RGB, WIDTH * HEIGHT
+
+// Rendered frame in memory, with or without a window to show it in
+pub struct Framebuffer {
   buffer: Buffer,
-  window: Window,
 }
 
-impl Screen {
+impl Framebuffer {
   const fn color_from_u3(rgb3: u3) -> u32 {
     assert!(rgb3 < 8);
     let mut rgb24 = 0u32;
@@ -49,47 +59,136 @@ impl Screen {
   const YELLOW:  u32 = Self::color_from_u3(0b110);
   const WHITE:   u32 = Self::color_from_u3(0b111);
 
-  const COLORS: [u32; 8] = [
-    Self::BLACK,
-    Self::BLUE,
-    Self::GREEN,
-    Self::CYAN,
-    Self::RED,
-    Self::MAGENTA,
-    Self::YELLOW,
-    Self::WHITE,
+  // BBC physical colours: b0 red, b1 green, b2 blue
+  const PHYSICAL_COLORS: [u32; 8] = [
+    Self::BLACK, Self::RED, Self::GREEN, Self::YELLOW,
+    Self::BLUE, Self::MAGENTA, Self::CYAN, Self::WHITE,
   ];
 
-  const MONOCHROME: [u32; 2] = [Screen::BLACK, Screen::WHITE];
-  const FOURCOLORS: [u32; 4] = [
-    Screen::BLACK, Screen::RED, Screen::YELLOW, Screen::WHITE
-  ];
-  const ALL_COLORS: [u32; 8] = [
-    Screen::BLACK, Screen::RED, Screen::GREEN, Screen::YELLOW,
-    Screen::BLUE, Screen::MAGENTA, Screen::CYAN, Screen::WHITE,
-  ];
+  pub const fn physical_color(color: u3) -> u32 {
+    Self::PHYSICAL_COLORS[color as usize & 0b111]
+  }
+
+  pub fn new() -> Self {
+    Framebuffer { buffer: vec![Self::BLACK; WIDTH * HEIGHT] }
+  }
+
+  // WIDTH * HEIGHT pixels, 24 bits RGB
+  pub fn pixels(&self) -> &[u32] {
+    &self.buffer
+  }
+
+  pub fn clear(&mut self) {
+    self.buffer.fill(Self::BLACK);
+  }
 
-  const fn get_colors(mode: u8) -> &'static [u32] {
-    match mode {
-      0 | 3 | 4 | 6 => &Self::MONOCHROME,
-      1 | 5         => &Self::FOURCOLORS,
-      2             => &Self::ALL_COLORS,
-      _             => panic!("MODE not implemented"),
+  pub fn save_png(&self, filename: &str) -> io::Result<()> {
+    let file = BufWriter::new(File::create(filename)?);
+    png::write_png(file, WIDTH, HEIGHT, &self.buffer)
+  }
+
+  // Serialise one raster line of bytes through the palette, stretched to fit
+  // the width of the screen. Bytes in cursor range are shown inverted
+  pub fn draw_line(&mut self, y: usize, bytes: &[u8], pixels_per_byte: usize,
+                   palette: &Palette, cursor: Option<Range<usize>>) {
+    assert!(y < LINES);
+    let pixels = bytes.len() * pixels_per_byte;
+    let pixel_width = (WIDTH / pixels.max(1)).max(1);
+    let row = &mut self.buffer[2 * y * WIDTH .. (2 * y + 1) * WIDTH];
+    let mut target = row.iter_mut();
+    for (x, byte) in bytes.iter().enumerate() {
+      let invert = match &cursor {
+        Some(range) if range.contains(&x) => Self::WHITE,
+        _ => Self::BLACK,
+      };
+      for color in PixelIter::new(*byte, pixels_per_byte as u8) {
+        let color = palette[color as usize] ^ invert;
+        for _ in 0..pixel_width {
+          if let Some(pixel) = target.next() {
+            *pixel = color;
+          }
+        }
+      }
     }
+    target.for_each(|pixel| *pixel = Self::BLACK);
+    self.buffer.copy_within(2 * y * WIDTH .. (2 * y + 1) * WIDTH,
+                            (2 * y + 1) * WIDTH);
   }
 
-  const PALETTE: Palette = make_palette(Self::get_colors(MODE));
+  // Teletext: rows of character codes through SAA5050, centered on screen.
+  // Cursor inverts given lines (0..20) of character cell
+  pub fn draw_teletext(&mut self, bytes: &[u8], columns: usize, flash_on: bool,
+                       cursor: Option<(usize, Range<usize>)>) {
+    use saa5050::{CELL_HEIGHT, CELL_WIDTH, render_row};
+    let columns = columns.clamp(1, WIDTH / CELL_WIDTH);
+    let width = columns * CELL_WIDTH;
+    let left = (WIDTH - width) / 2;
+    let rows = (bytes.len() / columns).min(HEIGHT / CELL_HEIGHT);
+    let top = (HEIGHT - rows * CELL_HEIGHT) / 2;
+    let mut pixels = vec![0; width * CELL_HEIGHT];
+    let mut lower = false;
+
+    self.clear();
+    for (row, bytes) in bytes.chunks_exact(columns).take(rows).enumerate() {
+      let double = render_row(bytes, flash_on, lower, &mut pixels);
+      lower = double && !lower;
+      for (line, colors) in pixels.chunks_exact(width).enumerate() {
+        let y = top + row * CELL_HEIGHT + line;
+        let target = &mut self.buffer[y * WIDTH + left .. y * WIDTH + left + width];
+        for (pixel, color) in target.iter_mut().zip(colors) {
+          *pixel = Self::physical_color(*color);
+        }
+        if let Some((index, lines)) = &cursor {
+          if index / columns == row && lines.contains(&line) {
+            let x = (index % columns) * CELL_WIDTH;
+            target[x .. x + CELL_WIDTH].iter_mut().for_each(|pixel| *pixel ^= Self::WHITE);
+          }
+        }
+      }
+    }
+  }
+}
 
+
+// Framebuffer shown in a window, which also reads the host keyboard
+pub struct Screen {
+  framebuffer: Framebuffer,
+  window: Window,
+}
+
+impl Screen {
   pub fn new(title: &str) -> Self {
-    let mut window_options = WindowOptions::default();
-    window_options.scale = minifb::Scale::X2;
+    let window_options = WindowOptions::default();
     let mut window = Window::new(title, WIDTH, HEIGHT, window_options)
       .unwrap_or_else(|e| { panic!("failed to open Window {}", e); });
 
     // Limit to max ~50 fps update rate
     window.set_target_fps(50);
 
-    Screen { buffer: [0u32; WIDTH * HEIGHT], window }
+    Screen { framebuffer: Framebuffer::new(), window }
+  }
+
+  pub fn framebuffer(&self) -> &Framebuffer {
+    &self.framebuffer
+  }
+
+  pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+    &mut self.framebuffer
+  }
+
+  // host key for a screenshot, kept from the BBC
+  pub fn screenshot_key(&self) -> bool {
+    self.window.is_key_pressed(SCREENSHOT_KEY, KeyRepeat::No)
+  }
+
+  // host key for a machine state snapshot
+  pub fn snapshot_key(&self) -> bool {
+    self.window.is_key_pressed(SNAPSHOT_KEY, KeyRepeat::No)
+  }
+
+  // host key to pause into the machine code monitor
+  pub fn monitor_key(&self) -> bool {
+    self.window.is_key_pressed(MONITOR_KEY, KeyRepeat::No)
   }
 
   pub fn done(&self) -> bool {
@@ -103,6 +202,7 @@ impl Screen {
     let pred = |key: &Key| {
       shift |= *key == Key::LeftShift || *key == RightShift;
       *key != Key::LeftShift && *key != RightShift
+        && *key != SCREENSHOT_KEY && *key != SNAPSHOT_KEY && *key != MONITOR_KEY
     };
     keys = keys.into_iter().filter(pred).collect::<Vec<Key>>();
     for key in keys.iter() {
@@ -115,33 +215,10 @@ impl Screen {
     // We unwrap here as we want this code to exit if it fails. Real
     // applications may want to handle this in a different way
     self.window
-      .update_with_buffer(&self.buffer, WIDTH, HEIGHT)
+      .update_with_buffer(self.framebuffer.pixels(), WIDTH, HEIGHT)
       .unwrap();
   }
 
-  pub fn blit(&mut self, video_ram: &[u8]) {
-    for y in 0..HEIGHT {
-      let mut target = y * WIDTH;
-      let mut source = (y % 8) + (y / 8) * COLUMNS * 8;
-      for x in 0..(COLUMNS) {
-        assert!(y < HEIGHT);
-        assert!(x * PIXELS_PER_BYTE < WIDTH);
-        assert!((x * 8 + (y % 8) + (y / 8) * WIDTH) < WIDTH * HEIGHT); 
-        assert_eq!(source, x * 8 + (y % 8) + (y / 8) * COLUMNS * 8); 
-        assert_eq!(target, x * PIXELS_PER_BYTE * PIXEL_WIDTH + y * WIDTH);
-        let byte = video_ram[source];
-        for color in PixelIter::new(byte, PIXELS_PER_BYTE as u8) {
-          let color = Self::PALETTE[color as usize];
-          for _ in 0..PIXEL_WIDTH {
-            self.buffer[target] = color;
-            target += 1;
-          }
-        }
-        source += 8;
-      }
-    }
-  }
-
   fn key_to_ascii(key: Key, shift: bool) -> u8 {
     if Key::A <= key && key <= Key::Z {
       if shift {
@@ -191,51 +268,14 @@ impl Screen {
   }
 }
 
-const fn get_pixel_width(mode: u8) -> usize {
-  match mode {
-//  0 | 3     => ½, // ??? TODO MODE 0, 3
-    1 | 4 | 6 => 1,
-    2 | 5     => 2,
-    _         => panic!("MODE not implemented"),
-  }
-}
-
-const fn get_pixels_per_byte(mode: u8) -> usize {
-  match mode {
-    0 | 3 | 4 | 6 => 8, // monochrome
-    1 | 5         => 4, // 4 colours
-    2             => 2, // MODE 2: full "16" colours
-    _         => panic!("MODE not implemented"),
-  }
-}
-
-type Palette = [u32; 16];
-const fn make_palette(colors: &[u32]) -> Palette {
-  let len = colors.len();
-  assert!(len == 2 || len == 4 || len == 8);
-  let mut palette = [colors[0]; 16];
-  let mut index = 1;
-  while index != 16 {
-    let color = match len {
-      2 =>  (0b1000 & index) >> 3,
-      4 => ((0b1000 & index) >> 2) | ((0b0010 & index)>>1),
-      8 =>  (0b0111 & index) >> 0,
-      _ => panic!("Invalid colors, len must be 2, 4, or 8"),
-    };
-    palette[index] = colors[color as usize];
-    index += 1;
-  }
-  palette
-}
-
 struct PixelIter {
   byte: u8,
-  count: u8, // shift count down: 8 (monochrome), 4 (MODE 1, 5) or 2 (16 colors)
+  count: u8, // shift count down: 8 (2 colours), 4 (4 colours), 2 (16 colours) or 1
 }
 
 impl PixelIter {
   const fn new(byte: u8, count: u8) -> Self {
-    assert!(count == 2 || count == 4 || count == 8);
+    assert!(count == 1 || count == 2 || count == 4 || count == 8);
     PixelIter { byte, count }
   }
 
@@ -280,7 +320,7 @@ mod tests {
   fn update(buffer: &mut Buffer, time: u32) {
     let mut seed = time as usize;
     for iter in buffer.iter_mut() {
-      *iter = Screen::COLORS[(seed / 10) % 6]; // write something more funny here!
+      *iter = Framebuffer::PHYSICAL_COLORS[(seed / 10) % 6]; // write something more funny here!
       seed += 1;
     }
   }
@@ -298,8 +338,8 @@ mod tests {
     let mut buffer = Screen::new("Test (ESC to exit, time out after 2s)");
     let mut time = 0;
     while !buffer.done() && time < 2 * 50 {
-      update(&mut buffer.buffer, time);
-      draw_line(&mut buffer.buffer);
+      update(&mut buffer.framebuffer.buffer, time);
+      draw_line(&mut buffer.framebuffer.buffer);
       buffer.show();
       time += 1;
     }
@@ -0,0 +1,89 @@
+// Portable Network Graphics writer: 8 bit RGB, no interlace, rows unfiltered
+// and deflated as a single IDAT chunk
+
+use std::io::{self, Write};
+
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+const RGB: u8 = 2; // colour type
+const NO_FILTER: u8 = 0;
+
+// CRC-32 over chunk type and data, as for zip and gzip
+fn crc32(bytes: &[u8]) -> u32 {
+  let mut crc = !0u32;
+  for byte in bytes {
+    crc ^= *byte as u32;
+    for _ in 0..8 {
+      crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+    }
+  }
+  !crc
+}
+
+fn write_chunk<W: Write>(output: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+  let mut typed = Vec::with_capacity(4 + data.len());
+  typed.extend_from_slice(kind);
+  typed.extend_from_slice(data);
+  output.write_all(&(data.len() as u32).to_be_bytes())?;
+  output.write_all(&typed)?;
+  output.write_all(&crc32(&typed).to_be_bytes())
+}
+
+// pixels are 24 bits RGB in u32, row by row
+pub fn write_png<W: Write>(mut output: W, width: usize, height: usize,
+                           pixels: &[u32]) -> io::Result<()> {
+  assert_eq!(pixels.len(), width * height);
+  output.write_all(&SIGNATURE)?;
+
+  let mut header = Vec::with_capacity(13);
+  header.extend_from_slice(&(width as u32).to_be_bytes());
+  header.extend_from_slice(&(height as u32).to_be_bytes());
+  header.extend_from_slice(&[8, RGB, 0, 0, 0]); // depth, colour, deflate, filter, interlace
+  write_chunk(&mut output, b"IHDR", &header)?;
+
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  let mut scan_line = Vec::with_capacity(1 + 3 * width);
+  for row in pixels.chunks_exact(width) {
+    scan_line.clear();
+    scan_line.push(NO_FILTER);
+    for pixel in row {
+      scan_line.extend_from_slice(&pixel.to_be_bytes()[1..]);
+    }
+    encoder.write_all(&scan_line)?;
+  }
+  write_chunk(&mut output, b"IDAT", &encoder.finish()?)?;
+  write_chunk(&mut output, b"IEND", &[])?;
+  output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Read;
+  use flate2::read::ZlibDecoder;
+
+  #[test]
+  fn crc() {
+    assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+  }
+
+  #[test]
+  fn two_by_two() {
+    let mut png = Vec::new();
+    write_png(&mut png, 2, 2, &[0xFF0000, 0x00FF00, 0x0000FF, 0xFFFFFF]).unwrap();
+    assert_eq!(png[..8], SIGNATURE);
+    assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+    assert_eq!(png[16..29], [0, 0, 0, 2, 0, 0, 0, 2, 8, RGB, 0, 0, 0]);
+    assert_eq!(png[png.len() - 12..], [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]);
+
+    let length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+    assert_eq!(png[37..41], *b"IDAT");
+    let mut rows = Vec::new();
+    ZlibDecoder::new(&png[41..41 + length]).read_to_end(&mut rows).unwrap();
+    assert_eq!(rows, [0, 0xFF, 0, 0, 0, 0xFF, 0,
+                      0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF]);
+  }
+}
@@ -0,0 +1,330 @@
+// Mullard SAA5050 teletext character generator
+// Decodes a row of 7 bit character codes into 12 x 20 pixel character cells
+// (6 x 10 dot matrix, with character rounding), following the serial
+// attribute rules: control codes occupy a cell, displayed as a space (or the
+// held graphics character) and take effect "set at" or "set after" the cell.
+
+// 3 bit RGB color, as BBC physical colour: b0 red, b1 green, b2 blue
+#[allow(non_camel_case_types)]
+type u3 = u8;
+
+pub const CELL_WIDTH: usize = 12;
+pub const CELL_HEIGHT: usize = 20;
+
+// UK character set, 6 x 10 dots per character, b5 is left most
+const FONT: [[u8; 10]; 96] = [
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x20 space
+  [0x00, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04, 0x00, 0x00], // 0x21 !
+  [0x00, 0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x22 "
+  [0x00, 0x06, 0x09, 0x08, 0x1E, 0x08, 0x08, 0x1F, 0x00, 0x00], // 0x23 £
+  [0x00, 0x0E, 0x15, 0x14, 0x0E, 0x05, 0x15, 0x0E, 0x00, 0x00], // 0x24 $
+  [0x00, 0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00, 0x00], // 0x25 %
+  [0x00, 0x08, 0x14, 0x14, 0x08, 0x15, 0x12, 0x0D, 0x00, 0x00], // 0x26 &
+  [0x00, 0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x27 '
+  [0x00, 0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00, 0x00], // 0x28 (
+  [0x00, 0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00, 0x00], // 0x29 )
+  [0x00, 0x04, 0x15, 0x0E, 0x04, 0x0E, 0x15, 0x04, 0x00, 0x00], // 0x2a *
+  [0x00, 0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00, 0x00, 0x00], // 0x2b +
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0x08, 0x00], // 0x2c ,
+  [0x00, 0x00, 0x00, 0x00, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x2d -
+  [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00], // 0x2e .
+  [0x00, 0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00, 0x00], // 0x2f /
+  [0x00, 0x04, 0x0A, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00, 0x00], // 0x30 0
+  [0x00, 0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00, 0x00], // 0x31 1
+  [0x00, 0x0E, 0x11, 0x01, 0x06, 0x08, 0x10, 0x1F, 0x00, 0x00], // 0x32 2
+  [0x00, 0x1F, 0x01, 0x02, 0x06, 0x01, 0x11, 0x0E, 0x00, 0x00], // 0x33 3
+  [0x00, 0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02, 0x00, 0x00], // 0x34 4
+  [0x00, 0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E, 0x00, 0x00], // 0x35 5
+  [0x00, 0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E, 0x00, 0x00], // 0x36 6
+  [0x00, 0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00, 0x00], // 0x37 7
+  [0x00, 0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E, 0x00, 0x00], // 0x38 8
+  [0x00, 0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C, 0x00, 0x00], // 0x39 9
+  [0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00], // 0x3a :
+  [0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x04, 0x04, 0x08, 0x00], // 0x3b ;
+  [0x00, 0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00, 0x00], // 0x3c <
+  [0x00, 0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00], // 0x3d =
+  [0x00, 0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00, 0x00], // 0x3e >
+  [0x00, 0x0E, 0x11, 0x02, 0x04, 0x04, 0x00, 0x04, 0x00, 0x00], // 0x3f ?
+  [0x00, 0x0E, 0x11, 0x17, 0x15, 0x17, 0x10, 0x0E, 0x00, 0x00], // 0x40 @
+  [0x00, 0x04, 0x0A, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x00, 0x00], // 0x41 A
+  [0x00, 0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00, 0x00], // 0x42 B
+  [0x00, 0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E, 0x00, 0x00], // 0x43 C
+  [0x00, 0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E, 0x00, 0x00], // 0x44 D
+  [0x00, 0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F, 0x00, 0x00], // 0x45 E
+  [0x00, 0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10, 0x00, 0x00], // 0x46 F
+  [0x00, 0x0E, 0x11, 0x10, 0x10, 0x13, 0x11, 0x0F, 0x00, 0x00], // 0x47 G
+  [0x00, 0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00, 0x00], // 0x48 H
+  [0x00, 0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00, 0x00], // 0x49 I
+  [0x00, 0x01, 0x01, 0x01, 0x01, 0x01, 0x11, 0x0E, 0x00, 0x00], // 0x4a J
+  [0x00, 0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00, 0x00], // 0x4b K
+  [0x00, 0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F, 0x00, 0x00], // 0x4c L
+  [0x00, 0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00, 0x00], // 0x4d M
+  [0x00, 0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00, 0x00], // 0x4e N
+  [0x00, 0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00, 0x00], // 0x4f O
+  [0x00, 0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10, 0x00, 0x00], // 0x50 P
+  [0x00, 0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D, 0x00, 0x00], // 0x51 Q
+  [0x00, 0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11, 0x00, 0x00], // 0x52 R
+  [0x00, 0x0E, 0x11, 0x10, 0x0E, 0x01, 0x11, 0x0E, 0x00, 0x00], // 0x53 S
+  [0x00, 0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x00], // 0x54 T
+  [0x00, 0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00, 0x00], // 0x55 U
+  [0x00, 0x11, 0x11, 0x11, 0x0A, 0x0A, 0x04, 0x04, 0x00, 0x00], // 0x56 V
+  [0x00, 0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A, 0x00, 0x00], // 0x57 W
+  [0x00, 0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11, 0x00, 0x00], // 0x58 X
+  [0x00, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04, 0x00, 0x00], // 0x59 Y
+  [0x00, 0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F, 0x00, 0x00], // 0x5a Z
+  [0x00, 0x00, 0x04, 0x08, 0x1F, 0x08, 0x04, 0x00, 0x00, 0x00], // 0x5b ←
+  [0x00, 0x10, 0x10, 0x10, 0x16, 0x02, 0x04, 0x08, 0x0E, 0x00], // 0x5c ½
+  [0x00, 0x00, 0x04, 0x02, 0x1F, 0x02, 0x04, 0x00, 0x00, 0x00], // 0x5d →
+  [0x00, 0x04, 0x0E, 0x15, 0x04, 0x04, 0x04, 0x04, 0x00, 0x00], // 0x5e ↑
+  [0x00, 0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A, 0x00, 0x00], // 0x5f #
+  [0x00, 0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00], // 0x60 —
+  [0x00, 0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00, 0x00], // 0x61 a
+  [0x00, 0x10, 0x10, 0x1E, 0x11, 0x11, 0x11, 0x1E, 0x00, 0x00], // 0x62 b
+  [0x00, 0x00, 0x00, 0x0F, 0x10, 0x10, 0x10, 0x0F, 0x00, 0x00], // 0x63 c
+  [0x00, 0x01, 0x01, 0x0F, 0x11, 0x11, 0x11, 0x0F, 0x00, 0x00], // 0x64 d
+  [0x00, 0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E, 0x00, 0x00], // 0x65 e
+  [0x00, 0x06, 0x08, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x00, 0x00], // 0x66 f
+  [0x00, 0x00, 0x00, 0x0F, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 0x67 g
+  [0x00, 0x10, 0x10, 0x1E, 0x11, 0x11, 0x11, 0x11, 0x00, 0x00], // 0x68 h
+  [0x00, 0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E, 0x00, 0x00], // 0x69 i
+  [0x00, 0x04, 0x00, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x18], // 0x6a j
+  [0x00, 0x08, 0x08, 0x09, 0x0A, 0x0C, 0x0A, 0x09, 0x00, 0x00], // 0x6b k
+  [0x00, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00, 0x00], // 0x6c l
+  [0x00, 0x00, 0x00, 0x1A, 0x15, 0x15, 0x15, 0x15, 0x00, 0x00], // 0x6d m
+  [0x00, 0x00, 0x00, 0x1E, 0x11, 0x11, 0x11, 0x11, 0x00, 0x00], // 0x6e n
+  [0x00, 0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00, 0x00], // 0x6f o
+  [0x00, 0x00, 0x00, 0x1E, 0x11, 0x11, 0x11, 0x1E, 0x10, 0x10], // 0x70 p
+  [0x00, 0x00, 0x00, 0x0F, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x01], // 0x71 q
+  [0x00, 0x00, 0x00, 0x0B, 0x0C, 0x08, 0x08, 0x08, 0x00, 0x00], // 0x72 r
+  [0x00, 0x00, 0x00, 0x0F, 0x10, 0x0E, 0x01, 0x1E, 0x00, 0x00], // 0x73 s
+  [0x00, 0x08, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x06, 0x00, 0x00], // 0x74 t
+  [0x00, 0x00, 0x00, 0x11, 0x11, 0x11, 0x11, 0x0F, 0x00, 0x00], // 0x75 u
+  [0x00, 0x00, 0x00, 0x11, 0x11, 0x0A, 0x0A, 0x04, 0x00, 0x00], // 0x76 v
+  [0x00, 0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A, 0x00, 0x00], // 0x77 w
+  [0x00, 0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x00, 0x00], // 0x78 x
+  [0x00, 0x00, 0x00, 0x11, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 0x79 y
+  [0x00, 0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F, 0x00, 0x00], // 0x7a z
+  [0x00, 0x10, 0x10, 0x10, 0x11, 0x03, 0x05, 0x0F, 0x01, 0x00], // 0x7b ¼
+  [0x00, 0x0A, 0x0A, 0x0A, 0x0A, 0x0A, 0x0A, 0x0A, 0x00, 0x00], // 0x7c ‖
+  [0x00, 0x18, 0x04, 0x18, 0x05, 0x1B, 0x05, 0x0F, 0x01, 0x00], // 0x7d ¾
+  [0x00, 0x00, 0x04, 0x00, 0x1F, 0x00, 0x04, 0x00, 0x00, 0x00], // 0x7e ÷
+  [0x3F, 0x3F, 0x3F, 0x3F, 0x3F, 0x3F, 0x3F, 0x3F, 0x3F, 0x3F], // 0x7f ■
+];
+
+// Character shape and how it's drawn
+#[derive(Clone, Copy)]
+enum Glyph {
+  Alpha(u8),
+  Mosaic(u8, bool), // code, separated
+}
+
+impl Glyph {
+  const SPACE: Glyph = Glyph::Alpha(0x20);
+
+  // 12 pixels of line v in 0..20 (of single height character), as bit mask
+  fn line(&self, v: usize) -> u16 {
+    match *self {
+      Glyph::Alpha(code) => Self::rounded(&FONT[code as usize - 0x20], v),
+      Glyph::Mosaic(code, separated) => Self::mosaic(code, separated, v),
+    }
+  }
+
+  // Double dots to 12 x 20 and fill in diagonals with half dots: even lines
+  // are smoothed towards row above, odd lines towards row below
+  fn rounded(glyph: &[u8; 10], v: usize) -> u16 {
+    let row = v / 2;
+    let a = glyph[row];
+    let b = match (v & 1, row) {
+      (0, 0) => 0,
+      (0, _) => glyph[row - 1],
+      (_, 9) => 0,
+      (_, _) => glyph[row + 1],
+    };
+    let left = (a >> 1) & b & !a & !(b >> 1);
+    let right = (a << 1) & b & !a & !(b << 1);
+    let mut pixels = 0u16;
+    for x in (0..6).rev() {
+      let dot = 1 << x;
+      pixels <<= 2;
+      if (a | left) & dot != 0 { pixels |= 0b10; }
+      if (a | right) & dot != 0 { pixels |= 0b01; }
+    }
+    pixels
+  }
+
+  // 2 x 3 blocks: b0 b1 (6 lines), b2 b3 (8 lines), b4 b6 (6 lines)
+  fn mosaic(code: u8, separated: bool, v: usize) -> u16 {
+    let (bits, gap) = match v {
+      0 ..= 5  => (code & 0b11, v >= 4),
+      6 ..= 13 => ((code >> 2) & 0b11, v >= 12),
+      _        => (((code >> 4) & 0b01) | ((code >> 5) & 0b10), v >= 18),
+    };
+    if separated && gap {
+      return 0;
+    }
+    // separated blocks lose their left most dot (and bottom line)
+    let block = if separated { 0b1111 } else { 0b11_1111 };
+    let mut pixels = 0u16;
+    if bits & 0b01 != 0 { pixels |= block << 6; }
+    if bits & 0b10 != 0 { pixels |= block; }
+    pixels
+  }
+}
+
+// Render a row of character codes into CELL_HEIGHT lines of `bytes.len() *
+// CELL_WIDTH` physical colours. Lower half of double height characters are
+// shown when row follows a row with double height. Returns whether the row
+// contains double height characters.
+pub fn render_row(bytes: &[u8], flash_on: bool, lower: bool,
+                  pixels: &mut [u3]) -> bool {
+  let width = bytes.len() * CELL_WIDTH;
+  assert_eq!(pixels.len(), width * CELL_HEIGHT);
+
+  let mut foreground = 7;
+  let mut background = 0;
+  let mut graphics = false;
+  let mut separated = false;
+  let mut flash = false;
+  let mut double = false;
+  let mut conceal = false;
+  let mut hold = false;
+  let mut held = Glyph::SPACE;
+  let mut has_double = false;
+
+  for (column, byte) in bytes.iter().enumerate() {
+    let code = byte & 0x7F;
+
+    // "Set at" codes
+    match code {
+      0x09 => flash = false,
+      0x0C => {
+        if double { held = Glyph::SPACE; }
+        double = false;
+      },
+      0x18 => conceal = true,
+      0x19 => separated = false,
+      0x1A => separated = true,
+      0x1C => background = 0,
+      0x1D => background = foreground,
+      0x1E => hold = true,
+      _ => {},
+    }
+
+    let glyph = if code < 0x20 {
+      if hold && graphics { held } else { Glyph::SPACE }
+    } else if graphics && code & 0x20 != 0 {
+      held = Glyph::Mosaic(code, separated);
+      held
+    } else {
+      Glyph::Alpha(code)
+    };
+
+    let hidden = conceal || (flash && !flash_on) || (lower && !double);
+    let x = column * CELL_WIDTH;
+    for line in 0..CELL_HEIGHT {
+      let v = match (double, lower) {
+        (false, _)    => line,
+        (true, false) => line / 2,
+        (true, true)  => CELL_HEIGHT / 2 + line / 2,
+      };
+      let dots = if hidden { 0 } else { glyph.line(v) };
+      let target = &mut pixels[line * width + x .. line * width + x + CELL_WIDTH];
+      for (bit, pixel) in target.iter_mut().enumerate() {
+        let set = dots & (1 << (CELL_WIDTH - 1 - bit)) != 0;
+        *pixel = if set { foreground } else { background };
+      }
+    }
+
+    // "Set after" codes
+    match code {
+      0x01 ..= 0x07 => {
+        if graphics { held = Glyph::SPACE; }
+        foreground = code;
+        graphics = false;
+        conceal = false;
+      },
+      0x08 => flash = true,
+      0x0D => {
+        if !double { held = Glyph::SPACE; }
+        double = true;
+        has_double = true;
+      },
+      0x11 ..= 0x17 => {
+        if !graphics { held = Glyph::SPACE; }
+        foreground = code & 0b111;
+        graphics = true;
+        conceal = false;
+      },
+      0x1F => hold = false,
+      _ => {},
+    }
+  }
+  has_double
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn render(bytes: &[u8], lower: bool) -> (Vec<u3>, bool) {
+    let mut pixels = vec![0xFF; bytes.len() * CELL_WIDTH * CELL_HEIGHT];
+    let double = render_row(bytes, true, lower, &mut pixels);
+    (pixels, double)
+  }
+
+  fn cell(pixels: &[u3], columns: usize, column: usize, line: usize) -> &[u3] {
+    let start = line * columns * CELL_WIDTH + column * CELL_WIDTH;
+    &pixels[start .. start + CELL_WIDTH]
+  }
+
+  #[test]
+  fn rounding() {
+    // "/" of 0x2F: row 2 has right most dot, row 3 the next one to the left
+    assert_eq!(Glyph::rounded(&FONT[0x0F], 4), 0b0000_0000_0011);
+    assert_eq!(Glyph::rounded(&FONT[0x0F], 5), 0b0000_0000_0111);
+    assert_eq!(Glyph::rounded(&FONT[0x0F], 6), 0b0000_0000_1110);
+    assert_eq!(Glyph::rounded(&FONT[0x0F], 7), 0b0000_0001_1100);
+  }
+
+  #[test]
+  fn colours_and_graphics() {
+    // red alpha "A", green graphics full block, separated
+    let bytes = [0x81, 0x41, 0x92, 0xFF, 0x9A, 0xFF];
+    let (pixels, double) = render(&bytes, false);
+    assert!(!double);
+    // control codes are spaces in black
+    assert!(cell(&pixels, 6, 0, 5).iter().all(|p| *p == 0));
+    // "A" top: single dot in middle, in red
+    assert_eq!(cell(&pixels, 6, 1, 2), [0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0]);
+    // contiguous block fills the cell
+    assert!(cell(&pixels, 6, 3, 19).iter().all(|p| *p == 2));
+    // separated block leaves gaps
+    assert_eq!(cell(&pixels, 6, 5, 0), [0, 0, 2, 2, 2, 2, 0, 0, 2, 2, 2, 2]);
+    assert!(cell(&pixels, 6, 5, 19).iter().all(|p| *p == 0));
+  }
+
+  #[test]
+  fn hold_graphics() {
+    // graphics red, block, hold, yellow (shows held block in red)
+    let bytes = [0x91, 0x7F, 0x9E, 0x93, 0x20];
+    let (pixels, _) = render(&bytes, false);
+    assert!(cell(&pixels, 5, 2, 0).iter().all(|p| *p == 1));
+    assert!(cell(&pixels, 5, 3, 0).iter().all(|p| *p == 1));
+    assert!(cell(&pixels, 5, 4, 0).iter().all(|p| *p == 0));
+  }
+
+  #[test]
+  fn new_background_and_double_height() {
+    // blue, new background, red, double height, block, normal height, "A"
+    let bytes = [0x84, 0x9D, 0x81, 0x8D, 0x7F, 0x8C, 0x41];
+    let (pixels, double) = render(&bytes, false);
+    assert!(double);
+    assert!(cell(&pixels, 7, 1, 0).iter().all(|p| *p == 4));
+    assert!(cell(&pixels, 7, 4, 0).iter().all(|p| *p == 1));
+    assert_eq!(cell(&pixels, 7, 6, 2), [4, 4, 4, 4, 4, 4, 1, 1, 4, 4, 4, 4]);
+    // second row: normal height characters are hidden
+    let (pixels, _) = render(&bytes, true);
+    assert!(cell(&pixels, 7, 4, 19).iter().all(|p| *p == 1));
+    assert!(cell(&pixels, 7, 6, 2).iter().all(|p| *p == 4));
+  }
+}
@@ -0,0 +1,108 @@
+// Cassette deck
+// Plays a tape through the serial ULA, while its relay runs the motor. The
+// tape is a sequence of tones: high tone carrier, silence and framed bytes,
+// as loaded from a UEF image. Bytes go to the 6850 receive path as they
+// start, carrier drives its data carrier detect input.
+
+pub mod uef;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tone {
+  Carrier(u64),   // high tone, us
+  Gap(u64),       // silence, us
+  Byte(u8, u64),  // start bit, data, stop bit(s), us
+}
+
+impl Tone {
+  pub const fn duration(&self) -> u64 {
+    match *self {
+      Tone::Carrier(us) | Tone::Gap(us) | Tone::Byte(_, us) => us,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Cassette {
+  tape: Vec<Tone>,
+  position: usize,      // current tone
+  elapsed: u64,         // us into current tone
+  started: bool,        // current tone reached the head
+  received: Option<u8>, // byte started, not yet taken by the ACIA
+}
+
+impl Cassette {
+  pub fn new() -> Self {
+    Cassette::default()
+  }
+
+  pub fn insert(&mut self, tape: Vec<Tone>) {
+    *self = Cassette { tape, ..Cassette::default() };
+  }
+
+  pub fn load_uef(&mut self, filename: &str) -> std::io::Result<()> {
+    self.insert(uef::load(filename)?);
+    Ok(())
+  }
+
+  pub fn rewind(&mut self) {
+    self.position = 0;
+    self.elapsed = 0;
+    self.started = false;
+    self.received = None;
+  }
+
+  pub fn at_end(&self) -> bool {
+    self.position >= self.tape.len()
+  }
+
+  // high tone under the head
+  pub fn carrier(&self) -> bool {
+    matches!(self.tape.get(self.position), Some(Tone::Carrier(_)))
+  }
+
+  pub fn take_byte(&mut self) -> Option<u8> {
+    self.received.take()
+  }
+
+  // motor running for some microseconds
+  pub fn play(&mut self, mut us: u64) {
+    while let Some(&tone) = self.tape.get(self.position) {
+      if !self.started {
+        self.started = true;
+        if let Tone::Byte(byte, _) = tone {
+          if self.received.replace(byte).is_some() {
+            log::debug!("cassette: byte lost");
+          }
+        }
+      }
+      let remaining = tone.duration() - self.elapsed;
+      if us < remaining {
+        self.elapsed += us;
+        break;
+      }
+      us -= remaining;
+      self.position += 1;
+      self.elapsed = 0;
+      self.started = false;
+    }
+  }
+}
+
+#[test]
+fn play() {
+  let mut cassette = Cassette::new();
+  cassette.insert(vec![Tone::Carrier(1000), Tone::Byte(0x2A, 100),
+                       Tone::Byte(0x41, 100), Tone::Gap(50)]);
+  assert!(cassette.carrier());
+  cassette.play(999);
+  assert!(cassette.carrier());
+  assert_eq!(cassette.take_byte(), None);
+  cassette.play(1);
+  assert!(!cassette.carrier());
+  assert_eq!(cassette.take_byte(), Some(0x2A));
+  cassette.play(250);
+  assert_eq!(cassette.take_byte(), Some(0x41));
+  assert!(cassette.at_end());
+  cassette.rewind();
+  assert!(cassette.carrier());
+}
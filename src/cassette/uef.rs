@@ -0,0 +1,197 @@
+// Unified Emulator Format tape images, usually gzipped
+// A sequence of chunks: 16 bit id, 32 bit length, then data. Only the tape
+// chunks are played, other chunks (origin, instructions, ...) are skipped.
+
+use std::fs::File;
+use std::io;
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use super::Tone;
+
+const MAGIC: &[u8] = b"UEF File!\0";
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+  u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn f32_at(data: &[u8], offset: usize) -> f32 {
+  f32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+struct Encoding {
+  baud: u32,          // data bits per second
+  base_frequency: f32, // Hz, carrier is twice this
+}
+
+impl Encoding {
+  // carrier cycles and integer gaps are counted at twice base frequency
+  fn cycles_us(&self, cycles: u64) -> u64 {
+    (cycles as f64 * 1e6 / (2.0 * self.base_frequency as f64)) as u64
+  }
+
+  fn frame_us(&self, bits: u64) -> u64 {
+    bits * 1_000_000 / self.baud as u64
+  }
+}
+
+// 8 bit bytes framed by a start and a stop bit
+fn implicit_bytes(data: &[u8], encoding: &Encoding, tape: &mut Vec<Tone>) {
+  let us = encoding.frame_us(1 + 8 + 1);
+  tape.extend(data.iter().map(|&byte| Tone::Byte(byte, us)));
+}
+
+// bits per packet, parity 'N', 'E' or 'O' and stop bits, then packets
+fn defined_bytes(data: &[u8], encoding: &Encoding, tape: &mut Vec<Tone>) {
+  if data.len() < 3 {
+    return;
+  }
+  let bits = data[0] as u64;
+  let parity = if data[1] == b'N' { 0 } else { 1 };
+  let stop = (data[2] as i8).unsigned_abs() as u64;
+  let us = encoding.frame_us(1 + bits + parity + stop);
+  let mask = ((1u16 << bits.min(8)) - 1) as u8;
+  tape.extend(data[3..].iter().map(|&byte| Tone::Byte(byte & mask, us)));
+}
+
+// individual bits, LSB first: find 8N1 frames amongst high tone
+fn explicit_bits(data: &[u8], encoding: &Encoding, tape: &mut Vec<Tone>) {
+  if data.is_empty() {
+    return;
+  }
+  let count = ((data.len() - 1) * 8).saturating_sub(data[0] as usize);
+  let bit = |n: usize| data[1 + n / 8] >> (n % 8) & 1 == 1;
+  let bit_us = encoding.frame_us(1);
+  let mut n = 0;
+  while n < count {
+    if bit(n) || n + 10 > count {
+      tape.push(Tone::Carrier(bit_us));
+      n += 1;
+    } else {
+      let byte = (0..8).fold(0, |byte, i| byte | (bit(n + 1 + i) as u8) << i);
+      tape.push(Tone::Byte(byte, 10 * bit_us));
+      n += 10;
+    }
+  }
+}
+
+pub fn parse(bytes: &[u8]) -> io::Result<Vec<Tone>> {
+  let mut unzipped = Vec::new();
+  let bytes = if bytes.starts_with(&[0x1F, 0x8B]) {
+    GzDecoder::new(bytes).read_to_end(&mut unzipped)?;
+    &unzipped[..]
+  } else {
+    bytes
+  };
+  if !bytes.starts_with(MAGIC) || bytes.len() < MAGIC.len() + 2 {
+    return Err(invalid("not a UEF file"));
+  }
+
+  let mut encoding = Encoding { baud: 1200, base_frequency: 1200.0 };
+  let mut tape = Vec::new();
+  let mut offset = MAGIC.len() + 2; // minor, major version
+  while offset + 6 <= bytes.len() {
+    let id = u16_at(bytes, offset);
+    let length = u32::from_le_bytes(bytes[offset + 2..offset + 6].try_into().unwrap()) as usize;
+    let data = bytes.get(offset + 6..offset + 6 + length)
+      .ok_or_else(|| invalid("truncated UEF chunk"))?;
+    offset += 6 + length;
+    match (id, length) {
+      (0x0100, _) => implicit_bytes(data, &encoding, &mut tape),
+      (0x0102, _) => explicit_bits(data, &encoding, &mut tape),
+      (0x0104, _) => defined_bytes(data, &encoding, &mut tape),
+      (0x0110, 2..) => {
+        tape.push(Tone::Carrier(encoding.cycles_us(u16_at(data, 0) as u64)));
+      },
+      (0x0111, 4..) => {
+        // carrier tone with dummy byte &AA
+        tape.push(Tone::Carrier(encoding.cycles_us(u16_at(data, 0) as u64)));
+        implicit_bytes(&[0xAA], &encoding, &mut tape);
+        tape.push(Tone::Carrier(encoding.cycles_us(u16_at(data, 2) as u64)));
+      },
+      (0x0112, 2..) => {
+        tape.push(Tone::Gap(encoding.cycles_us(u16_at(data, 0) as u64)));
+      },
+      (0x0113, 4..) => encoding.base_frequency = f32_at(data, 0),
+      (0x0114, 3..) => {
+        // security cycles: neither carrier nor data
+        let cycles = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+        tape.push(Tone::Gap(encoding.cycles_us(cycles as u64)));
+      },
+      (0x0116, 4..) => {
+        tape.push(Tone::Gap((f32_at(data, 0) as f64 * 1e6) as u64));
+      },
+      (0x0117, 2..) => encoding.baud = u16_at(data, 0).max(1) as u32,
+      _ => log::debug!("UEF chunk &{id:04X} ({length} bytes) skipped"),
+    }
+  }
+  tape.retain(|tone| tone.duration() > 0);
+  Ok(tape)
+}
+
+pub fn load(filename: &str) -> io::Result<Vec<Tone>> {
+  let mut bytes = Vec::new();
+  File::open(filename)?.read_to_end(&mut bytes)?;
+  parse(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use flate2::Compression;
+  use flate2::write::GzEncoder;
+  use std::io::Write;
+
+  fn chunk(id: u16, data: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_le_bytes().to_vec();
+    chunk.extend((data.len() as u32).to_le_bytes());
+    chunk.extend(data);
+    chunk
+  }
+
+  #[test]
+  fn chunks() {
+    let mut uef = MAGIC.to_vec();
+    uef.extend([10, 0]);
+    uef.extend(chunk(0x0000, b"origin\0"));
+    uef.extend(chunk(0x0110, &2400u16.to_le_bytes()));       // 1 second
+    uef.extend(chunk(0x0100, b"*AB"));
+    uef.extend(chunk(0x0112, &240u16.to_le_bytes()));        // 0.1 second
+    uef.extend(chunk(0x0117, &300u16.to_le_bytes()));
+    uef.extend(chunk(0x0104, &[7, b'E', 1, 0xFF]));
+    uef.extend(chunk(0x0116, &0.5f32.to_le_bytes()));
+    let tape = parse(&uef).unwrap();
+    assert_eq!(tape, vec![
+      Tone::Carrier(1_000_000),
+      Tone::Byte(b'*', 8333), Tone::Byte(b'A', 8333), Tone::Byte(b'B', 8333),
+      Tone::Gap(100_000),
+      Tone::Byte(0x7F, 33333),
+      Tone::Gap(500_000),
+    ]);
+
+    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+    gzip.write_all(&uef).unwrap();
+    assert_eq!(parse(&gzip.finish().unwrap()).unwrap(), tape);
+    assert!(parse(b"UEF File?\0\x0a\x00").is_err());
+  }
+
+  #[test]
+  fn explicit() {
+    // high tone, then &41 framed by start and stop bits
+    let bits: u32 = 0b11 | (0x41 << 3) | 1 << 11;
+    let mut data = vec![4]; // ignore 4 of 16 bits
+    data.extend(&bits.to_le_bytes()[..2]);
+    let tape = {
+      let mut tape = Vec::new();
+      explicit_bits(&data, &Encoding { baud: 1200, base_frequency: 1200.0 }, &mut tape);
+      tape
+    };
+    assert_eq!(tape, vec![Tone::Carrier(833), Tone::Carrier(833),
+                          Tone::Byte(0x41, 8330)]);
+  }
+}
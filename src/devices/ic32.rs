@@ -62,6 +62,14 @@ impl IC32 {
     IC32(Cell::new(0u8))
   }
 
+  pub fn latch(&self) -> u8 {
+    self.0.get()
+  }
+
+  pub fn set_latch(&self, value: u8) {
+    self.0.set(value);
+  }
+
   pub fn has<const BIT: u8>(&self) -> bool {
     self.0.get() & Self::get_mask(BIT) != 0
   }
@@ -70,13 +78,13 @@ impl IC32 {
   // screen address in hardware to control hardware scrolling:
   //
   //   Mode | Size | Start of screen | Increase | B5 | B4
-  //   0,1,2| 20kB |     &3000       |   12k    |  1 |  1
+  //   0,1,2| 20kB |     &3000       |   12k    |  1 |  0
   //     3  | 16kB |     &4000       |   16k    |  0 |  0
-  //    4,5 | 10kB |     &5800       |   22k    |  1 |  0
+  //    4,5 | 10kB |     &5800       |   22k    |  1 |  1
   //     6  |  8kB |     &6000       |   24k    |  0 |  1
   //
+  // (as programmed by MOS 1.20, see tables at &C44B and &C44F)
   // Also: https://beebwiki.mdfs.net/Address_translation#Calculation_of_the_adjusted_address
-#[allow(unused)]
   fn lookup_mode_adjust(&self) -> u8 {
     // one's complement to be subtracted with borrow
     let b2k = match (self.has::<{Self::C1_B5}>(), self.has::<{Self::C0_B4}>()) {
@@ -88,6 +96,16 @@ impl IC32 {
     b2k
   }
 
+  // Screen addresses beyond &7FFF wrap back into screen memory: add the
+  // increase, ignoring carry out of A14
+  pub fn wrap_screen_address(&self, address: u16) -> u16 {
+    if address & 0x8000 == 0 {
+      return address;
+    }
+    let increase = (self.lookup_mode_adjust() as u16 + 1) * 2 * 1024;
+    address.wrapping_add(increase) & 0x7FFF
+  }
+
   pub fn write(&self, address: u8, value: bool) {
     log::trace!("IC32[{address}] = {value}, {}", Self::get_message(address, value));
     let mut latch = self.0.get();
@@ -111,3 +129,16 @@ fn mode_adjust() {
   assert_eq!(address, 0x4000); // MODE 3: wraps to &4000
   assert_eq!(offset/1024, 16); // MODE 3: 16k increase
 }
+
+#[test]
+fn wrap_screen_address() {
+  let ic32 = IC32::new();
+  assert_eq!(ic32.wrap_screen_address(0x7FFF), 0x7FFF); // no wrap
+  assert_eq!(ic32.wrap_screen_address(0x8000), 0x4000); // MODE 3
+  ic32.write(IC32::C1_B5, true);
+  assert_eq!(ic32.wrap_screen_address(0x8008), 0x3008); // MODE 0, 1, 2
+  ic32.write(IC32::C0_B4, true);
+  assert_eq!(ic32.wrap_screen_address(0x8100), 0x5900); // MODE 4, 5
+  ic32.write(IC32::C1_B5, false);
+  assert_eq!(ic32.wrap_screen_address(0x9FFF), 0x7FFF); // MODE 6
+}
@@ -2,6 +2,8 @@
 // bottom row (a. o. SHIFT, CTRL) does not cause interrupts
 // bottom row 2-9 is wired to a dip switch that controls boot options
 //
+use crate::snapshot::{Reader, Snapshot, Writer};
+
 const MAX_COL: u8 = 10;
 
 #[derive(Debug)]
@@ -37,14 +39,16 @@ impl Keyboard {
   }
 
   pub fn is_key_pressed(&self, key_code: u8) -> bool {
-    if key_code == 15 {
+    let (row, col) = Self::decode(key_code);
+    if col >= MAX_COL {
       // Just before scanning the keyboard:
       //   0xF0F0  LDA #15 ; select a non-existent keyboard column 15 (0-9 only!)
       //   0xF0F2  STA .systemVIARegisterANoHandshake
+      // and the keyboard interrupt may test a stale key number in X, such as
+      // &ED. Columns 10-15 are not wired to any key
       return false;
     }
 
-    let (row, col) = Self::decode(key_code);
     let pressed = self.read(row, col);
     pressed
   }
@@ -75,6 +79,12 @@ impl Keyboard {
     self.write(0, 0, false); // release SHIFT
   }
 
+  // whether press_key_ascii can type it
+  pub fn has_key_ascii(ascii: u8) -> bool {
+    let ascii = ascii as char;
+    ASCII_TO_KEY_CODE.iter().any(|pair| ascii == pair.0 || ascii == pair.1)
+  }
+
   // if true, send CA1 to system VIA (ic32 KB autoscan disabled)
   pub fn scan_column(&self, col: u8) -> bool {
     if col < MAX_COL {
@@ -129,6 +139,18 @@ impl Keyboard {
   }
 }
 
+// Keys held down, DIP switches included
+impl Snapshot for Keyboard {
+  fn save(&self, state: &mut Writer) {
+    state.bytes(&self.matrix);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.matrix = state.array()?;
+    Ok(())
+  }
+}
+
 const fn ascii_to_key_code(ascii: char) -> (u8, bool) {
   let mut i = 0_usize;
   while i < ASCII_TO_KEY_CODE.len() {
@@ -263,6 +285,16 @@ fn press_keyboard() {
   }
 }
 
+#[test]
+fn unwired_columns() {
+  let mut kb = Keyboard::new();
+  kb.press_key(0x69); // row 6 as &ED, column 9
+  for key_code in [0x0F, 0x6A, 0xED, 0xFF] { // columns 10-15, stale in X for the MOS
+    assert!(!kb.is_key_pressed(key_code));
+  }
+  assert!(kb.is_key_pressed(0x69));
+}
+
 #[test]
 fn test_dip_switch() {
   let mut kb = Keyboard::new();
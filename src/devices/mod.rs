@@ -1,5 +1,6 @@
 pub mod ic32;
 pub mod keyboard;
+pub mod paged_rom;
 
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
@@ -7,12 +8,23 @@ use std::vec::Vec;
 
 use ic32::IC32;
 use keyboard::Keyboard;
+use paged_rom::RomSelect;
 
+use crate::i8271::FDC;
 use crate::memory::{Address, MemoryBus};
 use crate::mc6845::CRTC;
-use crate::mos6522::{UserVIA, UserPortA, UserPortB};
+use crate::mc6850::ACIA;
+use crate::serial_ula::SerialULA;
+use crate::snapshot::{b_em, SnapshotDevices, ACIA_TAG, ADC_TAG, CRTC_TAG, KEYBOARD_TAG,
+                      ROM_SELECT_TAG, SERIAL_ULA_TAG, SOUND_TAG, SYSTEM_VIA_TAG,
+                      USER_VIA_TAG, VIDEO_ULA_TAG};
+use crate::sn76489::SN76489;
+use crate::tube::TubeULA;
+use crate::upd7002::ADC;
+use crate::video_ula::VideoULA;
 use crate::mos6522::alt_via::AltVIA;
 use crate::mos6522::system_via::{SystemVIA, SystemPortA, SystemPortB};
+use crate::mos6522::user_via::{UserVIA, PrinterPort, UserPort};
 
 #[derive(Debug)]
 pub struct Signal(Cell<bool>);
@@ -62,16 +74,10 @@ impl<D: BogusDevice> MemoryBus for D {
   }
 }
 
+//  &00–&07 6845 CRTC Video controller 18
 //  &08–&0F 6850 ACIA Serial controller 20.3
-struct ACIA {}
-impl BogusDevice for ACIA {}
-impl Device for ACIA {
-  fn name(&self) -> &'static str { "6850 ACIA Serial controller" }
-}
-
 //  &10–&1F Serial ULA Serial system chip 20.9
 //  &20–&2F Video ULA Video system chip 19
-//  &30–&3F 74LS161 Paged ROM selector 21
 //  &40–&5F 6522 VIA SYSTEM VIA 23
 //  &60–&7F 6522 VIA USER VIA 24
 //  &80–&9F 8271 FDC Floppy disc controller 25.1
@@ -90,50 +96,128 @@ pub trait DevicePage<const PAGE: u8> : MemoryBus {
 }
 
 pub struct SheilaPage {
-  crtc: Rc<RefCell<CRTC>>,
-  acia: RefCell<ACIA>,
+  pub crtc: Rc<RefCell<CRTC>>,
+  pub acia: Rc<RefCell<ACIA>>,
+  pub serial_ula: Rc<RefCell<SerialULA>>,
+  pub video_ula: Rc<RefCell<VideoULA>>,
+  pub rom_select: Rc<RefCell<RomSelect>>,
+  pub ic32: Rc<IC32>,
+  pub sound: Rc<RefCell<SN76489>>,
   alt_sysvia: Rc<RefCell<AltVIA>>,
   system_via: Rc<RefCell<SystemVIA>>,
-  user_via: RefCell<UserVIA>,
+  pub user_via: Rc<RefCell<UserVIA>>,
+  pub fdc: Rc<RefCell<FDC>>,
+  pub adc: Rc<RefCell<ADC>>,
+  pub tube: Option<Rc<RefCell<TubeULA>>>,
+  pub keyboard: Rc<RefCell<Keyboard>>,
   device_todo: RefCell<UnimplementedDevice>,
   pub irq: Rc<Signal>,
+  pub nmi: Rc<Signal>,
   pub use_alt_system_via: bool,
+  b_em_system_via: bool,
 }
 
 impl SheilaPage {
   pub fn new(keyboard: Rc<RefCell<Keyboard>>) -> Self {
     let crtc = CRTC::new();
-    let acia = RefCell::new(ACIA{});
+    let serial_ula = Rc::new(RefCell::new(SerialULA::new()));
+    let mut acia = ACIA::new(serial_ula.clone());
+    let rom_select = Rc::new(RefCell::new(RomSelect::new()));
     let ic32 = Rc::new(IC32::new());
     let mut system_port_a = SystemPortA::new(ic32.clone(), keyboard.clone());
-    let mut alt_sysvia = AltVIA::new(keyboard);
+    let mut alt_sysvia = AltVIA::new(keyboard.clone());
     system_port_a.crtc_vsync = crtc.vsync.clone(); // connect CA1 to 6845 vsync
+    let sound = system_port_a.sound.clone(); // on slow data bus
     alt_sysvia.crtc_vsync = crtc.b_em_vsync.clone(); // connect CA1 to vsync duplicate
     let crtc = Rc::new(RefCell::new(crtc));
+    let video_ula = Rc::new(RefCell::new(VideoULA::new(crtc.clone())));
     let irq = alt_sysvia.irq.clone();
+    acia.irq = irq.clone();
+    let acia = Rc::new(RefCell::new(acia));
     let alt_sysvia = Rc::new(RefCell::new(alt_sysvia));
-    let system_port_b = SystemPortB::new(ic32);
+    let mut system_port_b = SystemPortB::new(ic32.clone());
+    system_port_b.sound = sound.clone();
+    let adc = system_port_b.adc.clone(); // EOC on CB1, fire buttons on PB4-5
     let mut system_via = SystemVIA::new(system_port_a, system_port_b);
     system_via.irq = irq.clone();
     let system_via = Rc::new(RefCell::new(system_via));
-    let mut user_via = UserVIA::new(UserPortA::new(0), UserPortB::new(0));
+    let mut user_via = UserVIA::new(PrinterPort::new(), UserPort::new());
     user_via.irq = irq.clone(); // connect IRQB wires for logic "OR"
-    let user_via = RefCell::new(user_via);
+    let user_via = Rc::new(RefCell::new(user_via));
+    let fdc = FDC::new();
+    let nmi = fdc.nmi.clone(); // INT
+    let fdc = Rc::new(RefCell::new(fdc));
     let device_todo = RefCell::new(UnimplementedDevice{}); // catch all
-    SheilaPage { crtc, acia,
-                 alt_sysvia, system_via, user_via,
-                 device_todo, irq, use_alt_system_via: false,
+    SheilaPage { crtc, acia, serial_ula, video_ula, rom_select, ic32, sound,
+                 alt_sysvia, system_via, user_via, fdc, adc, tube: None,
+                 keyboard, device_todo, irq, nmi, use_alt_system_via: false,
+                 b_em_system_via: false,
     }
   }
 
+  // Let the B-em system VIA answer reads and interrupt, instead of checking
+  // against ours. Ours still takes the writes, to drive IC32 and the slow
+  // data bus
+  pub fn use_b_em_system_via(&mut self) {
+    self.system_via.borrow_mut().irq = Rc::new(Signal::new()); // not connected
+    self.b_em_system_via = true;
+  }
+
+  // Second processor interface, absent on a plain model B
+  pub fn attach_tube(&mut self) -> Rc<RefCell<TubeULA>> {
+    let mut tube = TubeULA::new();
+    tube.irq = self.irq.clone();
+    let tube = Rc::new(RefCell::new(tube));
+    self.tube = Some(tube.clone());
+    tube
+  }
+
   pub fn get_clocked_devices(&self) -> ClockedDevices {
     let mut devices = ClockedDevices::new();
     devices.push(self.crtc.clone());
     devices.push(self.alt_sysvia.clone());
     devices.push(self.system_via.clone());
+    devices.push(self.user_via.clone()); // printer acknowledge, user port
+    devices.push(self.sound.clone());
+    devices.push(self.serial_ula.clone()); // cassette, before ACIA
+    devices.push(self.acia.clone());
+    devices.push(self.fdc.clone());
+    devices.push(self.adc.clone());
+    if let Some(tube) = &self.tube {
+      devices.push(tube.clone());
+    }
     devices
   }
 
+  // State saved in machine snapshots, by section tag. Not the B-em VIA, nor
+  // (yet) disc and Tube devices
+  pub fn get_snapshot_devices(&self) -> SnapshotDevices {
+    let mut devices = SnapshotDevices::new();
+    devices.push((CRTC_TAG, self.crtc.clone()));
+    devices.push((ACIA_TAG, self.acia.clone()));
+    devices.push((SERIAL_ULA_TAG, self.serial_ula.clone()));
+    devices.push((VIDEO_ULA_TAG, self.video_ula.clone()));
+    devices.push((ROM_SELECT_TAG, self.rom_select.clone()));
+    devices.push((SYSTEM_VIA_TAG, self.system_via.clone()));
+    devices.push((USER_VIA_TAG, self.user_via.clone()));
+    devices.push((SOUND_TAG, self.sound.clone()));
+    devices.push((ADC_TAG, self.adc.clone()));
+    devices.push((KEYBOARD_TAG, self.keyboard.clone()));
+    devices
+  }
+
+  // Those B-em savestates hold, by type
+  pub fn get_b_em_devices(&self) -> b_em::Devices {
+    b_em::Devices {
+      crtc: self.crtc.clone(),
+      video_ula: self.video_ula.clone(),
+      rom_select: self.rom_select.clone(),
+      system_via: self.system_via.clone(),
+      user_via: self.user_via.clone(),
+      ic32: self.ic32.clone(),
+    }
+  }
+
   fn get_device(&self, address: Address) -> &RefCell<dyn Device> {
     assert_eq!(address.hi_u8(), Self::page());
     match address.lo_u8() & 0b1111_0000 {
@@ -141,12 +225,21 @@ impl SheilaPage {
         if address.lo_u8() & 0b0000_1000 == 0 {
           &*self.crtc
         } else {
-          &self.acia
+          &*self.acia
         }
       },
-      //...
-      0x40 | 0x50 => &*self.system_via, 
-      0x60 | 0x70 => &self.user_via,
+      0x10 => &*self.serial_ula,
+      0x20 => &*self.video_ula,
+      0x30 => &*self.rom_select,
+      0x40 | 0x50 if self.b_em_system_via => &*self.alt_sysvia,
+      0x40 | 0x50 => &*self.system_via,
+      0x60 | 0x70 => &*self.user_via,
+      0x80 | 0x90 => &*self.fdc,
+      0xC0 | 0xD0 => &*self.adc,
+      0xE0 | 0xF0 => match &self.tube {
+        Some(tube) => &**tube,
+        None => &self.device_todo,
+      },
       _ => &self.device_todo, // to be removed
     }
   }
@@ -174,6 +267,9 @@ impl MemoryBus for SheilaPage {
     if address.lo_u8() & 0b1111_0000 == 0x40 && self.use_alt_system_via {
       self.alt_sysvia.borrow_mut().write(address, value);
     }
+    if address.lo_u8() & 0b1110_0000 == 0x40 && self.b_em_system_via {
+      self.system_via.borrow_mut().write(address, value);
+    }
     device.borrow_mut().write(address, value);
   }
 }
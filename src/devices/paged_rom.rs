@@ -0,0 +1,185 @@
+//
+// 74LS161 paged ROM select register at SHEILA &30 (mirrored &31–&3F). It
+// latches the lower 4 bits written to it, selecting which one of 16 sideways
+// ROM sockets / banks is visible at &8000–&BFFF. The register is write-only,
+// so MOS 1.20 keeps a copy of the currently selected bank in zero page &F4.
+//
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::devices::Device;
+use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+pub const BANKS: usize = 16;
+pub const BANK_SIZE: usize = 16 * 1024;
+const BANK_START: u16 = 0x8000;
+
+#[derive(Debug)]
+pub struct RomSelect {
+  bank: u8,
+}
+
+impl RomSelect {
+  pub const fn new() -> Self {
+    RomSelect { bank: 0 }
+  }
+
+  pub const fn bank(&self) -> u8 {
+    self.bank
+  }
+}
+
+//  &30–&3F 74LS161 Paged ROM selector 21
+impl Device for RomSelect {
+  fn name(&self) -> &'static str { "74LS161 Paged ROM selector" }
+}
+
+impl MemoryBus for RomSelect {
+  fn read(&self, _address: Address) -> u8 {
+    0x00 // write only
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    self.bank = value & 0b0000_1111;
+    log::trace!("write {value:#04x} -> {address:?} select sideways bank {}", self.bank);
+  }
+}
+
+impl Snapshot for RomSelect {
+  fn save(&self, state: &mut Writer) {
+    state.u8(self.bank);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.bank = state.u8()? & 0b0000_1111;
+    Ok(())
+  }
+}
+
+struct Bank {
+  data: Box<[u8; BANK_SIZE]>,
+  writable: bool, // sideways RAM rather than ROM
+}
+
+// 16 x 16kB banks sharing &8000–&BFFF, switched by RomSelect
+pub struct SidewaysRoms {
+  select: Rc<RefCell<RomSelect>>,
+  banks: [Option<Bank>; BANKS],
+}
+
+impl SidewaysRoms {
+  pub fn new(select: Rc<RefCell<RomSelect>>) -> Self {
+    SidewaysRoms { select, banks: Default::default() }
+  }
+
+  pub const fn pages() -> std::ops::RangeInclusive<u8> {
+    0x80 ..= 0xBF
+  }
+
+  // Put ROM image in socket; 8kB images are mirrored in both halves of the bank
+  pub fn load_at(&mut self, image: &[u8], bank: u8) {
+    assert!((bank as usize) < BANKS);
+    assert!(!image.is_empty() && image.len() <= BANK_SIZE, "bad ROM image size");
+    let mut data = Box::new([0xFFu8; BANK_SIZE]);
+    for (index, byte) in data.iter_mut().enumerate() {
+      *byte = image[index % image.len()];
+    }
+    self.banks[bank as usize] = Some(Bank { data, writable: false });
+  }
+
+  pub fn load_bin_at(&mut self, filename: &str, bank: u8) -> usize {
+    let image = std::fs::read(filename).expect("failed to read ROM image");
+    self.load_at(&image, bank);
+    image.len()
+  }
+
+  // Fit 16kB of sideways RAM in socket, keeping ROM image (if any) as contents
+  pub fn add_ram(&mut self, bank: u8) {
+    assert!((bank as usize) < BANKS);
+    let slot = &mut self.banks[bank as usize];
+    match slot {
+      Some(bank) => bank.writable = true,
+      None => {
+        let data = Box::new([0u8; BANK_SIZE]);
+        *slot = Some(Bank { data, writable: true });
+      },
+    }
+  }
+
+  pub fn unload(&mut self, bank: u8) {
+    self.banks[bank as usize] = None;
+  }
+
+  fn selected(&self) -> Option<&Bank> {
+    let bank = self.select.borrow().bank();
+    self.banks[bank as usize].as_ref()
+  }
+
+  const fn offset(address: Address) -> usize {
+    (address.to_u16() - BANK_START) as usize
+  }
+}
+
+impl MemoryBus for SidewaysRoms {
+  fn read(&self, address: Address) -> u8 {
+    match self.selected() {
+      Some(bank) => bank.data[Self::offset(address)],
+      None => 0xFF, // empty socket, pulled up data bus
+    }
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    let index = self.select.borrow().bank() as usize;
+    match &mut self.banks[index] {
+      Some(bank) if bank.writable => {
+        bank.data[Self::offset(address)] = value;
+      },
+      _ => {
+        log::debug!("{value:02x} -> {address:?} | Ignoring write to sideways ROM {index}");
+      },
+    }
+  }
+
+  fn try_slice(&self, from: Address, to: Address) -> Option<&[u8]> {
+    let bank = self.selected()?;
+    Some(&bank.data[Self::offset(from) .. Self::offset(to)])
+  }
+}
+
+impl Device for SidewaysRoms {
+  fn name(&self) -> &'static str { "Sideways ROMs" }
+}
+
+#[test]
+fn select_banks() {
+  let select = Rc::new(RefCell::new(RomSelect::new()));
+  let mut roms = SidewaysRoms::new(select.clone());
+  roms.load_at(&[1, 2, 3, 4], 3);
+  roms.load_at(&[0x55; 8 * 1024], 15);
+  let address = Address::from(0x8001);
+  assert_eq!(roms.read(address), 0xFF); // bank 0 is empty
+  select.borrow_mut().write(Address::from(0xFE30), 3);
+  assert_eq!(roms.read(address), 2);
+  roms.write(address, 42);
+  assert_eq!(roms.read(address), 2); // ROM
+  select.borrow_mut().write(Address::from(0xFE3F), 0xFF); // mirror, 4 bits
+  assert_eq!(select.borrow().bank(), 15);
+  assert_eq!(roms.read(Address::from(0xBFFF)), 0x55);
+}
+
+#[test]
+fn sideways_ram() {
+  let select = Rc::new(RefCell::new(RomSelect::new()));
+  let mut roms = SidewaysRoms::new(select.clone());
+  roms.load_at(&[1, 2, 3, 4], 0);
+  roms.add_ram(4);
+  let address = Address::from(0x9000);
+  roms.write(address, 42); // bank 0 is ROM
+  assert_eq!(roms.read(address), 1);
+  select.borrow_mut().write(Address::from(0xFE30), 4);
+  assert_eq!(roms.read(address), 0);
+  roms.write(address, 42);
+  assert_eq!(roms.read(address), 42);
+}
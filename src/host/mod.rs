@@ -1,16 +1,24 @@
+pub mod options;
+
 use std::cell::RefCell;
+use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{BufReader, Read, Write};
 use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::mpsc::TryRecvError;
 use std::thread;
 
-use screen::Screen as Mode4;
+use screen::{Framebuffer, LINES, Palette, Screen as Mode4};
 
 use crate::devices::Clocked;
+use crate::devices::ic32::IC32;
+use crate::devices::keyboard::Keyboard;
+use crate::mc6845::CRTC;
 use crate::memory::{Address, MemoryBus};
+use crate::serial_ula::SerialLink;
+use crate::video_ula::VideoULA;
 
 pub struct KeyboardBuffer {
   rx: Receiver<u8>,
@@ -41,16 +49,172 @@ impl KeyboardBuffer {
   }
 }
 
+// Output kept in memory, for whoever holds a clone to look at: tests of the
+// printer and serial ports
+#[derive(Clone, Default)]
+pub struct Capture(Rc<RefCell<Vec<u8>>>);
+
+impl Capture {
+  pub fn bytes(&self) -> Vec<u8> {
+    self.0.borrow().clone()
+  }
+}
+
+impl Write for Capture {
+  fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+    self.0.borrow_mut().extend_from_slice(bytes);
+    Ok(bytes.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+// RS423 port connected to host byte streams: files, named pipes or ptys
+pub struct SerialStream {
+  rx: Receiver<u8>,
+  output: Box<dyn Write>,
+}
+
+impl SerialStream {
+  pub fn new<R, W>(input: R, output: W) -> Self
+  where R: Read + Send + 'static, W: Write + 'static {
+    let (tx, rx) = mpsc::channel::<u8>();
+    thread::spawn(move || {
+      for byte in input.bytes() {
+        match byte {
+          Ok(byte) => if tx.send(byte).is_err() { break },
+          Err(error) => {
+            log::warn!("RS423 input: {error}");
+            break;
+          },
+        }
+      }
+    });
+
+    SerialStream { rx, output: Box::new(output) }
+  }
+
+  // same path twice for a pty
+  pub fn open(input: &str, output: &str) -> io::Result<Self> {
+    let reader = File::open(input)?;
+    let writer = File::options().write(true).create(true).truncate(true).open(output)?;
+    Ok(SerialStream::new(reader, writer))
+  }
+}
+
+impl SerialLink for SerialStream {
+  fn read(&mut self) -> Option<u8> {
+    self.rx.try_recv().ok() // nothing more after end of input
+  }
+
+  fn write(&mut self, byte: u8) {
+    if let Err(error) = self.output.write_all(&[byte]).and_then(|_| self.output.flush()) {
+      log::warn!("RS423 output: {error}");
+    }
+  }
+}
+
+// Screen memory as addressed by the 6845 and video ULA, rendered a frame at a
+// time
+struct Raster {
+  memory: Rc<RefCell<dyn MemoryBus>>,
+  crtc: Rc<RefCell<CRTC>>,
+  video_ula: Rc<RefCell<VideoULA>>,
+  ic32: Rc<IC32>,
+}
+
+impl Raster {
+  // 6845 memory address to RAM address. Teletext (MA13 set): MA0-9 within 1K
+  // at &3C00, or &7C00 with MA11. Bitmap modes: MA0-12 gives A3-15, row
+  // address RA0-2 gives A0-2, and IC32 hardware scrolling wraps A15 back
+  fn screen_address(ic32: &IC32, memory_address: u16, raster_address: u8) -> usize {
+    if memory_address & 0x2000 != 0 {
+      let base = if memory_address & 0x0800 != 0 { 0x7C00 } else { 0x3C00 };
+      return base | (memory_address & 0x03FF) as usize;
+    }
+    let address = (memory_address & 0x1FFF) << 3 | raster_address as u16;
+    ic32.wrap_screen_address(address) as usize
+  }
+
+  fn blit(&self, framebuffer: &mut Framebuffer) {
+    let crtc = self.crtc.borrow();
+    let ula = self.video_ula.borrow();
+    let memory = self.memory.borrow();
+    let ram = memory.try_slice(Address::from(0x0000), Address::from(0x8000));
+    let ram = ram.expect("Could not get RAM slice");
+    let columns = crtc.horizontal_displayed() as usize;
+    let scan_lines = crtc.scan_lines() as usize;
+    let rows = crtc.vertical_displayed() as usize;
+
+    if ula.teletext() {
+      let start = crtc.start_address();
+      let bytes: Vec<u8> = (0..rows * columns).map(|offset| {
+        let memory_address = start.wrapping_add(offset as u16);
+        ram[Self::screen_address(&self.ic32, memory_address, 0)]
+      }).collect();
+      let cursor = crtc.cursor_address().wrapping_sub(start) as usize;
+      let lines = crtc.cursor_lines();
+      let cursor = if crtc.cursor_on() && cursor < bytes.len() {
+        Some((cursor, *lines.start() as usize .. *lines.end() as usize + 1))
+      } else {
+        None
+      };
+      let flash_on = crtc.frames() % 64 < 48; // 3:1 on:off
+      framebuffer.draw_teletext(&bytes, columns, flash_on, cursor);
+      return;
+    }
+
+    let palette: Palette = std::array::from_fn(|logical| {
+      Framebuffer::physical_color(ula.physical_colour(logical as u8))
+    });
+    let pixels_per_byte = ula.pixels_per_byte();
+    let cursor_bytes = ula.cursor_bytes();
+    let first = cursor_bytes.trailing_zeros() as usize;
+    let last = 8 - cursor_bytes.leading_zeros() as usize;
+    let mut bytes = vec![0u8; columns];
+    for y in 0..LINES {
+      let (row, raster_address) = (y / scan_lines, (y % scan_lines) as u8);
+      // ULA blanks display when RA3 is set (MODE 3 and 6 gaps)
+      if row >= rows || raster_address & 0b1000 != 0 {
+        framebuffer.draw_line(y, &[], pixels_per_byte, &palette, None);
+        continue;
+      }
+
+      let start = crtc.start_address().wrapping_add((row * columns) as u16);
+      for (x, byte) in bytes.iter_mut().enumerate() {
+        let memory_address = start.wrapping_add(x as u16);
+        *byte = ram[Self::screen_address(&self.ic32, memory_address, raster_address)];
+      }
+
+      let cursor = crtc.cursor_address().wrapping_sub(start) as usize;
+      let cursor = if crtc.cursor_on()
+        && crtc.cursor_lines().contains(&raster_address)
+        && cursor < columns && cursor_bytes != 0 {
+        Some(cursor + first .. cursor + last)
+      } else {
+        None
+      };
+      framebuffer.draw_line(y, &bytes, pixels_per_byte, &palette, cursor);
+    }
+  }
+}
+
 pub struct Screen{
   screen: Mode4,
-  memory: Rc<RefCell<dyn MemoryBus>>,
+  raster: Raster,
+  frames: u64,
   cycles: u64,
 }
 
 impl Screen {
-  pub fn new(title: &str, memory: Rc<RefCell<dyn MemoryBus>>) -> Self {
+  pub fn new(title: &str, memory: Rc<RefCell<dyn MemoryBus>>,
+             crtc: Rc<RefCell<CRTC>>, video_ula: Rc<RefCell<VideoULA>>,
+             ic32: Rc<IC32>) -> Self {
     let screen = Mode4::new(title);
-    Screen { screen, memory, cycles: 0 }
+    let raster = Raster { memory, crtc, video_ula, ic32 };
+    Screen { screen, raster, frames: 0, cycles: 0 }
   }
 
   pub fn try_read(&self) -> Option<u8> {
@@ -63,28 +227,146 @@ impl Screen {
   }
 
   pub fn blit(&mut self) {
-// FIXME: let 6845 decide which memory to read
-//  let startup_options = Address::from(0x028F);
-//  let vdu_current_screen_mode = Address::from(0x0355);
-    let vdu_start_screen_address_high_byte = Address::from(0x034E);
-    let start_hi = self.memory.borrow().read(vdu_start_screen_address_high_byte);
-    let from = Address::from_le_bytes(0, start_hi);
-    let to = Address::from(0x8000);
-    let memory = self.memory.borrow();
-    let vram = memory.try_slice(from, to);
-    let vram = vram.expect("Could not get VRAM slice");
-    self.screen.blit(vram);
+    self.raster.blit(self.screen.framebuffer_mut());
+  }
+
+  pub fn framebuffer(&self) -> &Framebuffer {
+    self.screen.framebuffer()
+  }
+
+  // frames shown so far
+  pub const fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  pub fn screenshot_key(&self) -> bool {
+    self.screen.screenshot_key()
+  }
+
+  pub fn snapshot_key(&self) -> bool {
+    self.screen.snapshot_key()
+  }
+
+  pub fn monitor_key(&self) -> bool {
+    self.screen.monitor_key()
   }
 }
 
 impl Clocked for Screen {
   fn step(&mut self, us: u64) {
-    const REFRESH: u64 = 20000; // 50Hz
-    if self.cycles / REFRESH < us / REFRESH {
+    if self.cycles / REFRESH_US < us / REFRESH_US {
       self.blit();
       self.screen.show();
+      self.frames += 1;
+    }
+    self.cycles = us;
+  }
+}
+
+// Renders frames like Screen, but only to memory: no window, no display needed
+pub struct Headless {
+  framebuffer: Framebuffer,
+  raster: Raster,
+  frames: u64,
+  cycles: u64,
+}
+
+impl Headless {
+  pub fn new(memory: Rc<RefCell<dyn MemoryBus>>,
+             crtc: Rc<RefCell<CRTC>>, video_ula: Rc<RefCell<VideoULA>>,
+             ic32: Rc<IC32>) -> Self {
+    let raster = Raster { memory, crtc, video_ula, ic32 };
+    Headless { framebuffer: Framebuffer::new(), raster, frames: 0, cycles: 0 }
+  }
+
+  pub fn framebuffer(&self) -> &Framebuffer {
+    &self.framebuffer
+  }
+
+  // frames rendered so far
+  pub const fn frames(&self) -> u64 {
+    self.frames
+  }
+}
+
+impl Clocked for Headless {
+  fn step(&mut self, us: u64) {
+    if self.cycles / REFRESH_US < us / REFRESH_US {
+      self.raster.blit(&mut self.framebuffer);
+      self.frames += 1;
     }
     self.cycles = us;
   }
 }
 
+const REFRESH_US: u64 = 20000; // 50Hz
+
+// Types host bytes on the BBC keyboard, one key at a time, as fast as MOS
+// reliably picks them up. Input is a script file or stdin, read until its end.
+// MOS starts in CAPS LOCK, so letters come out in upper case either way
+pub struct Typist {
+  keyboard: Rc<RefCell<Keyboard>>,
+  rx: Receiver<u8>,
+  pressed: Option<u8>,
+  next_us: u64,
+  ended: bool,
+}
+
+const TYPIST_START_US: u64 = 1_000_000; // leave MOS to reset first
+const KEY_DOWN_US: u64 = 40_000;
+const KEY_UP_US: u64 = 40_000; // so repeated letters are seen twice
+
+impl Typist {
+  pub fn new<R>(keyboard: Rc<RefCell<Keyboard>>, input: R) -> Self
+  where R: Read + Send + 'static {
+    let (tx, rx) = mpsc::channel::<u8>();
+    thread::spawn(move || {
+      for byte in BufReader::new(input).bytes() {
+        match byte {
+          Ok(byte) => if tx.send(byte).is_err() { break },
+          Err(error) => {
+            log::warn!("keyboard input: {error}");
+            break;
+          },
+        }
+      }
+    });
+
+    Typist { keyboard, rx, pressed: None, next_us: TYPIST_START_US, ended: false }
+  }
+
+  // all input typed and keys released
+  pub const fn finished(&self) -> bool {
+    self.ended && self.pressed.is_none()
+  }
+
+  fn next_key(&mut self) -> Option<u8> {
+    loop {
+      match self.rx.try_recv() {
+        Ok(byte) if Keyboard::has_key_ascii(byte) => return Some(byte),
+        Ok(byte) => log::warn!("no key for {byte:#04x}"),
+        Err(TryRecvError::Empty) => return None,
+        Err(TryRecvError::Disconnected) => {
+          self.ended = true;
+          return None;
+        },
+      }
+    }
+  }
+}
+
+impl Clocked for Typist {
+  fn step(&mut self, us: u64) {
+    if us < self.next_us {
+      return;
+    }
+    if let Some(key) = self.pressed.take() {
+      self.keyboard.borrow_mut().release_key_ascii(key);
+      self.next_us = us + KEY_UP_US;
+    } else if let Some(key) = self.next_key() {
+      self.keyboard.borrow_mut().press_key_ascii(key);
+      self.pressed = Some(key);
+      self.next_us = us + KEY_DOWN_US;
+    }
+  }
+}
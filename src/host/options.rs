@@ -0,0 +1,341 @@
+// Command line of the bbc-b binary
+
+use crate::memory::parse_number;
+use crate::mos6502::trace::{Format, Trigger};
+
+pub const USAGE: &str = "\
+usage: bbc-b [OPTIONS]
+
+  --os FILE             MOS ROM image at &C000 [images/os120.bin]
+  --rom SLOT=FILE       sideways ROM image in slot 0-15 [15=images/Basic2.rom]
+  --ram SLOT            sideways RAM in slot 0-15
+  --mode N              start up screen MODE 0-7, by keyboard DIP switches [2]
+  --dip BITS            all 8 keyboard DIP switches, overrides --mode
+  --disc [DRIVE=]FILE   .ssd/.dsd disc image in drive 0 or 1 [0]
+  --tape FILE           UEF tape image in the cassette deck
+  --serial IN[,OUT]     RS423 from and to host files, pipes or a pty [OUT=IN]
+  --printer FILE        Centronics printer output
+  --tube FILE           6502 second processor with this client ROM
+  --headless            no window, render frames to memory only
+  --keys FILE           type FILE on the keyboard, headless [stdin]
+  --screenshot [FRAME=]FILE
+                        save frame number FRAME as PNG [when stopping]
+  --restore FILE        resume from machine state snapshot FILE, or B-em .snp
+  --save FILE           save a machine state snapshot to FILE when stopping,
+                        B-em savestate if named .snp
+  --monitor             start paused in the machine code monitor, on stdin
+  --break ADDR          stop in the monitor before the instruction at ADDR
+  --watch ADDR[-END]    stop in the monitor after an instruction reads or
+                        writes memory or SHEILA registers ADDR to END
+  --gdb PORT            wait for a GDB remote debugger on local TCP PORT, to
+                        drive the monitor instead of stdin
+  --symbols FILE        label addresses in disassembly, from ld65/VICE label
+                        files, NAME = ADDR assignments or ADDR NAME lines
+  --trace FILE          disassemble each instruction executed to FILE
+  --trace-format FORMAT bbc-b, or nestest: nestest.log less its PPU column
+  --trace-start WHEN    trace from pc=ADDR or cycle=N [from the start]
+  --trace-stop WHEN     stop tracing at pc=ADDR or cycle=N
+  --cycles N            stop after N CPU cycles
+  --oswrch ADDR         echo VDU output caught at ADDR to the terminal [&E0A4]
+  --sysvia VIA          system VIA implementation: native, b-em, or compare
+                        to run both and check they agree [native]
+  --help                show this
+
+ADDR and BITS are hexadecimal, with or without &, $ or 0x; other numbers
+decimal";
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+  pub os: String,
+  pub roms: Vec<(u8, String)>,
+  pub sideways_ram: Vec<u8>,
+  pub dip_switch: u8,
+  pub discs: Vec<(usize, String)>,
+  pub tape: Option<String>,
+  pub serial: Option<(String, String)>,
+  pub printer: Option<String>,
+  pub tube: Option<String>,
+  pub headless: bool,
+  pub keys: Option<String>,
+  pub screenshots: Vec<(Option<u64>, String)>,
+  pub restore: Option<String>,
+  pub save: Option<String>,
+  pub monitor: bool,
+  pub breakpoints: Vec<u16>,
+  pub watchpoints: Vec<(u16, u16)>,
+  pub gdb: Option<u16>,
+  pub symbols: Vec<String>,
+  pub trace: Option<String>,
+  pub trace_format: Format,
+  pub trace_start: Option<Trigger>,
+  pub trace_stop: Option<Trigger>,
+  pub cycles: Option<u64>,
+  pub oswrch: u16,
+  pub system_via: SystemVia,
+  pub help: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SystemVia {
+  Native,
+  BEm,
+  Compare,
+}
+
+impl Default for Options {
+  fn default() -> Self {
+    Options {
+      os: "images/os120.bin".to_string(),
+      roms: vec![(15, "images/Basic2.rom".to_string())], // highest priority language
+      sideways_ram: Vec::new(),
+      dip_switch: Self::mode_dip_switch(2),
+      discs: Vec::new(),
+      tape: None,
+      serial: None,
+      printer: None,
+      tube: None,
+      headless: false,
+      keys: None,
+      screenshots: Vec::new(),
+      restore: None,
+      save: None,
+      monitor: false,
+      breakpoints: Vec::new(),
+      watchpoints: Vec::new(),
+      gdb: None,
+      symbols: Vec::new(),
+      trace: None,
+      trace_format: Format::BbcB,
+      trace_start: None,
+      trace_stop: None,
+      cycles: None,
+      oswrch: 0xE0A4, // Basic bypasses vectored OSWRCH entry
+      system_via: SystemVia::Native,
+      help: false,
+    }
+  }
+}
+
+impl Options {
+  // lower 3 bits reflect mode, inverted
+  const fn mode_dip_switch(mode: u8) -> u8 {
+    !mode & 0b0000_0111
+  }
+
+  pub fn parse<I>(args: I) -> Result<Self, String>
+  where I: IntoIterator<Item = String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    let mut dip_switch = None;
+    while let Some(option) = args.next() {
+      let mut value = || args.next().ok_or_else(|| format!("{option} needs a value"));
+      match option.as_str() {
+        "--os" => options.os = value()?,
+        "--rom" => {
+          let (slot, file) = Self::slot_and_file(&value()?, 15)?;
+          options.roms.retain(|(rom_slot, _)| *rom_slot != slot);
+          options.roms.push((slot, file));
+        },
+        "--ram" => options.sideways_ram.push(Self::number(&value()?, 15)? as u8),
+        "--mode" => options.dip_switch = Self::mode_dip_switch(Self::number(&value()?, 7)? as u8),
+        "--dip" => dip_switch = Some(Self::hex(&value()?, 0xFF)? as u8),
+        "--disc" => {
+          let value = value()?;
+          let (drive, file) = match value.split_once('=') {
+            Some(_) => Self::slot_and_file(&value, 1)?,
+            None => (0, value),
+          };
+          options.discs.retain(|(disc_drive, _)| *disc_drive != drive as usize);
+          options.discs.push((drive as usize, file));
+        },
+        "--tape" => options.tape = Some(value()?),
+        "--serial" => {
+          let value = value()?;
+          options.serial = Some(match value.split_once(',') {
+            Some((input, output)) => (input.to_string(), output.to_string()),
+            None => (value.clone(), value),
+          });
+        },
+        "--printer" => options.printer = Some(value()?),
+        "--tube" => options.tube = Some(value()?),
+        "--headless" => options.headless = true,
+        "--keys" => options.keys = Some(value()?),
+        "--screenshot" => {
+          let value = value()?;
+          options.screenshots.push(match value.split_once('=') {
+            Some((frame, file)) => (Some(Self::number(frame, u64::MAX)?), file.to_string()),
+            None => (None, value),
+          });
+        },
+        "--restore" => options.restore = Some(value()?),
+        "--save" => options.save = Some(value()?),
+        "--monitor" => options.monitor = true,
+        "--break" => options.breakpoints.push(Self::hex(&value()?, 0xFFFF)? as u16),
+        "--watch" => {
+          let value = value()?;
+          let (start, end) = value.split_once('-').unwrap_or((&value, &value));
+          let (start, end) = (Self::hex(start, 0xFFFF)?, Self::hex(end, 0xFFFF)?);
+          if start > end {
+            return Err(format!("{value} ends before it starts"));
+          }
+          options.watchpoints.push((start as u16, end as u16));
+        },
+        "--gdb" => options.gdb = Some(Self::number(&value()?, 0xFFFF)? as u16),
+        "--symbols" => options.symbols.push(value()?),
+        "--trace" => options.trace = Some(value()?),
+        "--trace-format" => options.trace_format = match value()?.as_str() {
+          "bbc-b" => Format::BbcB,
+          "nestest" => Format::Nestest,
+          other => return Err(format!("unknown trace format {other}")),
+        },
+        "--trace-start" => options.trace_start = Some(Self::trigger(&value()?)?),
+        "--trace-stop" => options.trace_stop = Some(Self::trigger(&value()?)?),
+        "--cycles" => options.cycles = Some(Self::number(&value()?, u64::MAX)?),
+        "--oswrch" => options.oswrch = Self::hex(&value()?, 0xFFFF)? as u16,
+        "--sysvia" => options.system_via = match value()?.as_str() {
+          "native" => SystemVia::Native,
+          "b-em" => SystemVia::BEm,
+          "compare" => SystemVia::Compare,
+          other => return Err(format!("unknown system VIA {other}")),
+        },
+        "--help" | "-h" => options.help = true,
+        other => return Err(format!("unknown option {other}")),
+      }
+    }
+    if let Some(bits) = dip_switch {
+      options.dip_switch = bits;
+    }
+    if options.keys.is_some() && !options.headless {
+      return Err("--keys needs --headless".to_string());
+    }
+    let triggers = options.trace_start.is_some() || options.trace_stop.is_some();
+    if triggers && options.trace.is_none() {
+      return Err("--trace-start and --trace-stop need --trace".to_string());
+    }
+    if options.headless && options.keys.is_none()
+      && options.uses_monitor() && options.gdb.is_none() {
+      return Err("the monitor needs --keys when headless, to read stdin".to_string());
+    }
+    Ok(options)
+  }
+
+  // paused or stopping in it
+  pub fn uses_monitor(&self) -> bool {
+    self.monitor || !self.breakpoints.is_empty() || !self.watchpoints.is_empty()
+      || self.gdb.is_some()
+  }
+
+  // ADDR or BITS
+  fn hex(text: &str, max: u64) -> Result<u64, String> {
+    match parse_number(text) {
+      Some(number) if number <= max => Ok(number),
+      _ => Err(format!("bad hexadecimal number {text}, 0 to &{max:X}")),
+    }
+  }
+
+  // slots, drives, ports and counts
+  fn number(text: &str, max: u64) -> Result<u64, String> {
+    match text.parse().ok() {
+      Some(number) if number <= max => Ok(number),
+      _ => Err(format!("bad number {text}, 0 to {max}")),
+    }
+  }
+
+  // pc=ADDR or cycle=N
+  fn trigger(text: &str) -> Result<Trigger, String> {
+    match text.split_once('=') {
+      Some(("pc", address)) => Ok(Trigger::Address(Self::hex(address, 0xFFFF)? as u16)),
+      Some(("cycle", cycle)) => Ok(Trigger::Cycle(Self::number(cycle, u64::MAX)?)),
+      _ => Err(format!("{text} is not pc=ADDR or cycle=N")),
+    }
+  }
+
+  // SLOT=FILE
+  fn slot_and_file(text: &str, max: u64) -> Result<(u8, String), String> {
+    let (slot, file) = text.split_once('=').ok_or(format!("{text} is not SLOT=FILE"))?;
+    Ok((Self::number(slot, max)? as u8, file.to_string()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(args: &str) -> Result<Options, String> {
+    Options::parse(args.split_whitespace().map(str::to_string))
+  }
+
+  #[test]
+  fn defaults() {
+    let options = parse("").unwrap();
+    assert_eq!(options, Options::default());
+    assert_eq!(options.dip_switch, 0b0000_0101); // MODE 2
+    assert_eq!(options.roms, [(15, "images/Basic2.rom".to_string())]);
+  }
+
+  #[test]
+  fn all_options() {
+    let options = parse("--os mos.rom --rom 14=dfs.rom --rom 15=b.rom --ram 4 \
+                         --mode 7 --disc a.ssd --disc 1=b.dsd --tape t.uef \
+                         --serial /dev/pts/3 --printer lp.txt --tube client.rom \
+                         --headless --keys run.bas --restore a.snapshot --save b.snapshot \
+                         --monitor --break &E0A4 --break 1900 \
+                         --watch &70-&7F --watch &FE4D --gdb 2159 \
+                         --symbols os120.sym --symbols basic.lbl \
+                         --trace t.log --trace-format nestest --trace-start pc=&E0A4 \
+                         --trace-stop cycle=3000000 --cycles 4000000 \
+                         --oswrch $FFEE --sysvia b-em").unwrap();
+    assert_eq!(options.os, "mos.rom");
+    assert_eq!(options.roms, [(14, "dfs.rom".to_string()), (15, "b.rom".to_string())]);
+    assert_eq!(options.sideways_ram, [4]);
+    assert_eq!(options.dip_switch, 0);
+    assert_eq!(options.discs, [(0, "a.ssd".to_string()), (1, "b.dsd".to_string())]);
+    assert_eq!(options.tape.as_deref(), Some("t.uef"));
+    assert_eq!(options.serial, Some(("/dev/pts/3".to_string(), "/dev/pts/3".to_string())));
+    assert_eq!(options.printer.as_deref(), Some("lp.txt"));
+    assert_eq!(options.tube.as_deref(), Some("client.rom"));
+    assert!(options.headless);
+    assert_eq!(options.keys.as_deref(), Some("run.bas"));
+    assert_eq!(options.restore.as_deref(), Some("a.snapshot"));
+    assert_eq!(options.save.as_deref(), Some("b.snapshot"));
+    assert!(options.monitor);
+    assert_eq!(options.breakpoints, [0xE0A4, 0x1900]);
+    assert_eq!(options.watchpoints, [(0x70, 0x7F), (0xFE4D, 0xFE4D)]);
+    assert_eq!(options.gdb, Some(2159));
+    assert_eq!(options.symbols, ["os120.sym", "basic.lbl"]);
+    assert_eq!(options.trace.as_deref(), Some("t.log"));
+    assert_eq!(options.trace_format, Format::Nestest);
+    assert_eq!(options.trace_start, Some(Trigger::Address(0xE0A4)));
+    assert_eq!(options.trace_stop, Some(Trigger::Cycle(3_000_000)));
+    assert_eq!(options.cycles, Some(4_000_000));
+    assert_eq!(options.oswrch, 0xFFEE);
+    assert_eq!(options.system_via, SystemVia::BEm);
+
+    assert_eq!(parse("--dip 0x80 --mode 1").unwrap().dip_switch, 0x80);
+    assert_eq!(parse("--sysvia compare").unwrap().system_via, SystemVia::Compare);
+    assert_eq!(parse("--screenshot 50=boot.png --screenshot end.png").unwrap().screenshots,
+               [(Some(50), "boot.png".to_string()), (None, "end.png".to_string())]);
+    assert_eq!(parse("--serial in,out").unwrap().serial,
+               Some(("in".to_string(), "out".to_string())));
+  }
+
+  #[test]
+  fn bad_options() {
+    assert_eq!(parse("--rom 16=x.rom"), Err("bad number 16, 0 to 15".to_string()));
+    assert_eq!(parse("--rom x.rom"), Err("x.rom is not SLOT=FILE".to_string()));
+    assert_eq!(parse("--disc 2=x.ssd"), Err("bad number 2, 0 to 1".to_string()));
+    assert_eq!(parse("--mode"), Err("--mode needs a value".to_string()));
+    assert_eq!(parse("--keys x"), Err("--keys needs --headless".to_string()));
+    assert_eq!(parse("--headless --break 1900"),
+               Err("the monitor needs --keys when headless, to read stdin".to_string()));
+    assert_eq!(parse("--watch 9-8"), Err("9-8 ends before it starts".to_string()));
+    assert_eq!(parse("--trace t --trace-start e0a4"),
+               Err("e0a4 is not pc=ADDR or cycle=N".to_string()));
+    assert_eq!(parse("--trace-stop cycle=9"),
+               Err("--trace-start and --trace-stop need --trace".to_string()));
+    assert_eq!(parse("--break 10000"), Err("bad hexadecimal number 10000, 0 to &FFFF".to_string()));
+    assert_eq!(parse("--ram &4"), Err("bad number &4, 0 to 15".to_string()));
+    assert_eq!(parse("--sysvia c"), Err("unknown system VIA c".to_string()));
+    assert_eq!(parse("--fast"), Err("unknown option --fast".to_string()));
+  }
+}
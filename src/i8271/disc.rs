@@ -0,0 +1,99 @@
+// Acorn DFS disc images
+// .ssd: single sided, track after track. .dsd: double sided, interleaved
+// by track: track 0 side 0, track 0 side 1, track 1 side 0, ...
+// Always 10 sectors of 256 bytes per track, 40 or 80 tracks.
+
+use std::fs::File;
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+pub const SECTORS: usize = 10;
+pub const SECTOR_SIZE: usize = 256;
+const TRACK_SIZE: usize = SECTORS * SECTOR_SIZE;
+const MAX_TRACKS: usize = 80;
+
+#[derive(Debug)]
+pub struct DiscImage {
+  data: Vec<u8>,
+  sides: usize,
+  tracks: usize,
+  filename: Option<String>,   // written back on every sector write
+  pub write_protect: bool,
+}
+
+impl DiscImage {
+  pub fn blank(sides: usize, tracks: usize) -> Self {
+    assert!((1..=2).contains(&sides) && tracks <= MAX_TRACKS);
+    DiscImage { data: vec![0xE5; sides * tracks * TRACK_SIZE], sides, tracks,
+                filename: None, write_protect: false }
+  }
+
+  pub fn from_bytes(mut data: Vec<u8>, sides: usize) -> Self {
+    assert!((1..=2).contains(&sides));
+    // images are often truncated after the last used sector
+    let tracks = data.len().div_ceil(sides * TRACK_SIZE).clamp(1, MAX_TRACKS);
+    data.resize(sides * tracks * TRACK_SIZE, 0xE5);
+    DiscImage { data, sides, tracks, filename: None, write_protect: false }
+  }
+
+  // .dsd is double sided, anything else single sided
+  pub fn load(filename: &str) -> io::Result<Self> {
+    let mut data = Vec::new();
+    File::open(filename)?.read_to_end(&mut data)?;
+    let sides = if filename.to_ascii_lowercase().ends_with(".dsd") { 2 } else { 1 };
+    let mut disc = DiscImage::from_bytes(data, sides);
+    disc.filename = Some(filename.to_string());
+    Ok(disc)
+  }
+
+  pub const fn sides(&self) -> usize {
+    self.sides
+  }
+
+  pub const fn tracks(&self) -> usize {
+    self.tracks
+  }
+
+  fn offset(&self, side: usize, track: usize, sector: usize) -> Option<usize> {
+    if side < self.sides && track < self.tracks && sector < SECTORS {
+      Some(((track * self.sides + side) * SECTORS + sector) * SECTOR_SIZE)
+    } else {
+      None
+    }
+  }
+
+  pub fn sector(&self, side: usize, track: usize, sector: usize) -> Option<&[u8]> {
+    let offset = self.offset(side, track, sector)?;
+    Some(&self.data[offset..offset + SECTOR_SIZE])
+  }
+
+  pub fn write_sector(&mut self, side: usize, track: usize, sector: usize,
+                      bytes: &[u8]) -> io::Result<()> {
+    let offset = self.offset(side, track, sector)
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no such sector"))?;
+    let length = bytes.len().min(SECTOR_SIZE);
+    self.data[offset..offset + length].copy_from_slice(&bytes[..length]);
+    if let Some(filename) = &self.filename {
+      let mut file = File::options().write(true).open(filename)?;
+      file.seek(SeekFrom::Start(offset as u64))?;
+      file.write_all(&self.data[offset..offset + SECTOR_SIZE])?;
+    }
+    Ok(())
+  }
+}
+
+#[test]
+fn interleaved_sides() {
+  let mut data = vec![0; 2 * TRACK_SIZE + SECTOR_SIZE];
+  data[TRACK_SIZE] = 1;                   // track 0, side 1
+  data[2 * TRACK_SIZE + 2] = 2;           // track 1, side 0
+  let mut disc = DiscImage::from_bytes(data, 2);
+  assert_eq!(disc.tracks(), 2);
+  assert_eq!(disc.sector(1, 0, 0).unwrap()[0], 1);
+  assert_eq!(disc.sector(0, 1, 0).unwrap()[2], 2);
+  assert_eq!(disc.sector(1, 1, 9).unwrap()[0], 0xE5); // padded
+  assert!(disc.sector(0, 2, 0).is_none());
+  assert!(disc.sector(0, 0, 10).is_none());
+  disc.write_sector(1, 1, 3, &[7; SECTOR_SIZE]).unwrap();
+  assert_eq!(disc.sector(1, 1, 3).unwrap(), &[7; SECTOR_SIZE]);
+}
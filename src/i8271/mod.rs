@@ -0,0 +1,525 @@
+// Intel 8271 floppy disc controller
+// Commands and their parameters are written to the command and parameter
+// registers; a result is read back when done. The BBC uses it in non-DMA
+// mode: each data byte is transferred by the NMI handler, as the controller
+// asserts INT with a data request in the status register.
+
+pub mod disc;
+
+use std::cell::Cell;
+use std::rc::Rc;
+
+use disc::{DiscImage, SECTORS, SECTOR_SIZE};
+
+use crate::devices::{Clocked, Device, Signal};
+use crate::memory::{Address, MemoryBus};
+
+// Status register
+const BUSY: u8         = 0b1000_0000;
+const RESULT_FULL: u8  = 0b0001_0000;
+const INT: u8          = 0b0000_1000;
+const DATA_REQUEST: u8 = 0b0000_0100;
+
+// Result codes
+const OK: u8                = 0x00;
+const NOT_READY: u8         = 0x10;
+const WRITE_PROTECT: u8     = 0x12;
+const SECTOR_NOT_FOUND: u8  = 0x18;
+
+// Special registers, others (scan sector, bad tracks, mode, ...) just stored
+const TRACK_SURFACE_0: u8   = 0x12;
+const TRACK_SURFACE_1: u8   = 0x1A;
+const OUTPUT_PORT: u8       = 0x23;
+
+// Drive control output port
+const SIDE_SELECT: u8       = 0b0010_0000;
+
+// Timing, single density: 64us per byte
+const BYTE_US: u64 = 64;
+const COMMAND_US: u64 = 100;
+const STEP_US: u64 = 3_000;
+const SETTLE_US: u64 = 15_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+  WriteData,
+  ReadData,
+  Verify,
+  ReadId,
+  Format,
+  Seek,
+  ReadDriveStatus,
+  Specify,
+  WriteSpecial,
+  ReadSpecial,
+  Unknown,
+}
+
+impl Command {
+  // commands ending in 0b10 transfer a single 128 byte record
+  fn decode(command: u8) -> (Command, usize) {
+    match command & 0x3F {
+      0x0A | 0x0E => (Command::WriteData, 2),
+      0x0B | 0x0F => (Command::WriteData, 3),
+      0x12 | 0x16 => (Command::ReadData, 2),
+      0x13 | 0x17 => (Command::ReadData, 3),
+      0x1E        => (Command::Verify, 2),
+      0x1F        => (Command::Verify, 3),
+      0x1B        => (Command::ReadId, 3),
+      0x23        => (Command::Format, 5),
+      0x29        => (Command::Seek, 1),
+      0x2C        => (Command::ReadDriveStatus, 0),
+      0x35        => (Command::Specify, 4),
+      0x3A        => (Command::WriteSpecial, 2),
+      0x3D        => (Command::ReadSpecial, 1),
+      _           => (Command::Unknown, 0),
+    }
+  }
+}
+
+#[derive(Debug)]
+enum Phase {
+  Idle,
+  Parameters(usize),               // still needed
+  Executing(u64),                  // until us
+  Reading(Vec<u8>, usize),         // bytes to CPU, next
+  Writing(Vec<u8>, usize),         // bytes from CPU, expected
+}
+
+//  &80–&9F 8271 FDC Floppy disc controller
+#[derive(Debug)]
+pub struct FDC {
+  pub nmi: Rc<Signal>,             // INT, wired to the 6502 NMI
+  pub discs: [Option<DiscImage>; 2],
+  command: u8,
+  parameters: Vec<u8>,
+  phase: Phase,
+  status: Cell<u8>,
+  result: u8,
+  data: Cell<u8>,
+  next_us: Cell<u64>,              // next data request, after last transfer
+  tracks: [u8; 2],                 // current track under each head
+  specials: [u8; 0x40],
+  clock_us: u64,
+}
+
+impl FDC {
+  pub fn new() -> Self {
+    FDC { nmi: Rc::new(Signal::new()), discs: [None, None],
+          command: 0, parameters: Vec::new(), phase: Phase::Idle,
+          status: Cell::new(0), result: 0, data: Cell::new(0),
+          next_us: Cell::new(0), tracks: [0; 2], specials: [0; 0x40], clock_us: 0,
+    }
+  }
+
+  pub fn insert(&mut self, drive: usize, disc: DiscImage) {
+    self.discs[drive] = Some(disc);
+  }
+
+  pub fn eject(&mut self, drive: usize) -> Option<DiscImage> {
+    self.discs[drive].take()
+  }
+
+  pub const fn status(&self) -> u8 {
+    self.status.get()
+  }
+
+  // command b7-6 select drive 1 or 0
+  const fn drive(&self) -> usize {
+    if self.command & 0b1000_0000 != 0 { 1 } else { 0 }
+  }
+
+  const fn side(&self) -> usize {
+    if self.specials[OUTPUT_PORT as usize] & SIDE_SELECT != 0 { 1 } else { 0 }
+  }
+
+  fn set_status(&self, bits: u8, value: bool) {
+    let status = self.status.get();
+    self.status.set(if value { status | bits } else { status & !bits });
+  }
+
+  fn interrupt(&self) {
+    self.set_status(INT, true);
+    self.nmi.raise();
+  }
+
+  fn finish(&mut self, result: u8, interrupt: bool) {
+    log::debug!("8271 command {:#04x} {:02x?} -> result {result:#04x}",
+                self.command, self.parameters);
+    self.phase = Phase::Idle;
+    self.result = result;
+    self.set_status(BUSY | DATA_REQUEST, false);
+    self.set_status(RESULT_FULL, true);
+    if interrupt {
+      self.interrupt();
+    }
+  }
+
+  fn start(&mut self) {
+    let (command, _) = Command::decode(self.command);
+    let steps = match command {
+      Command::ReadData | Command::WriteData | Command::Verify |
+      Command::ReadId | Command::Format | Command::Seek => {
+        let track = self.parameters[0];
+        (track as i32 - self.tracks[self.drive()] as i32).unsigned_abs() as u64
+      },
+      _ => 0,
+    };
+    let settle = if steps > 0 { SETTLE_US } else { 0 };
+    self.phase = Phase::Executing(self.clock_us + COMMAND_US + steps * STEP_US + settle);
+  }
+
+  // size/count parameter: b7-5 record size 128 << n, b4-0 records
+  fn records(&self) -> (usize, usize) {
+    match self.parameters.get(2) {
+      Some(size_count) => (128 << (size_count >> 5), (size_count & 0x1F) as usize),
+      None => (128, 1),
+    }
+  }
+
+  fn execute(&mut self) {
+    let (command, _) = Command::decode(self.command);
+    let drive = self.drive();
+    let side = self.side();
+    if let Some(&track) = self.parameters.first() {
+      if !matches!(command, Command::Specify | Command::WriteSpecial | Command::ReadSpecial) {
+        self.tracks[drive] = track;
+      }
+    }
+    let ready = self.discs[drive].as_ref().is_some_and(|disc| side < disc.sides());
+    let write_protect = self.discs[drive].as_ref().is_some_and(|disc| disc.write_protect);
+    let track = self.parameters.first().copied().unwrap_or(0) as usize;
+    let sector = self.parameters.get(1).copied().unwrap_or(0) as usize;
+    let (size, count) = self.records();
+    let exists = |fdc: &FDC| size <= SECTOR_SIZE && (sector..sector + count).all(|sector| {
+      fdc.discs[drive].as_ref().and_then(|disc| disc.sector(side, track, sector)).is_some()
+    });
+
+    match command {
+      Command::ReadData | Command::Verify | Command::WriteData | Command::Format if !ready => {
+        self.finish(NOT_READY, true)
+      },
+      Command::ReadData | Command::Verify | Command::WriteData if !exists(self) => {
+        self.finish(SECTOR_NOT_FOUND, true)
+      },
+      Command::WriteData | Command::Format if write_protect => {
+        self.finish(WRITE_PROTECT, true)
+      },
+      Command::ReadData => {
+        let disc = self.discs[drive].as_ref().unwrap();
+        let mut bytes = Vec::with_capacity(size * count);
+        for sector in sector..sector + count {
+          let data = disc.sector(side, track, sector).unwrap();
+          bytes.extend(&data[..size]);
+        }
+        self.next_us.set(self.clock_us);
+        self.phase = Phase::Reading(bytes, 0);
+      },
+      Command::WriteData => {
+        self.next_us.set(self.clock_us);
+        self.phase = Phase::Writing(Vec::new(), size * count);
+      },
+      Command::Format => {
+        // CPU supplies 4 byte ID (track, head, sector, size) per sector
+        let count = (self.parameters[2] & 0x1F) as usize;
+        self.next_us.set(self.clock_us);
+        self.phase = Phase::Writing(Vec::new(), 4 * count);
+      },
+      Command::ReadId => {
+        let count = self.parameters[2] as usize;
+        let ids = (0..count).flat_map(|n| [track as u8, 0, (n % SECTORS) as u8, 1]);
+        self.next_us.set(self.clock_us);
+        self.phase = Phase::Reading(ids.collect(), 0);
+      },
+      Command::Verify | Command::Seek => self.finish(OK, true),
+      Command::ReadDriveStatus => {
+        let status = self.drive_status();
+        self.finish(status, false)
+      },
+      Command::Specify => {
+        self.finish(OK, false);
+        self.set_status(RESULT_FULL, false);
+      },
+      Command::WriteSpecial => {
+        let (register, value) = (self.parameters[0], self.parameters[1]);
+        match register {
+          TRACK_SURFACE_0 => self.tracks[0] = value,
+          TRACK_SURFACE_1 => self.tracks[1] = value,
+          _ => {},
+        }
+        self.specials[register as usize & 0x3F] = value;
+        self.finish(OK, false);
+        self.set_status(RESULT_FULL, false);
+      },
+      Command::ReadSpecial => {
+        let register = self.parameters[0];
+        let value = match register {
+          TRACK_SURFACE_0 => self.tracks[0],
+          TRACK_SURFACE_1 => self.tracks[1],
+          _ => self.specials[register as usize & 0x3F],
+        };
+        self.finish(value, false)
+      },
+      Command::Unknown => {
+        log::warn!("8271 command {:#04x} not implemented", self.command);
+        self.finish(OK, true)
+      },
+    }
+  }
+
+  // b6 drive 1 ready, b3 write protect, b2 drive 0 ready, b1 track 0
+  fn drive_status(&self) -> u8 {
+    let drive = self.drive();
+    let mut status = 0;
+    if self.discs[0].is_some() { status |= 0b0000_0100 }
+    if self.discs[1].is_some() { status |= 0b0100_0000 }
+    if self.discs[drive].as_ref().is_some_and(|disc| disc.write_protect) {
+      status |= 0b0000_1000;
+    }
+    if self.tracks[drive] == 0 { status |= 0b0000_0010 }
+    status
+  }
+
+  fn write_back(&mut self, bytes: &[u8]) -> u8 {
+    let (command, _) = Command::decode(self.command);
+    let (drive, side) = (self.drive(), self.side());
+    let track = self.parameters[0] as usize;
+    let sector = self.parameters[1] as usize;
+    let (size, count) = self.records();
+    let Some(disc) = self.discs[drive].as_mut() else {
+      return NOT_READY; // ejected while waiting for the data
+    };
+    let result = if command == Command::Format {
+      (0..bytes.len() / 4)
+        .try_for_each(|n| disc.write_sector(side, track, bytes[4 * n + 2] as usize,
+                                            &[0xE5; SECTOR_SIZE]))
+    } else {
+      (0..count).try_for_each(|n| {
+        disc.write_sector(side, track, sector + n, &bytes[n * size..(n + 1) * size])
+      })
+    };
+    match result {
+      Ok(()) => OK,
+      Err(error) => {
+        log::error!("8271 write failed: {error}");
+        SECTOR_NOT_FOUND
+      },
+    }
+  }
+}
+
+impl Device for FDC {
+  fn name(&self) -> &'static str { "8271 FDC Floppy disc controller" }
+}
+
+impl MemoryBus for FDC {
+  fn read(&self, address: Address) -> u8 {
+    match address.lo_u8() & 0b111 {
+      0 => self.status.get(),
+      1 => {
+        self.set_status(RESULT_FULL | INT, false);
+        self.result
+      },
+      4..=7 => {
+        self.set_status(DATA_REQUEST | INT, false);
+        self.next_us.set(self.clock_us + BYTE_US);
+        self.data.get()
+      },
+      _ => 0x00,
+    }
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    match address.lo_u8() & 0b111 {
+      0 => {
+        if self.status.get() & BUSY != 0 {
+          log::warn!("8271 busy, command {value:#04x} ignored");
+          return;
+        }
+        self.command = value;
+        self.parameters.clear();
+        self.set_status(BUSY, true);
+        self.set_status(RESULT_FULL | INT, false);
+        match Command::decode(value) {
+          (_, 0) => self.start(),
+          (_, needed) => self.phase = Phase::Parameters(needed),
+        }
+      },
+      1 => {
+        if let Phase::Parameters(needed) = self.phase {
+          self.parameters.push(value);
+          if self.parameters.len() == needed {
+            self.start();
+          }
+        }
+      },
+      2 if value & 1 != 0 => { // reset
+        self.phase = Phase::Idle;
+        self.status.set(0);
+      },
+      4..=7 => {
+        self.set_status(DATA_REQUEST | INT, false);
+        self.next_us.set(self.clock_us + BYTE_US);
+        if let Phase::Writing(bytes, _) = &mut self.phase {
+          bytes.push(value);
+        }
+      },
+      _ => {},
+    }
+  }
+}
+
+impl Clocked for FDC {
+  fn step(&mut self, us: u64) {
+    self.clock_us = us;
+    let waiting = self.status.get() & DATA_REQUEST != 0 || self.next_us.get() > us;
+    match &mut self.phase {
+      Phase::Executing(until) if *until <= us => self.execute(),
+      Phase::Reading(bytes, next) if !waiting => {
+        if let Some(&byte) = bytes.get(*next) {
+          *next += 1;
+          self.data.set(byte);
+          self.set_status(DATA_REQUEST, true);
+          self.interrupt();
+        } else {
+          self.finish(OK, true);
+        }
+      },
+      Phase::Writing(bytes, expected) if !waiting => {
+        if bytes.len() < *expected {
+          self.set_status(DATA_REQUEST, true);
+          self.interrupt();
+        } else {
+          let bytes = std::mem::take(bytes);
+          let result = self.write_back(&bytes);
+          self.finish(result, true);
+        }
+      },
+      _ => {},
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const STATUS: u16 = 0xFE80;
+  const RESULT: u16 = 0xFE81;
+  const DATA: u16 = 0xFE84;
+
+  // Command with parameters, then run as the DFS NMI handler would
+  fn run(fdc: &mut FDC, command: u8, parameters: &[u8], output: &[u8]) -> (u8, Vec<u8>) {
+    fdc.write(Address::from(STATUS), command);
+    for &parameter in parameters {
+      fdc.write(Address::from(RESULT), parameter);
+    }
+    transfer(fdc, output)
+  }
+
+  // data bytes to and from the command under way, until its result
+  fn transfer(fdc: &mut FDC, mut output: &[u8]) -> (u8, Vec<u8>) {
+    let mut input = Vec::new();
+    let mut us = fdc.clock_us;
+    loop {
+      us += 1;
+      fdc.step(us);
+      assert!(us < 1_000_000, "timed out");
+      let status = fdc.read(Address::from(STATUS));
+      if fdc.nmi.sense() {
+        assert_ne!(status & INT, 0);
+        if status & DATA_REQUEST != 0 {
+          if let Some((&byte, rest)) = output.split_first() {
+            fdc.write(Address::from(DATA), byte);
+            output = rest;
+          } else {
+            input.push(fdc.read(Address::from(DATA)));
+          }
+          continue;
+        }
+      }
+      if status & BUSY == 0 {
+        return (fdc.read(Address::from(RESULT)), input);
+      }
+    }
+  }
+
+  fn numbered_disc() -> DiscImage {
+    let mut disc = DiscImage::blank(2, 40);
+    for side in 0..2 {
+      for track in 0..40 {
+        for sector in 0..SECTORS {
+          let id = [side as u8, track as u8, sector as u8];
+          disc.write_sector(side, track, sector, &id.repeat(SECTOR_SIZE / 3 + 1)).unwrap();
+        }
+      }
+    }
+    disc
+  }
+
+  #[test]
+  fn read_data() {
+    let mut fdc = FDC::new();
+    fdc.insert(0, numbered_disc());
+    let (result, bytes) = run(&mut fdc, 0x53, &[5, 8, 0x22], &[]); // 2 x 256
+    assert_eq!(result, OK);
+    assert_eq!(bytes.len(), 512);
+    assert_eq!(&bytes[0..3], &[0, 5, 8]);
+    assert_eq!(&bytes[256..259], &[0, 5, 9]);
+
+    // track register follows seek, then side 1 selected by output port
+    assert_eq!(run(&mut fdc, 0x7D, &[TRACK_SURFACE_0], &[]).0, 5);
+    run(&mut fdc, 0x7A, &[OUTPUT_PORT, SIDE_SELECT], &[]);
+    let (result, bytes) = run(&mut fdc, 0x52, &[39, 0], &[]); // 128 bytes
+    assert_eq!(result, OK);
+    assert_eq!(&bytes[..3], &[1, 39, 0]);
+    assert_eq!(bytes.len(), 128);
+  }
+
+  #[test]
+  fn errors() {
+    let mut fdc = FDC::new();
+    assert_eq!(run(&mut fdc, 0x53, &[0, 0, 0x21], &[]).0, NOT_READY);
+    fdc.insert(0, DiscImage::blank(1, 40));
+    assert_eq!(run(&mut fdc, 0x53, &[0, 9, 0x22], &[]).0, SECTOR_NOT_FOUND);
+    assert_eq!(run(&mut fdc, 0x53, &[40, 0, 0x21], &[]).0, SECTOR_NOT_FOUND);
+    assert_eq!(run(&mut fdc, 0x5F, &[0, 0, 0x2A], &[]).0, OK); // verify
+    assert_eq!(run(&mut fdc, 0x93, &[0, 0, 0x21], &[]).0, NOT_READY); // drive 1
+    fdc.discs[0].as_mut().unwrap().write_protect = true;
+    assert_eq!(run(&mut fdc, 0x4B, &[0, 0, 0x21], &[0; 256]).0, WRITE_PROTECT);
+    assert_eq!(run(&mut fdc, 0x6C, &[], &[]).0, 0b0000_1110); // drive status
+  }
+
+  #[test]
+  fn ejected_while_writing() {
+    let mut fdc = FDC::new();
+    fdc.insert(0, DiscImage::blank(1, 40));
+    fdc.write(Address::from(STATUS), 0x4A); // write a 128 byte record
+    fdc.write(Address::from(RESULT), 0);
+    fdc.write(Address::from(RESULT), 0);
+    fdc.step(COMMAND_US);
+    assert!(matches!(fdc.phase, Phase::Writing(..)));
+    fdc.eject(0);
+    assert_eq!(transfer(&mut fdc, &[0; 128]), (NOT_READY, vec![]));
+  }
+
+  #[test]
+  fn write_then_read() {
+    let mut fdc = FDC::new();
+    fdc.insert(1, DiscImage::blank(1, 80));
+    let data: Vec<u8> = (0..768).map(|n| (n % 251) as u8).collect();
+    assert_eq!(run(&mut fdc, 0x8B, &[79, 7, 0x23], &data), (OK, vec![]));
+    let (result, bytes) = run(&mut fdc, 0x93, &[79, 7, 0x23], &[]);
+    assert_eq!(result, OK);
+    assert_eq!(bytes, data);
+    assert_eq!(fdc.discs[1].as_ref().unwrap().sector(0, 79, 9).unwrap()[0], (512 % 251) as u8);
+
+    // format puts it back to &E5
+    let ids: Vec<u8> = (0..10).flat_map(|sector| [79, 0, sector, 1]).collect();
+    assert_eq!(run(&mut fdc, 0xA3, &[79, 21, 0x2A, 0, 16], &ids).0, OK);
+    assert_eq!(fdc.discs[1].as_ref().unwrap().sector(0, 79, 9).unwrap()[0], 0xE5);
+    let (result, bytes) = run(&mut fdc, 0x9B, &[79, 0, 2], &[]);
+    assert_eq!(result, OK);
+    assert_eq!(bytes, vec![79, 0, 0, 1, 79, 0, 1, 1]);
+  }
+}
@@ -1,7 +1,16 @@
+pub mod cassette;
 pub mod devices;
 pub mod host;
+pub mod i8271;   // Floppy disc controller
 pub mod memory;
+pub mod monitor; // Machine code monitor
 pub mod mos6502; // CPU
 pub mod mos6522; // Versatile Interface Adapter
 pub mod mc6845;  // Cathode ray tube controller
-
+pub mod mc6850;  // Asynchronous communications interface adapter
+pub mod serial_ula; // Serial ULA
+pub mod sn76489; // Sound generator
+pub mod snapshot; // Machine state snapshots
+pub mod tube;    // Second processor interface
+pub mod upd7002; // Analogue to digital converter
+pub mod video_ula; // Video ULA
@@ -1,19 +1,36 @@
-use std::io::{stdout, Write};
+use std::fs::File;
+use std::io::{self, stdin, stdout, BufReader, BufWriter, Write};
+use std::net::TcpStream;
+use std::process::exit;
 use std::rc::Rc;
 use std::cell::RefCell;
 
-use bbc_b::mos6502::{CPU, stop_at};
+use bbc_b::mos6502::CPU;
+use bbc_b::mos6502::symbols::Symbols;
+use bbc_b::mos6502::trace::Tracer;
 use bbc_b::devices::{ClockedDevices, DevicePage, SheilaPage};
 use bbc_b::devices::keyboard::Keyboard;
-use bbc_b::host::Screen;
-use bbc_b::memory::{Address, PageDispatcher, read_address};
+use bbc_b::devices::paged_rom::{SidewaysRoms, BANK_SIZE};
+use bbc_b::host::{Headless, Screen, SerialStream, Typist};
+use bbc_b::host::options::{Options, SystemVia, USAGE};
+use bbc_b::i8271::disc::DiscImage;
+use bbc_b::memory::{Address, PageDispatcher};
 use bbc_b::memory::ram::RAM;
+use bbc_b::memory::rom::ROM;
+use bbc_b::memory::watch::Access;
+use bbc_b::monitor::{Action, Monitor};
+use bbc_b::monitor::gdb::Gdb;
+use bbc_b::snapshot::{self, SnapshotDevices};
+use bbc_b::tube::parasite::Parasite;
+use screen::Framebuffer;
+
+const MOS_SIZE: usize = 0x4000; // &C000–&FFFF
 
 fn vdu_to_terminal(a_register: u8) {
   let mut out = stdout();
   match a_register {
     0x20..0x80  => {
-      out.write(&[a_register]).unwrap();
+      out.write_all(&[a_register]).unwrap();
       out.flush().unwrap();
     },
     0x0A => {
@@ -24,49 +41,336 @@ fn vdu_to_terminal(a_register: u8) {
   }
 }
 
+// B-em savestates rather than our own snapshots
+fn is_b_em(filename: &str) -> bool {
+  filename.to_lowercase().ends_with(".snp")
+}
+
+// give up on files that can't be used, naming them
+fn or_exit<T, E: std::fmt::Display>(result: Result<T, E>, filename: &str) -> T {
+  result.unwrap_or_else(|error| {
+    eprintln!("bbc-b: {filename}: {error}");
+    exit(1);
+  })
+}
+
+// ROM image fitting the space it goes in, which the ROM constructors assert
+fn read_rom(filename: &str, size: usize) -> io::Result<Vec<u8>> {
+  let image = std::fs::read(filename)?;
+  if image.is_empty() || image.len() > size {
+    let message = format!("ROM image of {} bytes, not 1 to {size}", image.len());
+    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+  }
+  Ok(image)
+}
+
 fn main() {
 //println!("My first BBC-B emulator");
-  let mut ram = RAM::new();
-  ram.load_bin_at("images/os120.bin", Address::from(0xC000));
-  ram.load_bin_at("images/Basic2.rom", Address::from(0x8000));
+  let options = Options::parse(std::env::args().skip(1)).unwrap_or_else(|error| {
+    eprintln!("bbc-b: {error}\n\n{USAGE}");
+    exit(2);
+  });
+  if options.help {
+    println!("{USAGE}");
+    return;
+  }
 
-  let irq_vector = Address::from(0xFFFE);
-  assert_eq!(read_address(&ram, irq_vector).to_u16(), 0xDC1C); // as per MOS
+  let ram = RAM::new();
+  let mos = ROM::new(&or_exit(read_rom(&options.os, MOS_SIZE), &options.os), Address::from(0xC000));
   let mut mem = PageDispatcher::new(Box::new(ram));
+  mem.add_backend_range(mos.pages(), Box::new(mos));
   let mut keyboard = Keyboard::new();
-  // start in MODE 5. lower 3 bits reflect mode, inverted
-//keyboard.set_dip_switch(0b0000_0011); // MODE 4, monochrome
-  keyboard.set_dip_switch(0b0000_0010); // MODE 5, 4 colours
-//keyboard.set_dip_switch(0b0000_0110); // MODE 1, 4 colours
-  keyboard.set_dip_switch(0b0000_0101); // MODE 2, 16 colours
+  keyboard.set_dip_switch(options.dip_switch);
   let keyboard = Rc::new(RefCell::new(keyboard));
   let mut sheila = SheilaPage::new(keyboard.clone());
-  sheila.use_alt_system_via = false;//true;
+  match options.system_via {
+    SystemVia::Native => {},
+    SystemVia::BEm => sheila.use_b_em_system_via(),
+    SystemVia::Compare => sheila.use_alt_system_via = true,
+  }
   let irq_level = sheila.irq.clone();
+  let nmi_level = sheila.nmi.clone();
+  let mut roms = SidewaysRoms::new(sheila.rom_select.clone());
+  for (slot, filename) in options.roms.iter() {
+    roms.load_at(&or_exit(read_rom(filename, BANK_SIZE), filename), *slot);
+  }
+  for slot in options.sideways_ram.iter() {
+    roms.add_ram(*slot);
+  }
+  mem.add_backend_range(SidewaysRoms::pages(), Box::new(roms));
+
+  for (drive, filename) in options.discs.iter() {
+    sheila.fdc.borrow_mut().insert(*drive, or_exit(DiscImage::load(filename), filename));
+  }
+  if let Some(filename) = &options.tape {
+    or_exit(sheila.serial_ula.borrow_mut().cassette.load_uef(filename), filename);
+  }
+  if let Some((input, output)) = &options.serial {
+    let stream = or_exit(SerialStream::open(input, output), input);
+    sheila.serial_ula.borrow_mut().connect_rs423(Box::new(stream));
+  }
+  if let Some(filename) = &options.printer {
+    let file = or_exit(File::create(filename), filename);
+    sheila.user_via.borrow_mut().connect_printer(Box::new(file));
+  }
+  let tube = options.tube.as_ref().map(|_| sheila.attach_tube());
   let mut clocked_devices: ClockedDevices = sheila.get_clocked_devices();
+  if let (Some(tube), Some(filename)) = (tube, &options.tube) {
+    let parasite = or_exit(Parasite::load_rom(tube, filename), filename);
+    clocked_devices.push(Rc::new(RefCell::new(parasite)));
+  }
+  let (crtc, video_ula) = (sheila.crtc.clone(), sheila.video_ula.clone());
+  let ic32 = sheila.ic32.clone();
+  let snapshot_devices = sheila.get_snapshot_devices();
+  let b_em_devices = sheila.get_b_em_devices();
   mem.add_backend(SheilaPage::page(), Box::new(sheila));
 
-  // intercept calls to "OS write character" (ie. BBC Basic II VDU commands)
-  // and translate to STDOUT
-//let break_oswrch = stop_at::<0xFFEE>;
-  let break_oswrch = stop_at::<0xE0A4>; // Basic bypasses vectored OSWRCH entry 
   let mut cpu = CPU::new();
   cpu.irq_level = irq_level;
+  cpu.nmi_level = nmi_level;
   cpu.handle_rst(&mut mem);
+  if let Some(filename) = &options.restore {
+    let file = BufReader::new(or_exit(File::open(filename), filename));
+    let result = if is_b_em(filename) {
+      snapshot::b_em::import(file, &mut cpu, &mut mem, &b_em_devices)
+    } else {
+      snapshot::restore(file, &mut cpu, &mut mem, &snapshot_devices)
+    };
+    or_exit(result, filename);
+  }
+
+  let mut symbols = Symbols::new();
+  for filename in options.symbols.iter() {
+    or_exit(symbols.load(filename), filename);
+  }
+  let symbols = Rc::new(symbols);
 
   let mem = Rc::new(RefCell::new(mem));
-  let screen = Screen::new("BBC-B", mem.clone());
-  let screen = Rc::new(RefCell::new(screen));
-  clocked_devices.push(screen.clone());
-  let mut last_key: Option<u8> =  None;
-  let mut wait_a_while: u32 = 0;
-  loop {
-    if break_oswrch(&cpu, &*mem.borrow()) {
+  let monitor = options.uses_monitor().then(|| {
+    let mut monitor = Monitor::new();
+    monitor.set_symbols(symbols.clone());
+    if options.monitor || options.gdb.is_some() {
+      monitor.pause();
+    }
+    for address in options.breakpoints.iter() {
+      monitor.add_breakpoint(*address);
+    }
+    for (start, end) in options.watchpoints.iter() {
+      monitor.add_watchpoint(*start..=*end, Access::Read);
+      monitor.add_watchpoint(*start..=*end, Access::Write);
+    }
+    monitor
+  });
+  let mut machine = Machine {
+    mem: mem.clone(),
+    oswrch: options.oswrch,
+    trace: options.trace.as_ref().map(|filename| {
+      let file = BufWriter::new(or_exit(File::create(filename), filename));
+      let mut tracer = Tracer::new(file, options.trace_format);
+      tracer.set_symbols(symbols.clone());
+      if let Some(trigger) = options.trace_start {
+        tracer.start_at(trigger);
+      }
+      if let Some(trigger) = options.trace_stop {
+        tracer.stop_at(trigger);
+      }
+      tracer
+    }),
+    cycles: options.cycles.unwrap_or(u64::MAX),
+    screenshots: options.screenshots,
+    snapshot_devices,
+    b_em_devices,
+    save: options.save,
+    monitor,
+    gdb: options.gdb.map(|port| {
+      let name = format!("port {port}");
+      let listener = or_exit(Gdb::bind(port), &name);
+      eprintln!("bbc-b: waiting for GDB on {}", or_exit(listener.local_addr(), &name));
+      or_exit(Gdb::accept(&listener), &name)
+    }),
+    symbols,
+  };
+
+  if options.headless {
+    let headless = Headless::new(mem.clone(), crtc, video_ula, ic32);
+    let typist = match &options.keys {
+      Some(filename) => Typist::new(keyboard, or_exit(File::open(filename), filename)),
+      None => Typist::new(keyboard, io::stdin()),
+    };
+    let headless = Rc::new(RefCell::new(headless));
+    let typist = Rc::new(RefCell::new(typist));
+    clocked_devices.push(headless.clone());
+    clocked_devices.push(typist.clone());
+    run_headless(&mut cpu, &mut machine, &clocked_devices, &headless, &typist);
+  } else {
+    let screen = Screen::new("BBC-B", mem.clone(), crtc, video_ula, ic32);
+    let screen = Rc::new(RefCell::new(screen));
+    clocked_devices.push(screen.clone());
+    run_window(&mut cpu, &mut machine, &clocked_devices, &screen, &keyboard);
+  }
+}
+
+struct Machine {
+  mem: Rc<RefCell<PageDispatcher>>,
+  oswrch: u16,
+  trace: Option<Tracer<BufWriter<File>>>,
+  cycles: u64, // limit
+  screenshots: Vec<(Option<u64>, String)>, // still to save, at frame or end
+  snapshot_devices: SnapshotDevices,
+  b_em_devices: snapshot::b_em::Devices, // for .snp files instead
+  save: Option<String>, // snapshot when stopped
+  monitor: Option<Monitor>,
+  gdb: Option<Gdb<TcpStream>>, // driving the monitor
+  symbols: Rc<Symbols>,
+}
+
+impl Machine {
+  // false once out of cycles, or quit from the monitor or debugger
+  fn step(&mut self, cpu: &mut CPU, clocked_devices: &ClockedDevices) -> bool {
+    if let Some(monitor) = &mut self.monitor {
+      if self.gdb.as_mut().is_some_and(Gdb::interrupted) {
+        monitor.pause();
+      }
+      while monitor.check(cpu) {
+        let mut mem = self.mem.borrow_mut();
+        let action = match &mut self.gdb {
+          Some(gdb) => gdb.serve(monitor, cpu, &mut *mem),
+          None => monitor.prompt(cpu, &mut *mem, &mut stdin().lock(), &mut stdout()),
+        };
+        match action {
+          Ok(Action::Run) => {},
+          Ok(_) => return false,
+          Err(error) => {
+            eprintln!("bbc-b: monitor: {error}");
+            return false;
+          },
+        }
+      }
+      if self.gdb.as_ref().is_some_and(Gdb::detached) {
+        self.gdb = None;
+      }
+    }
+    // intercept calls to "OS write character" (ie. BBC Basic II VDU commands)
+    // and translate to STDOUT
+    if cpu.registers.pc.to_u16() == self.oswrch {
       vdu_to_terminal(cpu.registers.a);
     }
-    cpu.step(&mut *mem.borrow_mut());
+    if let Some(trace) = &mut self.trace {
+      if let Err(error) = trace.trace(cpu, &*self.mem.borrow()) {
+        eprintln!("bbc-b: trace: {error}");
+        self.trace = None;
+      }
+    }
+    let pc = cpu.registers.pc.to_u16();
+    match &mut self.monitor {
+      Some(monitor) => {
+        cpu.step(&mut monitor.watch(&mut *self.mem.borrow_mut()));
+        match &mut self.gdb {
+          Some(gdb) => gdb.watched(monitor),
+          None => {
+            if let Err(error) = monitor.watched(pc, &mut stdout()) {
+              eprintln!("bbc-b: monitor: {error}");
+            }
+          },
+        }
+      },
+      None => cpu.step(&mut *self.mem.borrow_mut()),
+    }
     for cd in clocked_devices.iter() {
-      cd.borrow_mut().step(cpu.cycles);
+      cd.borrow_mut().step(cpu.micros());
+    }
+    cpu.cycles < self.cycles
+  }
+
+  // save screenshots due at this frame, or all left when stopped
+  fn screenshots(&mut self, frames: u64, framebuffer: &Framebuffer, stopped: bool) {
+    self.screenshots.retain(|(frame, filename)| {
+      let due = match frame {
+        Some(frame) => *frame <= frames,
+        None => stopped,
+      };
+      if due {
+        save_png(framebuffer, filename);
+      }
+      !due
+    });
+  }
+
+  fn save_snapshot(&self, cpu: &CPU, filename: &str) {
+    let result = File::create(filename).and_then(|file| {
+      let (file, mut mem) = (BufWriter::new(file), self.mem.borrow_mut());
+      if is_b_em(filename) {
+        snapshot::b_em::export(file, cpu, &mut *mem, &self.b_em_devices)
+      } else {
+        snapshot::save(file, cpu, &mut *mem, &self.snapshot_devices)
+      }
+    });
+    match result {
+      Ok(()) => eprintln!("bbc-b: saved {filename}"),
+      Err(error) => eprintln!("bbc-b: {filename}: {error}"),
+    }
+  }
+
+  fn stopped(&mut self, cpu: &CPU, frames: u64, framebuffer: &Framebuffer) {
+    self.screenshots(frames, framebuffer, true);
+    if let Some(filename) = &self.save {
+      self.save_snapshot(cpu, filename);
+    }
+  }
+}
+
+fn save_png(framebuffer: &Framebuffer, filename: &str) {
+  match framebuffer.save_png(filename) {
+    Ok(()) => eprintln!("bbc-b: saved {filename}"),
+    Err(error) => eprintln!("bbc-b: {filename}: {error}"),
+  }
+}
+
+// until input ends and the machine has had time to deal with it
+fn run_headless(cpu: &mut CPU, machine: &mut Machine, clocked_devices: &ClockedDevices,
+                headless: &RefCell<Headless>, typist: &RefCell<Typist>) {
+  const AFTER_INPUT_US: u64 = 2_000_000;
+  let mut until = u64::MAX;
+  let mut frames = 0;
+  while cpu.micros() < until && machine.step(cpu, clocked_devices) {
+    if until == u64::MAX && typist.borrow().finished() {
+      until = cpu.micros() + AFTER_INPUT_US;
+    }
+    let headless = headless.borrow();
+    if headless.frames() != frames {
+      frames = headless.frames();
+      machine.screenshots(frames, headless.framebuffer(), false);
+    }
+  }
+  let headless = headless.borrow();
+  machine.stopped(cpu, headless.frames(), headless.framebuffer());
+  println!();
+}
+
+fn run_window(cpu: &mut CPU, machine: &mut Machine,
+              clocked_devices: &ClockedDevices,
+              screen: &RefCell<Screen>, keyboard: &RefCell<Keyboard>) {
+  let mut last_key: Option<u8> =  None;
+  let mut wait_a_while: u32 = 0;
+  let mut frames = 0;
+  while machine.step(cpu, clocked_devices) {
+    let screen = screen.borrow();
+    if screen.frames() != frames {
+      frames = screen.frames();
+      machine.screenshots(frames, screen.framebuffer(), false);
+      if screen.screenshot_key() {
+        save_png(screen.framebuffer(), &format!("bbc-b-{frames}.png"));
+      }
+      if screen.snapshot_key() {
+        machine.save_snapshot(cpu, &format!("bbc-b-{frames}.snapshot"));
+      }
+      if screen.monitor_key() {
+        machine.monitor.get_or_insert_with(|| {
+          let mut monitor = Monitor::new();
+          monitor.set_symbols(machine.symbols.clone());
+          monitor
+        }).pause();
+      }
     }
 
     if cpu.registers.p.has::<'I'>() {
@@ -75,7 +379,7 @@ fn main() {
 
     if wait_a_while == 0 {
       wait_a_while = 100;
-      let new_key = screen.borrow().try_read();
+      let new_key = screen.try_read();
       if new_key != last_key {
         if let Some(key) = last_key {
           keyboard.borrow_mut().release_key_ascii(key);
@@ -91,4 +395,6 @@ fn main() {
       wait_a_while -= 1;
     }
   }
+  let screen = screen.borrow();
+  machine.stopped(cpu, screen.frames(), screen.framebuffer());
 }
@@ -1,26 +1,269 @@
 // Motorola 6845 video controller
-// Just provide 50 Hz VSync signal to System VIA
+// Register file R0-R17 and raster timing: horizontal character counter,
+// scanline counter, character row counter, vertical adjust and interlace.
+// Generates vsync (CA1 on system VIA), display enable and the memory address
+// of each character, from which the video ULA fetches its bytes.
 
+use std::ops::RangeInclusive;
 use std::rc::Rc;
 
 use crate::devices::{Clocked, Device, Signal};
 use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+//  R0  Horizontal total           R9  Scan lines per character row - 1
+//  R1  Horizontal displayed       R10 Cursor start (and blink mode)
+//  R2  Horizontal sync position   R11 Cursor end
+//  R3  Sync widths (V:4 | H:4)    R12 Start address high
+//  R4  Vertical total             R13 Start address low
+//  R5  Vertical total adjust      R14 Cursor address high
+//  R6  Vertical displayed         R15 Cursor address low
+//  R7  Vertical sync position     R16 Light pen high (read only)
+//  R8  Interlace and skew         R17 Light pen low (read only)
+pub const REGISTERS: usize = 18;
+
+// Only the implemented bits of each register are stored
+const REGISTER_MASKS: [u8; REGISTERS] = [
+  0xFF, 0xFF, 0xFF, 0xFF, 0x7F, 0x1F, 0x7F, 0x7F,
+  0xFF, 0x1F, 0x7F, 0x1F, 0x3F, 0xFF, 0x3F, 0xFF,
+  0x3F, 0xFF,
+];
+
+// Power up in MODE 7, as programmed by MOS 1.20 (see VDU table &C46E)
+const MODE7_REGISTERS: [u8; REGISTERS] = [
+  0x3F, 0x28, 0x33, 0x24, 0x1E, 0x02, 0x19, 0x1B,
+  0x93, 0x12, 0x72, 0x13, 0x28, 0x00, 0x00, 0x00,
+  0x00, 0x00,
+];
 
 //  &00–&07 6845 CRTC Video controller 18
 #[derive(Debug)]
 pub struct CRTC {
   pub vsync: Rc<Signal>,
   pub b_em_vsync: Rc<Signal>,
+  address: u8,                    // selected register
+  registers: [u8; REGISTERS],
+  fast_clock: bool,               // 2MHz (80 columns) rather than 1MHz
+  // raster state
+  character: u8,                  // horizontal character counter
+  line: u8,                       // scan line within character row
+  row: u8,                        // vertical character row counter
+  adjust: Option<u8>,             // scan lines into vertical total adjust
+  half_line: u8,                  // characters left of odd interlace field
+  vsync_lines: u8,                // scan lines left of vertical sync pulse
+  odd_field: bool,
+  row_address: u16,               // memory address at start of row
+  memory_address: u16,            // memory address of current character
+  frames: u64,
   clock_us: u64,
 }
 
 impl CRTC {
-  const FIFTY_HERZ: u64 = 20_000;
   pub fn new() -> Self {
     let vsync = Rc::new(Signal::new());
     let b_em_vsync = Rc::new(Signal::new());
-    let clock_us = 0;
-    CRTC { vsync, b_em_vsync, clock_us }
+    let registers = MODE7_REGISTERS;
+    let start = (registers[12] as u16) << 8 | registers[13] as u16;
+    CRTC { vsync, b_em_vsync,
+           address: 0, registers, fast_clock: false,
+           character: 0, line: 0, row: 0, adjust: None, half_line: 0,
+           vsync_lines: 0, odd_field: false,
+           row_address: start, memory_address: start,
+           frames: 0, clock_us: 0,
+    }
+  }
+
+  // Character clock is selected by video ULA: 2MHz for MODE 0-3, else 1MHz
+  pub fn set_fast_clock(&mut self, fast: bool) {
+    self.fast_clock = fast;
+  }
+
+  pub const fn register(&self, index: usize) -> u8 {
+    self.registers[index]
+  }
+
+  pub const fn start_address(&self) -> u16 {
+    (self.registers[12] as u16) << 8 | self.registers[13] as u16
+  }
+
+  pub const fn cursor_address(&self) -> u16 {
+    (self.registers[14] as u16) << 8 | self.registers[15] as u16
+  }
+
+  // Cursor blink mode in R10 bits 6, 5: steady, none, 16 or 32 field period
+  pub const fn cursor_on(&self) -> bool {
+    match self.registers[10] & 0b0110_0000 {
+      0b0000_0000 => true,
+      0b0010_0000 => false,
+      0b0100_0000 => self.frames & 0b0_1000 == 0,
+      _           => self.frames & 0b1_0000 == 0,
+    }
+  }
+
+  // Cursor start and end scan lines
+  pub const fn cursor_lines(&self) -> RangeInclusive<u8> {
+    RangeInclusive::new(self.registers[10] & 0b0001_1111, self.registers[11])
+  }
+
+  pub const fn horizontal_displayed(&self) -> u8 {
+    self.registers[1]
+  }
+
+  pub const fn vertical_displayed(&self) -> u8 {
+    self.registers[6]
+  }
+
+  pub const fn interlace_video(&self) -> bool {
+    self.registers[8] & 0b11 == 0b11
+  }
+
+  // scan lines per character row, per field
+  pub const fn scan_lines(&self) -> u8 {
+    if self.interlace_video() {
+      (self.registers[9] >> 1) + 1
+    } else {
+      self.registers[9] + 1
+    }
+  }
+
+  pub const fn frames(&self) -> u64 {
+    self.frames
+  }
+
+  pub const fn memory_address(&self) -> u16 {
+    self.memory_address
+  }
+
+  pub const fn row_address(&self) -> u16 {
+    self.row_address
+  }
+
+  pub const fn registers(&self) -> [u8; REGISTERS] {
+    self.registers
+  }
+
+  // Horizontal character, scan line and character row counters
+  pub const fn counters(&self) -> (u8, u8, u8) {
+    (self.character, self.line, self.row)
+  }
+
+  // Raster state from another emulator's savestate, leaving timing alone
+  pub fn set_registers(&mut self, registers: [u8; REGISTERS]) {
+    for (index, value) in registers.into_iter().enumerate() {
+      self.registers[index] = value & REGISTER_MASKS[index];
+    }
+  }
+
+  pub fn set_counters(&mut self, (character, line, row): (u8, u8, u8)) {
+    (self.character, self.line, self.row) = (character, line, row);
+  }
+
+  pub fn set_addresses(&mut self, row_address: u16, memory_address: u16) {
+    (self.row_address, self.memory_address) = (row_address, memory_address);
+  }
+
+  // Row address lines RA0-4, as seen by the video ULA and SAA5050
+  pub const fn raster_address(&self) -> u8 {
+    if self.interlace_video() {
+      self.line << 1 | self.odd_field as u8
+    } else {
+      self.line
+    }
+  }
+
+  pub const fn in_vsync(&self) -> bool {
+    self.vsync_lines != 0
+  }
+
+  pub const fn display_enabled(&self) -> bool {
+    self.adjust.is_none()
+      && self.character < self.registers[1]
+      && self.row < self.registers[6]
+  }
+
+  fn vsync_width(&self) -> u8 {
+    match self.registers[3] >> 4 {
+      0 => 16,
+      lines => lines,
+    }
+  }
+
+  fn start_frame(&mut self) {
+    self.row = 0;
+    self.line = 0;
+    self.adjust = None;
+    self.row_address = self.start_address();
+    self.memory_address = self.row_address;
+    self.frames += 1;
+  }
+
+  fn end_of_frame(&mut self) {
+    let interlace = self.registers[8] & 0b01 != 0;
+    if interlace {
+      self.odd_field = !self.odd_field;
+      if self.odd_field {
+        // odd field is half a scan line longer
+        self.half_line = (self.registers[0] >> 1) + 1;
+        return;
+      }
+    }
+    self.start_frame();
+  }
+
+  fn end_of_line(&mut self) {
+    if self.vsync_lines != 0 {
+      self.vsync_lines -= 1;
+    }
+
+    if let Some(lines) = self.adjust {
+      let lines = lines + 1;
+      self.adjust = Some(lines);
+      if lines >= self.registers[5] {
+        self.end_of_frame();
+      }
+      return;
+    }
+
+    if self.line + 1 < self.scan_lines() {
+      self.line += 1;
+      self.memory_address = self.row_address;
+    } else {
+      self.line = 0;
+      self.row_address = self.row_address.wrapping_add(self.registers[1] as u16);
+      self.memory_address = self.row_address;
+      if self.row == self.registers[4] {
+        if self.registers[5] == 0 {
+          self.end_of_frame();
+        } else {
+          self.adjust = Some(0);
+        }
+      } else {
+        self.row = (self.row + 1) & 0x7F;
+        if self.row == self.registers[7] {
+          self.vsync_lines = self.vsync_width();
+          self.vsync.raise();
+          self.b_em_vsync.raise();
+        }
+      }
+    }
+  }
+
+  fn tick(&mut self) {
+    if self.half_line != 0 {
+      self.half_line -= 1;
+      if self.half_line == 0 {
+        self.start_frame();
+      }
+      return;
+    }
+
+    if self.character == self.registers[0] {
+      self.character = 0;
+      self.end_of_line();
+    } else {
+      self.character += 1;
+      self.memory_address = self.memory_address.wrapping_add(1);
+    }
   }
 }
 
@@ -29,25 +272,80 @@ impl Device for CRTC {
 }
 
 impl MemoryBus for CRTC {
-  fn read(&self, _address: Address) -> u8 {
-    0x00 // TODO
+  fn read(&self, address: Address) -> u8 {
+    if address.lo_u8() & 1 == 0 {
+      return 0x00; // address register is write only
+    }
+
+    // Only cursor and light pen registers can be read back
+    let index = self.address as usize;
+    let value = match index {
+      14 ..= 17 => self.registers[index],
+      _ => 0x00,
+    };
+    log::trace!("read {address:?} R{index} -> {value:#04x}");
+    value
   }
-  fn write(&mut self, _address: Address, _value: u8) {
-    // TODO
+
+  fn write(&mut self, address: Address, value: u8) {
+    if address.lo_u8() & 1 == 0 {
+      self.address = value & 0b0001_1111;
+      return;
+    }
+
+    let index = self.address as usize;
+    log::trace!("write {value:#04x} -> {address:?} R{index}");
+    if index < 16 {
+      self.registers[index] = value & REGISTER_MASKS[index];
+    }
   }
 }
 
 impl Clocked for CRTC {
   fn step(&mut self, us: u64) {
     assert!(self.clock_us < us); // can't go back in time
-    if self.clock_us / Self::FIFTY_HERZ != us / Self::FIFTY_HERZ {
-      self.vsync.raise();
-      self.b_em_vsync.raise();
+    let rate = if self.fast_clock { 2 } else { 1 };
+    for _ in 0 .. (us - self.clock_us) * rate {
+      self.tick();
     }
     self.clock_us = us;
   }
 }
 
+impl Snapshot for CRTC {
+  fn save(&self, state: &mut Writer) {
+    state.u8(self.address);
+    state.bytes(&self.registers);
+    state.bool(self.fast_clock);
+    state.bytes(&[self.character, self.line, self.row]);
+    state.bool(self.adjust.is_some());
+    state.u8(self.adjust.unwrap_or(0));
+    state.bytes(&[self.half_line, self.vsync_lines]);
+    state.bool(self.odd_field);
+    state.u16(self.row_address);
+    state.u16(self.memory_address);
+    state.u64(self.frames);
+    state.u64(self.clock_us);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.address = state.u8()?;
+    self.registers = state.array()?;
+    self.fast_clock = state.bool()?;
+    [self.character, self.line, self.row] = state.array()?;
+    let adjusting = state.bool()?;
+    let adjust = state.u8()?;
+    self.adjust = adjusting.then_some(adjust);
+    [self.half_line, self.vsync_lines] = state.array()?;
+    self.odd_field = state.bool()?;
+    self.row_address = state.u16()?;
+    self.memory_address = state.u16()?;
+    self.frames = state.u64()?;
+    self.clock_us = state.u64()?;
+    Ok(())
+  }
+}
+
 #[test]
 fn vsync_step1() {
   // run for a second
@@ -61,7 +359,9 @@ fn vsync_step1() {
     }
   }
 
-  assert_eq!(count, 49);
+  // MODE 7: first vsync at row 27 (10 lines of 64us each), then every 20ms
+  assert_eq!(count, 50);
+  assert_eq!(crtc.frames(), 50); // fields of 312 and 312.5 + 0.5 lines
 }
 
 #[test]
@@ -77,5 +377,71 @@ fn vsync_step3() {
     }
   }
 
-  assert_eq!(count, 49);
+  assert_eq!(count, 50);
+}
+
+#[test]
+fn registers() {
+  let mut crtc = CRTC::new();
+  let select = Address::from(0xFE00);
+  let data = Address::from(0xFE01);
+  crtc.write(select, 12);
+  crtc.write(data, 0xFF); // only 6 bits
+  crtc.write(select, 13);
+  crtc.write(data, 0x34);
+  assert_eq!(crtc.start_address(), 0x3F34);
+  assert_eq!(crtc.read(data), 0); // start address is write only
+  crtc.write(select, 14);
+  crtc.write(data, 0x12);
+  assert_eq!(crtc.read(data), 0x12); // cursor is read / write
+  crtc.write(select, 16);
+  crtc.write(data, 0x12);
+  assert_eq!(crtc.read(data), 0); // light pen is read only
+  crtc.write(Address::from(0xFE06), 15); // mirrored
+  crtc.write(Address::from(0xFE07), 0x56);
+  assert_eq!(crtc.cursor_address(), 0x1256);
+}
+
+#[test]
+fn mode4_raster() {
+  // MODE 4: 1MHz character clock, 64us per line, 40 x 32 characters
+  const MODE4: [u8; 14] = [
+    0x3F, 0x28, 0x31, 0x24, 0x26, 0x00, 0x20, 0x22,
+    0x01, 0x07, 0x67, 0x08, 0x0B, 0x00,
+  ];
+  let mut crtc = CRTC::new();
+  for (index, value) in MODE4.iter().enumerate() {
+    crtc.write(Address::from(0xFE00), index as u8);
+    crtc.write(Address::from(0xFE01), *value);
+  }
+
+  // let current (MODE 7) field complete
+  let mut us = 1;
+  let frames = crtc.frames();
+  while crtc.frames() == frames {
+    crtc.step(us);
+    us += 1;
+  }
+  assert_eq!(crtc.memory_address(), 0x0B00); // (&5800 / 8)
+  assert!(crtc.display_enabled());
+
+  // one character row later: 8 scan lines of 64 characters
+  us += 8 * 64 - 1;
+  crtc.step(us);
+  us += 1;
+  assert_eq!(crtc.memory_address(), 0x0B00 + 40);
+  assert_eq!(crtc.raster_address(), 0);
+
+  // vsync at row 34 = 34 x 8 lines of 64us, 312.5 lines per field
+  let vsync = crtc.vsync.clone();
+  vsync.sense();
+  let mut count = 0;
+  for _ in 0..1_000_000 {
+    crtc.step(us);
+    us += 1;
+    if vsync.sense() {
+      count += 1;
+    }
+  }
+  assert_eq!(count, 50);
 }
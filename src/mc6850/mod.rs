@@ -0,0 +1,295 @@
+// Motorola 6850 asynchronous communications interface adapter (ACIA)
+// Serialises bytes for RS423 and cassette, at the rates set by the serial
+// ULA. Interrupts when a byte is received or the transmit register is empty.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::devices::{Clocked, Device, Signal};
+use crate::memory::{Address, MemoryBus};
+use crate::serial_ula::SerialULA;
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+// Status register
+const RDRF: u8 = 0b0000_0001; // receive data register full
+const TDRE: u8 = 0b0000_0010; // transmit data register empty
+const DCD: u8  = 0b0000_0100; // data carrier detect (lost)
+const CTS: u8  = 0b0000_1000; // clear to send (high: not)
+const OVRN: u8 = 0b0010_0000; // receiver overrun
+const IRQ: u8  = 0b1000_0000;
+
+// Control register
+// b1-0 counter divide: ÷1, ÷16, ÷64 or master reset
+// b4-2 word select: data bits, parity and stop bits
+// b6-5 transmit control: RTS and transmit interrupt enable
+// b7   receive interrupt enable
+const MASTER_RESET: u8 = 0b11;
+
+//  &08–&0F 6850 ACIA Serial controller
+#[derive(Debug)]
+pub struct ACIA {
+  pub irq: Rc<Signal>,      // shared, hard-wired to other IRQ sources
+  serial_ula: Rc<RefCell<SerialULA>>,
+  control: u8,
+  status: Cell<u8>,
+  rdr: u8,                  // receive data register
+  tdr: Option<u8>,          // transmit data register
+  shifting: Option<(u8, u64)>,  // byte being transmitted, until us
+  receiving: Option<(u8, u64)>, // byte being received, until us
+  carrier: bool,            // data carrier detect input
+  dcd_latched: Cell<bool>,  // until data register read
+  clock_us: u64,
+}
+
+impl ACIA {
+  pub fn new(serial_ula: Rc<RefCell<SerialULA>>) -> Self {
+    ACIA { irq: Rc::new(Signal::new()), serial_ula,
+           control: MASTER_RESET, status: Cell::new(0),
+           rdr: 0, tdr: None, shifting: None, receiving: None,
+           carrier: false, dcd_latched: Cell::new(false), clock_us: 0,
+    }
+  }
+
+  const fn in_reset(&self) -> bool {
+    self.control & 0b11 == MASTER_RESET
+  }
+
+  const fn divide(&self) -> u64 {
+    match self.control & 0b11 {
+      0b00 => 1,
+      0b01 => 16,
+      _    => 64,
+    }
+  }
+
+  // start bit, 7 or 8 data bits, optional parity and 1 or 2 stop bits
+  const fn frame_bits(&self) -> u64 {
+    match (self.control >> 2) & 0b111 {
+      0b000 | 0b001 => 1 + 7 + 1 + 2,
+      0b010 | 0b011 => 1 + 7 + 1 + 1,
+      0b100         => 1 + 8 + 2,
+      0b101         => 1 + 8 + 1,
+      _             => 1 + 8 + 1 + 1,
+    }
+  }
+
+  fn frame_us(&self, clock_hz: u32) -> u64 {
+    self.frame_bits() * self.divide() * 1_000_000 / clock_hz as u64
+  }
+
+  // request to send, active low: host streams only send while asserted
+  const fn ready_to_receive(&self) -> bool {
+    self.control & 0b0110_0000 != 0b0100_0000
+  }
+
+  const fn tx_interrupt_enabled(&self) -> bool {
+    self.control & 0b0110_0000 == 0b0010_0000
+  }
+
+  const fn rx_interrupt_enabled(&self) -> bool {
+    self.control & 0b1000_0000 != 0
+  }
+
+  fn update_irq(&self) {
+    let mut status = self.status.get();
+    let received = status & (RDRF | OVRN) != 0 || self.dcd_latched.get();
+    let rx = self.rx_interrupt_enabled() && received;
+    let tx = self.tx_interrupt_enabled() && status & TDRE != 0;
+    if rx || tx {
+      status |= IRQ;
+      self.irq.raise();
+    } else {
+      status &= !IRQ;
+    }
+    self.status.set(status);
+  }
+
+  fn set_status(&self, bits: u8, value: bool) {
+    let status = self.status.get();
+    self.status.set(if value { status | bits } else { status & !bits });
+  }
+
+  fn start_transmit(&mut self) {
+    if self.shifting.is_none() {
+      if let Some(byte) = self.tdr.take() {
+        let clock_hz = self.serial_ula.borrow().tx_clock();
+        self.shifting = Some((byte, self.clock_us + self.frame_us(clock_hz)));
+        self.set_status(TDRE, true);
+      }
+    }
+  }
+}
+
+impl Device for ACIA {
+  fn name(&self) -> &'static str { "6850 ACIA Serial controller" }
+}
+
+impl MemoryBus for ACIA {
+  fn read(&self, address: Address) -> u8 {
+    if address.lo_u8() & 1 == 0 {
+      self.status.get()
+    } else {
+      // strictly, status register must be read first to clear DCD
+      self.dcd_latched.set(false);
+      self.set_status(RDRF | OVRN, false);
+      self.set_status(DCD, self.carrier);
+      self.update_irq();
+      self.rdr
+    }
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    if address.lo_u8() & 1 == 0 {
+      log::trace!("6850 control {value:#010b}");
+      self.control = value;
+      if self.in_reset() {
+        self.status.set(0);
+        self.tdr = None;
+        self.shifting = None;
+        self.receiving = None;
+        self.dcd_latched.set(false);
+      } else if self.tdr.is_none() {
+        self.set_status(TDRE, true);
+      }
+      self.set_status(CTS, false);
+    } else {
+      log::trace!("6850 transmit {value:#04x}");
+      self.tdr = Some(value);
+      self.set_status(TDRE, false);
+      self.start_transmit();
+    }
+    self.update_irq();
+  }
+}
+
+impl Clocked for ACIA {
+  fn step(&mut self, us: u64) {
+    assert!(self.clock_us < us); // can't go back in time
+    self.clock_us = us;
+    if self.in_reset() {
+      return;
+    }
+
+    if let Some((byte, done)) = self.shifting {
+      if done <= us {
+        self.serial_ula.borrow_mut().transmit(byte);
+        self.shifting = None;
+        self.start_transmit();
+      }
+    }
+
+    if let Some((byte, done)) = self.receiving {
+      if done <= us {
+        if self.status.get() & RDRF != 0 {
+          self.set_status(OVRN, true); // previous byte wasn't read in time
+        } else {
+          self.rdr = byte;
+          self.set_status(RDRF, true);
+        }
+        self.receiving = None;
+      }
+    }
+
+    if self.receiving.is_none() {
+      let received = self.serial_ula.borrow_mut().receive(self.ready_to_receive());
+      if let Some(byte) = received {
+        let clock_hz = self.serial_ula.borrow().rx_clock();
+        self.receiving = Some((byte, us + self.frame_us(clock_hz)));
+      }
+    }
+
+    let carrier = self.serial_ula.borrow().carrier_detect();
+    if carrier && !self.carrier {
+      self.dcd_latched.set(true);
+    }
+    self.carrier = carrier;
+    self.set_status(DCD, carrier || self.dcd_latched.get());
+
+    self.update_irq();
+  }
+}
+
+// Bytes in flight with the times they complete
+impl Snapshot for ACIA {
+  fn save(&self, state: &mut Writer) {
+    state.bytes(&[self.control, self.status.get(), self.rdr]);
+    state.bool(self.tdr.is_some());
+    state.u8(self.tdr.unwrap_or(0));
+    for frame in [self.shifting, self.receiving] {
+      let (byte, until) = frame.unwrap_or((0, 0));
+      state.bool(frame.is_some());
+      state.u8(byte);
+      state.u64(until);
+    }
+    state.bool(self.carrier);
+    state.bool(self.dcd_latched.get());
+    state.u64(self.clock_us);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    let [control, status, rdr] = state.array()?;
+    (self.control, self.rdr) = (control, rdr);
+    self.status.set(status);
+    let full = state.bool()?;
+    self.tdr = Some(state.u8()?).filter(|_| full);
+    for frame in [&mut self.shifting, &mut self.receiving] {
+      let busy = state.bool()?;
+      let byte = state.u8()?;
+      *frame = Some((byte, state.u64()?)).filter(|_| busy);
+    }
+    self.carrier = state.bool()?;
+    self.dcd_latched.set(state.bool()?);
+    self.clock_us = state.u64()?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::serial_ula::Loopback;
+
+  #[test]
+  fn loopback() {
+    let serial_ula = Rc::new(RefCell::new(SerialULA::new()));
+    serial_ula.borrow_mut().connect_rs423(Box::new(Loopback::default()));
+    serial_ula.borrow_mut().write(Address::from(0xFE10), 0b0110_0100); // 9600
+    let mut acia = ACIA::new(serial_ula);
+    let status = Address::from(0xFE08);
+    let data = Address::from(0xFE09);
+    assert_eq!(acia.read(status) & TDRE, 0);
+    acia.write(status, 0b1001_0110); // 8N1, ÷64, receive interrupt
+    assert_eq!(acia.read(status) & TDRE, TDRE);
+
+    acia.write(data, b'B');
+    acia.write(data, b'C'); // waits in transmit data register
+    assert_eq!(acia.read(status) & TDRE, 0);
+    assert!(!acia.irq.sense());
+
+    // 10 bits at 9600 baud: ~1ms per byte, to send then to receive
+    acia.step(1000);
+    assert_eq!(acia.read(status) & TDRE, 0);
+    acia.step(1100);
+    assert_eq!(acia.read(status) & (RDRF | TDRE), TDRE);
+    acia.step(2200);
+    assert_eq!(acia.read(status) & (IRQ | RDRF), IRQ | RDRF);
+    assert!(acia.irq.sense());
+    assert_eq!(acia.read(data), b'B');
+    assert_eq!(acia.read(status) & (IRQ | RDRF), 0);
+
+    acia.step(2300);
+    acia.step(3300);
+    assert_eq!(acia.read(data), b'C');
+  }
+
+  #[test]
+  fn transmit_interrupt() {
+    let serial_ula = Rc::new(RefCell::new(SerialULA::new()));
+    let mut acia = ACIA::new(serial_ula);
+    acia.write(Address::from(0xFE08), 0b0011_0110); // 8N1, ÷64, TX IRQ
+    assert!(acia.irq.sense());
+    assert_eq!(acia.read(Address::from(0xFE08)) & IRQ, IRQ);
+    acia.write(Address::from(0xFE08), MASTER_RESET);
+    assert_eq!(acia.read(Address::from(0xFE08)), 0);
+  }
+}
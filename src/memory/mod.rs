@@ -1,4 +1,6 @@
 pub mod ram;
+pub mod rom;
+pub mod watch; // Watchpoints on CPU accesses
 
 //  SHEILA Integrated Description Section address circuit number (offset from
 //  &FE00)
@@ -44,6 +46,16 @@ pub fn slice(memory: &dyn MemoryBus, mut address: Address, mut size: usize) -> V
   vec
 }
 
+// &FF, $FF, 0xFF or FF: hexadecimal, as addresses and bytes are on the BBC.
+// The one parser for them, in the monitor, on the command line and in symbols
+pub fn parse_number(text: &str) -> Option<u64> {
+  let digits = text.strip_prefix('&')
+    .or_else(|| text.strip_prefix('$'))
+    .or_else(|| text.strip_prefix("0x"))
+    .unwrap_or(text);
+  u64::from_str_radix(digits, 16).ok()
+}
+
 #[derive(Clone, Copy)]
 pub struct Address(u16);
 
@@ -107,6 +119,14 @@ impl PageDispatcher {
     self.mapping[page as usize] = self.backends.len() as u8;
     self.backends.push(backend.into());
   }
+
+  // map contiguous pages to a single backend, e.g. sideways ROM &80..=&BF
+  pub fn add_backend_range(&mut self, pages: std::ops::RangeInclusive<u8>, backend: Box<dyn MemoryBus>) {
+    for page in pages {
+      self.mapping[page as usize] = self.backends.len() as u8;
+    }
+    self.backends.push(backend);
+  }
 }
 
 impl MemoryBus for PageDispatcher {
@@ -0,0 +1,62 @@
+use crate::memory::Address;
+use crate::memory::MemoryBus;
+
+// Read only memory covering whole pages, e.g. MOS at &C000–&FFFF. Writes are
+// ignored, like on the real machine
+pub struct ROM {
+  start: Address,
+  data: Vec<u8>,
+}
+
+impl ROM {
+  pub fn new(image: &[u8], start: Address) -> ROM {
+    assert_eq!(start.lo_u8(), 0, "ROM must start on page boundary");
+    assert!(start.to_u16() as usize + image.len() <= 0x10000, "ROM too large");
+    ROM { start, data: image.to_vec() }
+  }
+
+  pub fn load_bin_at(filename: &str, start: Address) -> ROM {
+    let image = std::fs::read(filename).expect("failed to read ROM image");
+    ROM::new(&image, start)
+  }
+
+  // pages covered by ROM image, for use with PageDispatcher::add_backend_range
+  pub fn pages(&self) -> std::ops::RangeInclusive<u8> {
+    let first = self.start.hi_u8();
+    let count = self.data.len().div_ceil(0x100).max(1);
+    first ..= (first as usize + count - 1) as u8
+  }
+
+  fn offset(&self, address: Address) -> usize {
+    (address.to_u16() - self.start.to_u16()) as usize
+  }
+}
+
+impl MemoryBus for ROM {
+  fn read(&self, address: Address) -> u8 {
+    self.data.get(self.offset(address)).copied().unwrap_or(0xFF)
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    log::debug!("{value:02x} -> {address:?} | Ignoring write to ROM");
+  }
+
+  fn try_slice(&self, from: Address, to: Address) -> Option<&[u8]> {
+    self.data.get(self.offset(from) .. self.offset(to))
+  }
+}
+
+#[test]
+fn write_protected() {
+  use crate::memory::{PageDispatcher, ram::RAM};
+  let rom = ROM::new(&[0xAA; 0x4000], Address::from(0xC000));
+  assert_eq!(rom.pages(), 0xC0 ..= 0xFF);
+  let mut memory = PageDispatcher::new(Box::new(RAM::new()));
+  memory.add_backend_range(rom.pages(), Box::new(rom));
+  let address = Address::from(0xC000);
+  memory.write(address, 0x55);
+  assert_eq!(memory.read(address), 0xAA);
+  let address = Address::from(0xBFFF); // just below ROM
+  memory.write(address, 0x55);
+  assert_eq!(memory.read(address), 0x55);
+}
@@ -0,0 +1,133 @@
+// Watchpoints: note CPU reads and writes of address ranges, RAM or SHEILA
+// registers alike, for the monitor to stop after the instruction. Every
+// access the CPU makes counts, instruction fetches included.
+
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+
+use super::{Address, MemoryBus};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+  Read,
+  Write,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Hit {
+  pub access: Access,
+  pub address: u16,
+  pub value: u8, // read or written
+}
+
+#[derive(Debug, Default)]
+pub struct Watchpoints {
+  watched: Vec<(RangeInclusive<u16>, Access)>,
+  hits: RefCell<Vec<Hit>>, // since last taken
+}
+
+impl Watchpoints {
+  pub fn add(&mut self, range: RangeInclusive<u16>, access: Access) {
+    if !self.watched.contains(&(range.clone(), access)) {
+      self.watched.push((range, access));
+    }
+  }
+
+  // those covering address, false if none
+  pub fn remove(&mut self, address: u16) -> bool {
+    let before = self.watched.len();
+    self.watched.retain(|(range, _)| !range.contains(&address));
+    self.watched.len() != before
+  }
+
+  pub fn remove_range(&mut self, range: &RangeInclusive<u16>, access: Access) -> bool {
+    let before = self.watched.len();
+    self.watched.retain(|watched| *watched != (range.clone(), access));
+    self.watched.len() != before
+  }
+
+  pub fn clear(&mut self) {
+    self.watched.clear();
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &(RangeInclusive<u16>, Access)> {
+    self.watched.iter()
+  }
+
+  pub fn take_hits(&self) -> Vec<Hit> {
+    self.hits.take()
+  }
+
+  fn check(&self, access: Access, address: Address, value: u8) {
+    let address = address.to_u16();
+    let watched = self.watched.iter()
+      .any(|(range, watched)| *watched == access && range.contains(&address));
+    if !watched {
+      return;
+    }
+    let hit = Hit { access, address, value };
+    let mut hits = self.hits.borrow_mut();
+    if hits.last() != Some(&hit) { // opcodes are read again
+      hits.push(hit);
+    }
+  }
+}
+
+// The bus as seen by the CPU while watched
+pub struct Watched<'a> {
+  memory: &'a mut dyn MemoryBus,
+  watchpoints: &'a Watchpoints,
+}
+
+impl<'a> Watched<'a> {
+  pub fn new(memory: &'a mut dyn MemoryBus, watchpoints: &'a Watchpoints) -> Self {
+    Watched { memory, watchpoints }
+  }
+}
+
+impl MemoryBus for Watched<'_> {
+  fn read(&self, address: Address) -> u8 {
+    let value = self.memory.read(address);
+    self.watchpoints.check(Access::Read, address, value);
+    value
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    self.watchpoints.check(Access::Write, address, value);
+    self.memory.write(address, value);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::ram::RAM;
+
+  #[test]
+  fn hits() {
+    let mut ram = RAM::new();
+    let mut watchpoints = Watchpoints::default();
+    watchpoints.add(0x70..=0x71, Access::Write);
+    watchpoints.add(0xFE40..=0xFE4F, Access::Read);
+    watchpoints.add(0xFE40..=0xFE4F, Access::Read);
+    assert_eq!(watchpoints.iter().count(), 2);
+
+    let mut watched = Watched::new(&mut ram, &watchpoints);
+    watched.write(Address::from(0x70), 0x12);
+    watched.write(Address::from(0x72), 0x34);
+    assert_eq!(watched.read(Address::from(0x71)), 0);
+    assert_eq!(watched.read(Address::from(0x70)), 0x12); // not watched reading
+    watched.write(Address::from(0xFE4D), 0x7F);
+    assert_eq!(watched.read(Address::from(0xFE4D)), 0x7F);
+    assert_eq!(watchpoints.take_hits(), [
+      Hit { access: Access::Write, address: 0x70, value: 0x12 },
+      Hit { access: Access::Read, address: 0xFE4D, value: 0x7F },
+    ]);
+    assert!(watchpoints.take_hits().is_empty());
+
+    assert!(watchpoints.remove(0x71));
+    assert!(!watchpoints.remove(0x71));
+    watchpoints.clear();
+    assert_eq!(watchpoints.iter().count(), 0);
+  }
+}
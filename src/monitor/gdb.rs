@@ -0,0 +1,476 @@
+// GDB remote serial protocol: a debugger attached over TCP drives the
+// monitor, reading and writing registers and memory, setting breakpoints and
+// watchpoints, stepping and continuing. `target remote localhost:PORT`
+//
+// GDB has no 6502 architecture of its own: the target description names the
+// registers a, x, y, s, p and 16 bit pc, in that order.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::RangeInclusive;
+
+use super::{Action, Monitor};
+use crate::memory::{Address, MemoryBus, slice};
+use crate::memory::watch::{Access, Hit};
+use crate::mos6502::CPU;
+use crate::mos6502::registers::Status;
+
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target version=\"1.0\"><feature name=\"org.gnu.gdb.mos6502.core\">\
+<reg name=\"a\" bitsize=\"8\" regnum=\"0\"/>\
+<reg name=\"x\" bitsize=\"8\"/>\
+<reg name=\"y\" bitsize=\"8\"/>\
+<reg name=\"s\" bitsize=\"8\"/>\
+<reg name=\"p\" bitsize=\"8\"/>\
+<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+</feature></target>";
+
+const REGISTER_BYTES: usize = 7; // a, x, y, s, p, pc lo, pc hi
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+const INTERRUPT: u8 = 0x03; // Ctrl-C from the debugger while running
+const POLL_STEPS: u32 = 10_000; // instructions between looks for an interrupt
+
+// The debugger at the other end: TCP, or a script in tests
+pub trait Connection: Read + Write {
+  fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+  fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+    TcpStream::set_nonblocking(self, nonblocking)
+  }
+}
+
+enum Answer {
+  Reply(String),
+  Resume,
+  Detach,
+  Kill,
+}
+
+pub struct Gdb<C: Connection> {
+  connection: C,
+  running: bool,        // owes a stop reply
+  interrupted: bool,
+  watch_hit: Option<Hit>,
+  detached: bool,
+  polls: u32,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+  data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+  if !text.len().is_multiple_of(2) {
+    return None;
+  }
+  (0..text.len()).step_by(2)
+    .map(|at| text.get(at..at + 2).and_then(|hex| u8::from_str_radix(hex, 16).ok()))
+    .collect()
+}
+
+fn number(text: &str) -> Option<u16> {
+  u16::from_str_radix(text, 16).ok()
+}
+
+// ADDR,LENGTH
+fn address_and_length(text: &str) -> Option<(u16, u16)> {
+  let (address, length) = text.split_once(',')?;
+  Some((number(address)?, number(length)?))
+}
+
+fn registers(cpu: &CPU) -> [u8; REGISTER_BYTES] {
+  let registers = &cpu.registers;
+  let [pc_lo, pc_hi] = registers.pc.to_u16().to_le_bytes();
+  [registers.a, registers.x, registers.y, registers.s.to_u8(), registers.p.to_u8(), pc_lo, pc_hi]
+}
+
+fn set_registers(cpu: &mut CPU, bytes: &[u8]) -> Option<()> {
+  let [a, x, y, s, p, pc_lo, pc_hi]: [u8; REGISTER_BYTES] = bytes.try_into().ok()?;
+  let registers = &mut cpu.registers;
+  (registers.a, registers.x, registers.y) = (a, x, y);
+  *registers.s.borrow_mut() = s;
+  registers.p = Status::from(p);
+  registers.pc = Address::from_le_bytes(pc_lo, pc_hi);
+  Some(())
+}
+
+// register number to its bytes in the g packet
+fn register_bytes(number: usize) -> Option<std::ops::Range<usize>> {
+  match number {
+    0..=4 => Some(number..number + 1),
+    5 => Some(5..7),
+    _ => None,
+  }
+}
+
+impl Gdb<TcpStream> {
+  // For the debugger to connect to, from this host only
+  pub fn bind(port: u16) -> io::Result<TcpListener> {
+    TcpListener::bind(("127.0.0.1", port))
+  }
+
+  // Wait for the debugger to connect
+  pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+    let (stream, _) = listener.accept()?;
+    stream.set_nodelay(true)?;
+    Ok(Gdb::new(stream))
+  }
+}
+
+impl<C: Connection> Gdb<C> {
+  pub const fn new(connection: C) -> Self {
+    Gdb { connection, running: false, interrupted: false, watch_hit: None, detached: false,
+          polls: 0 }
+  }
+
+  // gone, leaving the machine running
+  pub const fn detached(&self) -> bool {
+    self.detached
+  }
+
+  // While running, every so often: has the debugger asked to stop?
+  pub fn interrupted(&mut self) -> bool {
+    if self.detached || !self.running {
+      return false;
+    }
+    self.polls += 1;
+    if self.polls < POLL_STEPS {
+      return false;
+    }
+    self.polls = 0;
+    let mut byte = [0];
+    let read = self.connection.set_nonblocking(true)
+      .and_then(|_| self.connection.read(&mut byte));
+    let _ = self.connection.set_nonblocking(false);
+    self.interrupted = matches!(read, Ok(1)) && byte[0] == INTERRUPT;
+    self.interrupted
+  }
+
+  // After stepping: watched accesses, for the stop reply
+  pub fn watched(&mut self, monitor: &mut Monitor) {
+    if let Some(hit) = monitor.take_hits().into_iter().next() {
+      self.watch_hit = Some(hit);
+    }
+  }
+
+  // While the monitor is paused: tell the debugger why, then answer it
+  // until it resumes the machine or kills it. Quit when it hangs up
+  pub fn serve(&mut self, monitor: &mut Monitor, cpu: &mut CPU, memory: &mut dyn MemoryBus)
+               -> io::Result<Action> {
+    if self.running {
+      self.running = false;
+      let reply = self.stop_reply();
+      self.send(&reply)?;
+    }
+    while let Some(packet) = self.receive()? {
+      match self.answer(&packet, monitor, cpu, memory) {
+        Answer::Reply(reply) => self.send(&reply)?,
+        Answer::Resume => {
+          self.running = true;
+          return Ok(Action::Run);
+        },
+        Answer::Detach => {
+          self.send("OK")?;
+          self.detached = true;
+          monitor.clear_points();
+          monitor.resume(cpu.registers.pc.to_u16(), None);
+          return Ok(Action::Run);
+        },
+        Answer::Kill => return Ok(Action::Quit),
+      }
+    }
+    Ok(Action::Quit)
+  }
+
+  fn stop_reply(&mut self) -> String {
+    if let Some(hit) = self.watch_hit.take() {
+      let kind = match hit.access {
+        Access::Read => "rwatch",
+        Access::Write => "watch",
+      };
+      return format!("T{SIGTRAP:02x}{kind}:{:x};", hit.address);
+    }
+    let signal = if std::mem::take(&mut self.interrupted) { SIGINT } else { SIGTRAP };
+    format!("S{signal:02x}")
+  }
+
+  fn byte(&mut self) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match self.connection.read(&mut byte)? {
+      0 => Ok(None),
+      _ => Ok(Some(byte[0])),
+    }
+  }
+
+  // $DATA#CHECKSUM, acknowledged. None when the connection ends
+  fn receive(&mut self) -> io::Result<Option<String>> {
+    loop {
+      match self.byte()? {
+        None => return Ok(None),
+        Some(b'$') => {},
+        Some(_) => continue, // acks, interrupts while stopped
+      }
+      let mut data = Vec::new();
+      loop {
+        match self.byte()? {
+          None => return Ok(None),
+          Some(b'#') => break,
+          Some(byte) => data.push(byte),
+        }
+      }
+      let (Some(hi), Some(lo)) = (self.byte()?, self.byte()?) else {
+        return Ok(None);
+      };
+      let sent = std::str::from_utf8(&[hi, lo]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+      if sent == Some(checksum(&data)) {
+        self.connection.write_all(b"+")?;
+        return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+      }
+      self.connection.write_all(b"-")?;
+    }
+  }
+
+  // sent again until acknowledged
+  fn send(&mut self, data: &str) -> io::Result<()> {
+    let packet = format!("${data}#{:02x}", checksum(data.as_bytes()));
+    loop {
+      self.connection.write_all(packet.as_bytes())?;
+      self.connection.flush()?;
+      if self.byte()? != Some(b'-') {
+        return Ok(());
+      }
+    }
+  }
+
+  fn answer(&mut self, packet: &str, monitor: &mut Monitor, cpu: &mut CPU,
+            memory: &mut dyn MemoryBus) -> Answer {
+    let rest = packet.get(1..).unwrap_or(""); // after the command letter
+    let answer = match (packet.as_bytes().first(), rest) {
+      (Some(b'?'), _) => Some(Answer::Reply(self.stop_reply())),
+      (Some(b'g'), _) => Some(Answer::Reply(to_hex(&registers(cpu)))),
+      (Some(b'G'), values) => from_hex(values).and_then(|bytes| set_registers(cpu, &bytes))
+        .map(|_| Answer::Reply("OK".to_string())),
+      (Some(b'p'), number) => usize::from_str_radix(number, 16).ok().and_then(register_bytes)
+        .map(|bytes| Answer::Reply(to_hex(&registers(cpu)[bytes]))),
+      (Some(b'P'), assignment) => assignment.split_once('=').and_then(|(number, value)| {
+        let bytes = register_bytes(usize::from_str_radix(number, 16).ok()?)?;
+        let value = from_hex(value).filter(|value| value.len() == bytes.len())?;
+        let mut values = registers(cpu);
+        values[bytes].copy_from_slice(&value);
+        set_registers(cpu, &values)
+      }).map(|_| Answer::Reply("OK".to_string())),
+      (Some(b'm'), range) => address_and_length(range).map(|(address, length)| {
+        Answer::Reply(to_hex(&slice(memory, Address::from(address), length as usize)))
+      }),
+      (Some(b'M'), write) => write.split_once(':').and_then(|(range, data)| {
+        let (address, length) = address_and_length(range)?;
+        let bytes = from_hex(data).filter(|bytes| bytes.len() == length as usize)?;
+        for (offset, byte) in bytes.iter().enumerate() {
+          memory.write(Address::from(address.wrapping_add(offset as u16)), *byte);
+        }
+        Some(Answer::Reply("OK".to_string()))
+      }),
+      (Some(b'c' | b's'), address) => {
+        if let Some(address) = number(address) {
+          cpu.registers.pc = Address::from(address);
+        }
+        let steps = packet.starts_with('s').then_some(1);
+        monitor.resume(cpu.registers.pc.to_u16(), steps);
+        Some(Answer::Resume)
+      },
+      (Some(b'Z' | b'z'), point) => Self::point(packet.starts_with('Z'), point, monitor)
+        .map(|_| Answer::Reply("OK".to_string())),
+      (Some(b'k'), _) => Some(Answer::Kill),
+      (Some(b'D'), _) => Some(Answer::Detach),
+      (Some(b'H' | b'T'), _) => Some(Answer::Reply("OK".to_string())),
+      (Some(b'q'), _) => Some(Answer::Reply(Self::query(packet))),
+      _ => Some(Answer::Reply(String::new())), // not supported
+    };
+    answer.unwrap_or_else(|| Answer::Reply("E01".to_string()))
+  }
+
+  // TYPE,ADDR,KIND: software or hardware breakpoint, write, read or access
+  // watchpoint of KIND bytes
+  fn point(insert: bool, point: &str, monitor: &mut Monitor) -> Option<()> {
+    let (kind, range) = point.split_once(',')?;
+    let (address, length) = address_and_length(range)?;
+    let range: RangeInclusive<u16> = address..=address.wrapping_add(length.max(1) - 1);
+    let accesses: &[Access] = match kind {
+      "0" | "1" => {
+        if insert {
+          monitor.add_breakpoint(address);
+        } else {
+          monitor.remove_breakpoint(address);
+        }
+        return Some(());
+      },
+      "2" => &[Access::Write],
+      "3" => &[Access::Read],
+      "4" => &[Access::Read, Access::Write],
+      _ => return None,
+    };
+    for access in accesses {
+      if insert {
+        monitor.add_watchpoint(range.clone(), *access);
+      } else {
+        monitor.remove_watchpoint(&range, *access);
+      }
+    }
+    Some(())
+  }
+
+  fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+      return "PacketSize=1000;qXfer:features:read+".to_string();
+    }
+    if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+      let Some((offset, length)) = address_and_length(range) else {
+        return "E01".to_string();
+      };
+      let (offset, length) = (offset as usize, length as usize);
+      let part = TARGET_XML.get(offset.min(TARGET_XML.len())..).unwrap_or("");
+      return match part.len() > length {
+        true => format!("m{}", &part[..length]),
+        false => format!("l{part}"),
+      };
+    }
+    match packet {
+      "qAttached" => "1",
+      "qC" => "QC1",
+      "qfThreadInfo" => "m1",
+      "qsThreadInfo" => "l",
+      _ => "",
+    }.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::VecDeque;
+
+  use super::*;
+  use crate::memory::ram::RAM;
+
+  // What the debugger will send, and what it was sent
+  #[derive(Default)]
+  struct Script {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+  }
+
+  impl Read for Script {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+      self.input.read(buffer)
+    }
+  }
+
+  impl Write for Script {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+      self.output.write(buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl Connection for Script {
+    fn set_nonblocking(&self, _nonblocking: bool) -> io::Result<()> {
+      Ok(())
+    }
+  }
+
+  // packets, each acknowledging the reply to the one before
+  fn send(gdb: &mut Gdb<Script>, packets: &[&str]) {
+    for packet in packets {
+      let packet = format!("+${packet}#{:02x}", checksum(packet.as_bytes()));
+      gdb.connection.input.extend(packet.as_bytes());
+    }
+    gdb.connection.input.push_back(b'+');
+  }
+
+  // packets sent since last asked
+  fn replies(gdb: &mut Gdb<Script>) -> Vec<String> {
+    let output = String::from_utf8(std::mem::take(&mut gdb.connection.output)).unwrap();
+    output.split('$').skip(1)
+      .map(|packet| packet.split_once('#').unwrap().0.to_string())
+      .collect()
+  }
+
+  // LDX #3 / loop: DEX / BNE loop / STX &70 / BRK
+  fn machine() -> (CPU, RAM, Monitor) {
+    let mut ram = RAM::new();
+    let program = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x86, 0x70, 0x00];
+    for (offset, byte) in program.iter().enumerate() {
+      ram.write(Address::from(0x1900 + offset as u16), *byte);
+    }
+    let mut cpu = CPU::new();
+    cpu.registers.pc = Address::from(0x1900);
+    let mut monitor = Monitor::new();
+    monitor.pause();
+    (cpu, ram, monitor)
+  }
+
+  // as main does, until paused again
+  fn run(gdb: &mut Gdb<Script>, monitor: &mut Monitor, cpu: &mut CPU, ram: &mut RAM) {
+    while !monitor.check(cpu) {
+      cpu.step(&mut monitor.watch(ram));
+      gdb.watched(monitor);
+    }
+  }
+
+  #[test]
+  fn registers_and_memory() {
+    let (mut cpu, mut ram, mut monitor) = machine();
+    let mut gdb = Gdb::new(Script::default());
+    send(&mut gdb, &["qSupported:swbreak+", "?", "g", "p5", "P0=42", "Px=1",
+                     "m1900,5", "M2000,2:4869", "G0102033430341a", "vCont?",
+                     "qXfer:features:read:target.xml:0,10", "k"]);
+    assert_eq!(gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap(), Action::Quit);
+    assert_eq!(replies(&mut gdb), [
+      "PacketSize=1000;qXfer:features:read+", "S05", "000000ff200019", "0019", "OK", "E01",
+      "a203cad0fd", "OK", "OK", "", "m<?xml version=\"1",
+    ]);
+    assert_eq!(ram.read(Address::from(0x2001)), b'i');
+    assert_eq!((cpu.registers.a, cpu.registers.s.to_u8()), (0x01, 0x34));
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1a34);
+
+    gdb.connection.input.extend(b"$g#00+$?#3f+");
+    gdb.connection.input.extend(b"$\x80#80+$\xc3\xa9#6c+"); // not ASCII, not supported
+    gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap(); // hung up
+    assert_eq!(String::from_utf8(gdb.connection.output).unwrap(), "-+$S05#b8+$#00+$#00");
+  }
+
+  #[test]
+  fn breakpoints_and_watchpoints() {
+    let (mut cpu, mut ram, mut monitor) = machine();
+    let mut gdb = Gdb::new(Script::default());
+    send(&mut gdb, &["Z0,1903,1", "Z2,70,1", "c"]);
+    assert_eq!(gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap(), Action::Run);
+    run(&mut gdb, &mut monitor, &mut cpu, &mut ram);
+    send(&mut gdb, &["s"]);
+    assert_eq!(gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap(), Action::Run);
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1903);
+    assert_eq!(cpu.registers.x, 2);
+    run(&mut gdb, &mut monitor, &mut cpu, &mut ram);
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1902);
+
+    send(&mut gdb, &["z0,1903,1", "c"]);
+    gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap();
+    run(&mut gdb, &mut monitor, &mut cpu, &mut ram);
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1907); // after STX
+    send(&mut gdb, &["D"]);
+    assert_eq!(gdb.serve(&mut monitor, &mut cpu, &mut ram).unwrap(), Action::Run);
+    assert!(gdb.detached());
+    assert!(!monitor.check(&cpu));
+    assert_eq!(replies(&mut gdb), ["OK", "OK", "S05", "S05", "OK", "T05watch:70;", "OK"]);
+  }
+}
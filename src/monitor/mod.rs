@@ -0,0 +1,541 @@
+// Machine code monitor: pause the machine, step it, set PC breakpoints and
+// watchpoints, look at and change memory and registers. Commands are read a
+// line at a time, an empty line repeats the last one (to keep stepping).
+// A GDB remote debugger can drive the same state instead of the commands.
+//
+// Memory is accessed through the bus, as the CPU would: reading SHEILA has
+// the side effects it has for a program.
+
+pub mod gdb; // GDB remote serial protocol
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::memory::{Address, MemoryBus, parse_number, slice};
+use crate::memory::watch::{Access, Hit, Watched, Watchpoints};
+use crate::mos6502::CPU;
+use crate::mos6502::disassemble::{disassemble_with_symbols, instruction_size};
+use crate::mos6502::registers::Status;
+use crate::mos6502::symbols::Symbols;
+
+pub const HELP: &str = "\
+  r [REG VALUE]       show registers, or set a, x, y, p, s or pc
+  s [N]               step N instructions [1]
+  c                   continue
+  b [ADDR]            break at ADDR, or list breakpoints
+  bc ADDR|*           clear breakpoint at ADDR, or all
+  wr|ww|wa RANGE      stop after reads, writes or any access in ADDR[-END]
+  wl                  list watchpoints
+  wc ADDR|*           clear watchpoints covering ADDR, or all
+  m [ADDR] [LEN]      dump LEN bytes of memory [after last dump] [128]
+  w ADDR BYTE...      write bytes to memory
+  d [ADDR] [N]        disassemble N instructions [around PC] [8]
+  q                   quit
+  h                   show this
+Addresses, bytes and register values are hexadecimal, counts decimal";
+
+const DUMP_BYTES: usize = 128;
+const DISASSEMBLE_LINES: usize = 8;
+const LINES_BEFORE_PC: usize = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+  Stay, // waiting for more commands
+  Run,
+  Quit,
+}
+
+#[derive(Debug, Default)]
+pub struct Monitor {
+  breakpoints: BTreeSet<u16>,
+  watchpoints: Watchpoints,
+  symbols: Rc<Symbols>,
+  paused: bool,
+  steps: Option<u64>,         // instructions left to single step
+  resumed_at: Option<u16>,    // don't break again straight away
+  last_command: String,
+  next_dump: u16,
+  next_disassembly: Option<u16>,
+}
+
+// &1900, $1900, 0x1900 or 1900
+fn parse_hex(text: &str) -> Result<u16, String> {
+  parse_number(text).and_then(|number| u16::try_from(number).ok())
+    .ok_or_else(|| format!("bad hexadecimal number {text}"))
+}
+
+// ADDR or ADDR-END
+fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+  let (start, end) = text.split_once('-').unwrap_or((text, text));
+  let (start, end) = (parse_hex(start)?, parse_hex(end)?);
+  if start > end {
+    return Err(format!("range {text} ends before it starts"));
+  }
+  Ok(start..=end)
+}
+
+fn parse_byte(text: &str) -> Result<u8, String> {
+  u8::try_from(parse_hex(text)?).map_err(|_| format!("{text} is not a byte"))
+}
+
+impl Monitor {
+  pub fn new() -> Self {
+    Monitor::default()
+  }
+
+  pub fn set_symbols(&mut self, symbols: Rc<Symbols>) {
+    self.symbols = symbols;
+  }
+
+  pub fn pause(&mut self) {
+    self.paused = true;
+  }
+
+  pub const fn paused(&self) -> bool {
+    self.paused
+  }
+
+  pub fn add_breakpoint(&mut self, address: u16) {
+    self.breakpoints.insert(address);
+  }
+
+  pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+    self.breakpoints.remove(&address)
+  }
+
+  pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, access: Access) {
+    self.watchpoints.add(range, access);
+  }
+
+  pub fn remove_watchpoint(&mut self, range: &RangeInclusive<u16>, access: Access) -> bool {
+    self.watchpoints.remove_range(range, access)
+  }
+
+  // Forget all breakpoints and watchpoints
+  pub fn clear_points(&mut self) {
+    self.breakpoints.clear();
+    self.watchpoints.clear();
+  }
+
+  // Run again from pc, for a number of instructions or until stopped
+  pub fn resume(&mut self, pc: u16, steps: Option<u64>) {
+    self.steps = steps;
+    self.resumed_at = Some(pc);
+    self.paused = false;
+  }
+
+  // The bus for the CPU to step on, noting watched accesses
+  pub fn watch<'a>(&'a self, memory: &'a mut dyn MemoryBus) -> Watched<'a> {
+    Watched::new(memory, &self.watchpoints)
+  }
+
+  // Watched accesses by the instruction just stepped, pausing if any
+  pub fn take_hits(&mut self) -> Vec<Hit> {
+    let hits = self.watchpoints.take_hits();
+    if !hits.is_empty() {
+      self.steps = None;
+      self.paused = true;
+    }
+    hits
+  }
+
+  // After stepping the instruction at pc: report watched accesses
+  pub fn watched(&mut self, pc: u16, output: &mut dyn Write) -> io::Result<()> {
+    for hit in self.take_hits() {
+      let access = match hit.access {
+        Access::Read => "read",
+        Access::Write => "write",
+      };
+      writeln!(output, "pc:{pc:04x} {access} {:04x} value:{:02x}", hit.address, hit.value)?;
+    }
+    Ok(())
+  }
+
+  // Before each instruction: pause on a breakpoint or after the steps asked
+  // for, true when paused
+  pub fn check(&mut self, cpu: &CPU) -> bool {
+    if self.paused {
+      return true;
+    }
+    let pc = cpu.registers.pc.to_u16();
+    let resuming = self.resumed_at.take() == Some(pc);
+    match self.steps {
+      Some(0) => {
+        self.steps = None;
+        self.paused = true;
+      },
+      Some(left) => self.steps = Some(left - 1),
+      None => {},
+    }
+    if self.breakpoints.contains(&pc) && !resuming {
+      self.steps = None;
+      self.paused = true;
+    }
+    self.paused
+  }
+
+  pub fn registers(&self, cpu: &CPU, output: &mut dyn Write) -> io::Result<()> {
+    let registers = &cpu.registers;
+    writeln!(output, "pc:{:04x} a:{:02x} x:{:02x} y:{:02x} s:{:02x} p:{:?} cycles:{}",
+             registers.pc.to_u16(), registers.a, registers.x, registers.y,
+             registers.s.to_u8(), registers.p, cpu.cycles)
+  }
+
+  fn disassemble(&mut self, cpu: &CPU, memory: &dyn MemoryBus, from: u16, lines: usize,
+                 output: &mut dyn Write) -> io::Result<()> {
+    let pc = cpu.registers.pc.to_u16();
+    let mut address = from;
+    for _ in 0..lines {
+      let bytes = slice(memory, Address::from(address), 3);
+      if let Some(name) = self.symbols.get(address) {
+        writeln!(output, "{name}")?;
+      }
+      let marker = if address == pc { '>' } else { ' ' };
+      let disassembly = disassemble_with_symbols(Address::from(address), &bytes, &self.symbols);
+      writeln!(output, "{marker} {disassembly}")?;
+      address = address.wrapping_add(instruction_size(bytes[0]) as u16);
+    }
+    self.next_disassembly = Some(address);
+    Ok(())
+  }
+
+  // Decode forward from a little way back, from the furthest start that
+  // lands exactly on the address. Code can't always be told from data: a
+  // best guess
+  fn instructions_before(memory: &dyn MemoryBus, address: u16, count: usize) -> u16 {
+    for back in (1..=count as u16 * 3).rev() {
+      let mut starts = Vec::new();
+      let mut start = address.wrapping_sub(back);
+      while starts.len() <= back as usize && start != address {
+        starts.push(start);
+        start = start.wrapping_add(instruction_size(memory.read(Address::from(start))) as u16);
+      }
+      if start == address {
+        return starts[starts.len().saturating_sub(count)];
+      }
+    }
+    address
+  }
+
+  // Where the machine stopped
+  pub fn show(&mut self, cpu: &CPU, memory: &dyn MemoryBus, output: &mut dyn Write)
+              -> io::Result<()> {
+    self.registers(cpu, output)?;
+    let pc = cpu.registers.pc.to_u16();
+    self.disassemble(cpu, memory, pc, 1, output)?;
+    self.next_disassembly = None;
+    Ok(())
+  }
+
+  fn dump(&mut self, memory: &dyn MemoryBus, from: u16, length: usize, output: &mut dyn Write)
+          -> io::Result<()> {
+    let bytes = slice(memory, Address::from(from), length);
+    for (row, bytes) in bytes.chunks(16).enumerate() {
+      let address = from.wrapping_add(row as u16 * 16);
+      let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+      let text: String = bytes.iter()
+        .map(|byte| if (0x20..0x7F).contains(byte) { *byte as char } else { '.' })
+        .collect();
+      writeln!(output, "{:?}  {:<47}  {text}", Address::from(address), hex.join(" "))?;
+    }
+    self.next_dump = from.wrapping_add(length as u16);
+    Ok(())
+  }
+
+  fn set_register(cpu: &mut CPU, register: &str, value: &str) -> Result<(), String> {
+    let registers = &mut cpu.registers;
+    match register {
+      "pc" => registers.pc = Address::from(parse_hex(value)?),
+      "a" => registers.a = parse_byte(value)?,
+      "x" => registers.x = parse_byte(value)?,
+      "y" => registers.y = parse_byte(value)?,
+      "s" => *registers.s.borrow_mut() = parse_byte(value)?,
+      "p" => registers.p = Status::from(parse_byte(value)?),
+      _ => return Err(format!("no register {register}")),
+    }
+    Ok(())
+  }
+
+  fn count(text: Option<&&str>, default: usize) -> Result<usize, String> {
+    text.map_or(Ok(default), |text| text.parse().map_err(|_| format!("bad count {text}")))
+  }
+
+  fn execute(&mut self, words: &[&str], cpu: &mut CPU, memory: &mut dyn MemoryBus,
+             output: &mut dyn Write) -> Result<Action, String> {
+    let io = |error: io::Error| error.to_string();
+    let pc = cpu.registers.pc.to_u16();
+    match words {
+      [] => {},
+      ["r"] => self.registers(cpu, output).map_err(io)?,
+      ["r", register, value] => Self::set_register(cpu, register, value)?,
+      ["s"] | ["s", _] => {
+        let steps = Self::count(words.get(1), 1)?;
+        if steps == 0 {
+          return Ok(Action::Stay);
+        }
+        self.resume(pc, Some(steps as u64));
+        return Ok(Action::Run);
+      },
+      ["c"] => {
+        self.resume(pc, None);
+        return Ok(Action::Run);
+      },
+      ["b"] => {
+        let addresses: Vec<String> = self.breakpoints.iter().map(|address| format!("{address:04x}"))
+          .collect();
+        writeln!(output, "breakpoints: {}", addresses.join(" ")).map_err(io)?;
+      },
+      ["b", address] => self.add_breakpoint(parse_hex(address)?),
+      ["bc", "*"] => self.breakpoints.clear(),
+      ["bc", address] => {
+        if !self.remove_breakpoint(parse_hex(address)?) {
+          return Err(format!("no breakpoint at {address}"));
+        }
+      },
+      ["wr", range] => self.add_watchpoint(parse_range(range)?, Access::Read),
+      ["ww", range] => self.add_watchpoint(parse_range(range)?, Access::Write),
+      ["wa", range] => {
+        let range = parse_range(range)?;
+        self.add_watchpoint(range.clone(), Access::Read);
+        self.add_watchpoint(range, Access::Write);
+      },
+      ["wl"] => {
+        for (range, access) in self.watchpoints.iter() {
+          writeln!(output, "{:04x}-{:04x} {access:?}", range.start(), range.end()).map_err(io)?;
+        }
+      },
+      ["wc", "*"] => self.watchpoints.clear(),
+      ["wc", address] => {
+        if !self.watchpoints.remove(parse_hex(address)?) {
+          return Err(format!("no watchpoint at {address}"));
+        }
+      },
+      ["m", ..] if words.len() <= 3 => {
+        let from = words.get(1).map_or(Ok(self.next_dump), |address| parse_hex(address))?;
+        let length = Self::count(words.get(2), DUMP_BYTES)?;
+        self.dump(memory, from, length, output).map_err(io)?;
+      },
+      ["w", address, bytes @ ..] if !bytes.is_empty() => {
+        let address = parse_hex(address)?;
+        let bytes = bytes.iter().map(|byte| parse_byte(byte)).collect::<Result<Vec<u8>, _>>()?;
+        for (offset, byte) in bytes.iter().enumerate() {
+          memory.write(Address::from(address.wrapping_add(offset as u16)), *byte);
+        }
+      },
+      ["d", ..] if words.len() <= 3 => {
+        let from = match words.get(1) {
+          Some(address) => parse_hex(address)?,
+          None => self.next_disassembly
+            .unwrap_or_else(|| Self::instructions_before(memory, pc, LINES_BEFORE_PC)),
+        };
+        let lines = Self::count(words.get(2), DISASSEMBLE_LINES)?;
+        self.disassemble(cpu, memory, from, lines, output).map_err(io)?;
+      },
+      ["q"] => return Ok(Action::Quit),
+      ["h"] | ["?"] => writeln!(output, "{HELP}").map_err(io)?,
+      _ => return Err(format!("unknown command {}, h for help", words.join(" "))),
+    }
+    Ok(Action::Stay)
+  }
+
+  pub fn command(&mut self, line: &str, cpu: &mut CPU, memory: &mut dyn MemoryBus,
+                 output: &mut dyn Write) -> io::Result<Action> {
+    let line = match line.trim() {
+      "" => self.last_command.clone(),
+      line => line.to_lowercase(),
+    };
+    self.last_command = line.clone();
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match self.execute(&words, cpu, memory, output) {
+      Ok(action) => Ok(action),
+      Err(message) => {
+        writeln!(output, "{message}")?;
+        Ok(Action::Stay)
+      },
+    }
+  }
+
+  // Commands until the machine is to run again, or quit (also at end of input)
+  pub fn prompt(&mut self, cpu: &mut CPU, memory: &mut dyn MemoryBus,
+                input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<Action> {
+    self.show(cpu, memory, output)?;
+    loop {
+      write!(output, "> ")?;
+      output.flush()?;
+      let mut line = String::new();
+      if input.read_line(&mut line)? == 0 {
+        writeln!(output)?;
+        return Ok(Action::Quit);
+      }
+      match self.command(&line, cpu, memory, output)? {
+        Action::Stay => {},
+        action => return Ok(action),
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::ram::RAM;
+
+  // LDX #3 / loop: DEX / BNE loop / BRK
+  const PROGRAM: [u8; 6] = [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x00];
+
+  fn machine() -> (CPU, RAM) {
+    let mut ram = RAM::new();
+    for (offset, byte) in PROGRAM.iter().enumerate() {
+      ram.write(Address::from(0x1900 + offset as u16), *byte);
+    }
+    let mut cpu = CPU::new();
+    cpu.registers.pc = Address::from(0x1900);
+    (cpu, ram)
+  }
+
+  fn command(monitor: &mut Monitor, line: &str, cpu: &mut CPU, ram: &mut RAM) -> (Action, String) {
+    let mut output = Vec::new();
+    let action = monitor.command(line, cpu, ram, &mut output).unwrap();
+    (action, String::from_utf8(output).unwrap())
+  }
+
+  // until paused
+  fn run(monitor: &mut Monitor, cpu: &mut CPU, ram: &mut RAM) {
+    while !monitor.check(cpu) {
+      cpu.step(ram);
+    }
+  }
+
+  #[test]
+  fn breakpoints_and_steps() {
+    let (mut cpu, mut ram) = machine();
+    let mut monitor = Monitor::new();
+    assert_eq!(command(&mut monitor, "b 1903", &mut cpu, &mut ram).0, Action::Stay);
+    command(&mut monitor, "B $1905", &mut cpu, &mut ram);
+    assert_eq!(command(&mut monitor, "b", &mut cpu, &mut ram).1, "breakpoints: 1903 1905\n");
+
+    run(&mut monitor, &mut cpu, &mut ram);
+    assert_eq!((cpu.registers.pc.to_u16(), cpu.registers.x), (0x1903, 2));
+    assert_eq!(command(&mut monitor, "c", &mut cpu, &mut ram).0, Action::Run);
+    run(&mut monitor, &mut cpu, &mut ram);
+    assert_eq!((cpu.registers.pc.to_u16(), cpu.registers.x), (0x1903, 1));
+
+    command(&mut monitor, "bc 1903", &mut cpu, &mut ram);
+    assert_eq!(command(&mut monitor, "s 2", &mut cpu, &mut ram).0, Action::Run);
+    run(&mut monitor, &mut cpu, &mut ram);
+    assert_eq!((cpu.registers.pc.to_u16(), cpu.registers.x), (0x1903, 0));
+    command(&mut monitor, "", &mut cpu, &mut ram); // again, not taken
+    run(&mut monitor, &mut cpu, &mut ram);
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1905);
+
+    command(&mut monitor, "bc *", &mut cpu, &mut ram);
+    assert_eq!(command(&mut monitor, "bc 1905", &mut cpu, &mut ram).1, "no breakpoint at 1905\n");
+  }
+
+  #[test]
+  fn memory_and_registers() {
+    let (mut cpu, mut ram) = machine();
+    let mut monitor = Monitor::new();
+    command(&mut monitor, "w 2000 48 65 6c 6c 6f", &mut cpu, &mut ram);
+    let (_, dump) = command(&mut monitor, "m 2000 10", &mut cpu, &mut ram);
+    assert_eq!(dump, format!("&0x2000  {:<47}  Hello.....\n", "48 65 6c 6c 6f 00 00 00 00 00"));
+    let (_, dump) = command(&mut monitor, "m", &mut cpu, &mut ram);
+    assert!(dump.starts_with("&0x200a  00 00"));
+
+    command(&mut monitor, "r a 42", &mut cpu, &mut ram);
+    command(&mut monitor, "r pc &1902", &mut cpu, &mut ram);
+    let (_, registers) = command(&mut monitor, "r", &mut cpu, &mut ram);
+    assert!(registers.starts_with("pc:1902 a:42 x:00"), "{registers}");
+    assert_eq!(command(&mut monitor, "r a 100", &mut cpu, &mut ram).1, "100 is not a byte\n");
+    assert_eq!(command(&mut monitor, "w 2000 zz", &mut cpu, &mut ram).1,
+               "bad hexadecimal number zz\n");
+    assert_eq!(command(&mut monitor, "x", &mut cpu, &mut ram).1, "unknown command x, h for help\n");
+  }
+
+  #[test]
+  fn disassemble_around_pc() {
+    let (mut cpu, mut ram) = machine();
+    cpu.registers.pc = Address::from(0x1903);
+    let mut monitor = Monitor::new();
+    let (_, listing) = command(&mut monitor, "d 1900 4", &mut cpu, &mut ram);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("  &0x1900  a2 03"), "{listing}");
+    assert!(lines[2].starts_with("> &0x1903  d0 fd"), "{listing}");
+    assert!(lines[3].starts_with("  &0x1905  00"), "{listing}");
+
+    let (_, listing) = command(&mut monitor, "d", &mut cpu, &mut ram); // carries on
+    assert!(listing.starts_with("  &0x1906"), "{listing}");
+    monitor.show(&cpu, &ram, &mut Vec::new()).unwrap();
+    let (_, listing) = command(&mut monitor, "d", &mut cpu, &mut ram);
+    assert!(listing.lines().any(|line| line.starts_with("  &0x1902  ca")), "{listing}");
+    assert!(listing.lines().any(|line| line.starts_with("> &0x1903")), "{listing}");
+
+    let mut symbols = Symbols::new();
+    symbols.insert(0x1902, ".loop");
+    monitor.set_symbols(Rc::new(symbols));
+    let (_, listing) = command(&mut monitor, "d 1902 2", &mut cpu, &mut ram);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines[0], ".loop");
+    assert!(lines[2].ends_with("BNE .loop"), "{listing}");
+  }
+
+  // steps as main does, reporting watched accesses
+  fn run_watched(monitor: &mut Monitor, cpu: &mut CPU, ram: &mut RAM, output: &mut Vec<u8>) {
+    while !monitor.check(cpu) {
+      let pc = cpu.registers.pc.to_u16();
+      cpu.step(&mut monitor.watch(ram));
+      monitor.watched(pc, output).unwrap();
+    }
+  }
+
+  #[test]
+  fn watchpoints() {
+    let (mut cpu, mut ram) = machine();
+    ram.write(Address::from(0x1905), 0x8E); // STX &0070
+    ram.write(Address::from(0x1906), 0x70);
+    ram.write(Address::from(0x1907), 0x00);
+    let mut monitor = Monitor::new();
+    command(&mut monitor, "ww 0070-007f", &mut cpu, &mut ram);
+    command(&mut monitor, "wr 1903", &mut cpu, &mut ram); // BNE, fetched
+    assert_eq!(command(&mut monitor, "wl", &mut cpu, &mut ram).1,
+               "0070-007f Write\n1903-1903 Read\n");
+
+    let mut output = Vec::new();
+    run_watched(&mut monitor, &mut cpu, &mut ram, &mut output);
+    assert_eq!(String::from_utf8(output).unwrap(), "pc:1903 read 1903 value:d0\n");
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1902); // after the branch
+    command(&mut monitor, "wc 1903", &mut cpu, &mut ram);
+    command(&mut monitor, "c", &mut cpu, &mut ram);
+
+    let mut output = Vec::new();
+    run_watched(&mut monitor, &mut cpu, &mut ram, &mut output);
+    assert_eq!(String::from_utf8(output).unwrap(), "pc:1905 write 0070 value:00\n");
+    assert_eq!(cpu.registers.pc.to_u16(), 0x1908);
+
+    assert_eq!(command(&mut monitor, "wc 80", &mut cpu, &mut ram).1, "no watchpoint at 80\n");
+    assert_eq!(command(&mut monitor, "ww 80-70", &mut cpu, &mut ram).1,
+               "range 80-70 ends before it starts\n");
+  }
+
+  #[test]
+  fn prompt_until_run() {
+    let (mut cpu, mut ram) = machine();
+    let mut monitor = Monitor::new();
+    monitor.pause();
+    assert!(monitor.check(&cpu));
+    let mut input = &b"r x 7\ns\n"[..];
+    let mut output = Vec::new();
+    let action = monitor.prompt(&mut cpu, &mut ram, &mut input, &mut output).unwrap();
+    assert_eq!(action, Action::Run);
+    assert!(!monitor.check(&cpu));
+    cpu.step(&mut ram);
+    assert!(monitor.check(&cpu)); // one step
+    assert_eq!(cpu.registers.x, 3);
+
+    let action = monitor.prompt(&mut cpu, &mut ram, &mut &b""[..], &mut output).unwrap();
+    assert_eq!(action, Action::Quit); // end of input
+  }
+}
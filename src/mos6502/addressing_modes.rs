@@ -7,6 +7,8 @@ use crate::memory::read_address;
 pub trait UseMode {
   fn get_size() -> u8 where Self: Sized;
   fn get_operand(_: &[u8]) -> String where Self: Sized;
+  // operand with a symbol in place of the address it holds
+  fn get_operand_named(name: &str) -> String where Self: Sized { name.to_string() }
   fn get_name() -> &'static str;
 }
 
@@ -115,6 +117,9 @@ impl<const XY: char> UseMode for UseZeroPageWith<XY> {
     let value = bytes[0];
     format!("&{:#04x} + {}", value, XY)
   }
+  fn get_operand_named(name: &str) -> String {
+    format!("{} + {}", name, XY)
+  }
   fn get_name() -> &'static str {
     match XY {
       'x'|'X' => "zero page X",
@@ -127,6 +132,10 @@ impl<const XY: char> UseMode for UseZeroPageWith<XY> {
 pub struct UseRelative;
 impl UseRelative {
   pub fn get_operand_with_address(address: Address, bytes: &[u8]) -> String {
+    format!("{:?}", Self::get_target(address, bytes))
+  }
+
+  pub fn get_target(address: Address, bytes: &[u8]) -> Address {
     // resolve offset to absolute address, relative to start of branch
     // instruction
     assert!(bytes.len() == 1); // operand is pc-relative offset (1 x i8)
@@ -138,7 +147,7 @@ impl UseRelative {
     } else {
       address.dec_by(!operand + 1)
     }
-    format!("{address:?}")
+    address
   }
 }
 impl UseAddress for UseRelative {
@@ -194,6 +203,13 @@ impl<const XY: char> UseAddress for UseAbsoluteWith<XY> {
     operand
   }
 }
+impl<const XY: char> UseAbsoluteWith<XY> {
+  pub fn crosses_page(registers: &Registers, memory: &dyn MemoryBus) -> bool {
+    let register = get_index_register::<XY>(registers);
+    let operand = read_address(memory, registers.pc);
+    operand.lo_u8().checked_add(*register).is_none()
+  }
+}
 impl<const XY: char> UseMode for UseAbsoluteWith<XY> {
   fn get_size() -> u8 { 2 }
   fn get_operand(bytes: &[u8]) -> String {
@@ -202,6 +218,9 @@ impl<const XY: char> UseMode for UseAbsoluteWith<XY> {
     let value = ((hi as u16) << 8) | (lo as u16);
     format!("&{:#06x} + {}", value, XY)
   }
+  fn get_operand_named(name: &str) -> String {
+    format!("{} + {}", name, XY)
+  }
   fn get_name() -> &'static str {
     match XY {
       'x'|'X' => "absolute X",
@@ -229,6 +248,9 @@ impl UseMode for UseIndirect {
     let value = ((hi as u16) << 8) | (lo as u16);
     format!("&({:#06x})", value)
   }
+  fn get_operand_named(name: &str) -> String {
+    format!("({})", name)
+  }
   fn get_name() -> &'static str { "indirect" }
 }
 
@@ -247,6 +269,9 @@ impl UseMode for UseIndexedIndirectX {
     let value = bytes[0];
     format!("(&{:#04x} + X)", value)
   }
+  fn get_operand_named(name: &str) -> String {
+    format!("({} + X)", name)
+  }
   fn get_name() -> &'static str { "indexed indirect X" }
 }
 
@@ -260,12 +285,23 @@ impl UseAddress for UseIndirectIndexedY {
     address
   }
 }
+impl UseIndirectIndexedY {
+  pub fn crosses_page(registers: &Registers, memory: &dyn MemoryBus) -> bool {
+    let operand = memory.read(registers.pc);
+    let address = Address::from_le_bytes(operand, 0x00);
+    let address = read_address(memory, address);
+    address.lo_u8().checked_add(registers.y).is_none()
+  }
+}
 impl UseMode for UseIndirectIndexedY {
   fn get_size() -> u8 { 1 }
   fn get_operand(bytes: &[u8]) -> String {
     let value = bytes[0];
     format!("(&{:#04x}) + Y", value)
   }
+  fn get_operand_named(name: &str) -> String {
+    format!("({}) + Y", name)
+  }
   fn get_name() -> &'static str { "indirect indexed Y" }
 }
 
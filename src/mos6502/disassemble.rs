@@ -1,6 +1,7 @@
 use super::instructions::{Instruction, AddressingMode};
 use crate::memory::Address;
 use crate::mos6502::addressing_modes::UseRelative;
+use crate::mos6502::symbols::Symbols;
 
 // iterate over variable size &[u8] chunks, where each 1, 2 or 3 byte chunk is
 // a 6502 instruction 
@@ -20,8 +21,8 @@ impl<'a> Iterator for Chunks<'a> {
   fn next(&mut self) -> Option<Self::Item> {
     if self.index < self.bytes.len() {
       let start = self.index;
-      let operation = Instruction::lookup(self.bytes[start]);
-      let end = start + 1 + operation.addressing_mode.get_size() as usize;
+      let end = start + instruction_size(self.bytes[start]);
+      let end = end.min(self.bytes.len()); // last instruction may be truncated
       self.index = end;
       Some(&self.bytes[start .. end])
     } else {
@@ -30,6 +31,11 @@ impl<'a> Iterator for Chunks<'a> {
   }
 }
 
+// opcode and operand bytes
+pub fn instruction_size(opcode: u8) -> usize {
+  1 + Instruction::lookup(opcode).addressing_mode.get_size() as usize
+}
+
 fn hexdump(bytes: &[u8], size: usize) -> String {
   // trucate if byte slice is too large
   let bytes = if size < bytes.len() {
@@ -53,21 +59,21 @@ fn hexdump(bytes: &[u8], size: usize) -> String {
   hexs.join(" ")
 }
 
-fn do_disassemble(bytes: &[u8], get_operand: impl Fn(&AddressingMode, &[u8]) -> String) -> String {
+// mnemonic and operand
+fn do_instruction(bytes: &[u8], get_operand: impl Fn(&AddressingMode, &[u8]) -> String) -> String {
   assert!(bytes.len() > 0);
   let operation = Instruction::lookup(bytes[0]);
   let operand_size = operation.addressing_mode.get_size() as usize;
-  let hexdump = hexdump(bytes, 1 + operand_size);
   let mnemonic = operation.mnemonic.to_str();
   let result = if bytes.len() < 1 + operand_size {
     let addressing_mode = operation.addressing_mode.get_name();
-    format!("{hexdump} {mnemonic} {addressing_mode}")
+    format!("{mnemonic} {addressing_mode}")
   } else if operand_size > 0 {
     let operand = &bytes[1 .. 1 + operand_size];
     let addressing_mode = get_operand(&operation.addressing_mode, operand);
-    format!("{hexdump} {mnemonic} {addressing_mode}")
+    format!("{mnemonic} {addressing_mode}")
   } else {
-    format!("{hexdump} {mnemonic}")
+    mnemonic.to_string()
   };
   if !operation.is_valid() {
     format!("{result} <-- invalid opcode")
@@ -76,6 +82,38 @@ fn do_disassemble(bytes: &[u8], get_operand: impl Fn(&AddressingMode, &[u8]) ->
   }
 }
 
+fn do_disassemble(bytes: &[u8], get_operand: impl Fn(&AddressingMode, &[u8]) -> String) -> String {
+  assert!(bytes.len() > 0);
+  let size = instruction_size(bytes[0]);
+  format!("{} {}", hexdump(bytes, size), do_instruction(bytes, get_operand))
+}
+
+// address the operand refers to, if any
+fn operand_address(addressing_mode: &AddressingMode, address: Address, bytes: &[u8])
+                   -> Option<u16> {
+  use AddressingMode::*;
+  match addressing_mode {
+    ZeroPage | ZeroPageX | ZeroPageY | IndexedIndirectX | IndirectIndexedY => Some(bytes[0] as u16),
+    Absolute | AbsoluteX | AbsoluteY | Indirect => {
+      Some(Address::from_le_bytes(bytes[0], bytes[1]).to_u16())
+    },
+    Relative => Some(UseRelative::get_target(address, bytes).to_u16()),
+    _ => None,
+  }
+}
+
+// branch targets as addresses, and addresses with symbols by name
+fn operand_at(address: Address, symbols: &Symbols) -> impl Fn(&AddressingMode, &[u8]) -> String + '_ {
+  move |addressing_mode: &AddressingMode, bytes: &[u8]| -> String {
+    let name = operand_address(addressing_mode, address, bytes).and_then(|value| symbols.get(value));
+    match (name, addressing_mode) {
+      (Some(name), _) => addressing_mode.get_operand_named(name),
+      (None, AddressingMode::Relative) => UseRelative::get_operand_with_address(address, bytes),
+      (None, _) => addressing_mode.get_operand(bytes),
+    }
+  }
+}
+
 pub fn disassemble(bytes: &[u8]) -> String {
   let get_operand = |addressing_mode: &AddressingMode, bytes: &[u8]| -> String {
     addressing_mode.get_operand(bytes)
@@ -84,15 +122,36 @@ pub fn disassemble(bytes: &[u8]) -> String {
 }
 
 pub fn disassemble_with_address(address: Address, bytes: &[u8]) -> String {
-  let get_operand = |addressing_mode: &AddressingMode, bytes: &[u8]| -> String {
-    use AddressingMode::*;
-    match addressing_mode {
-      Relative => UseRelative::get_operand_with_address(address, bytes),
-      _        => addressing_mode.get_operand(bytes),
-    }
-  };
+  disassemble_with_symbols(address, bytes, &Symbols::new())
+}
 
-  format!("{address:?}  {}", do_disassemble(bytes, get_operand))
+pub fn disassemble_with_symbols(address: Address, bytes: &[u8], symbols: &Symbols) -> String {
+  format!("{address:?}  {}", do_disassemble(bytes, operand_at(address, symbols)))
+}
+
+// Mnemonic and operand, without address or bytes
+pub fn instruction_with_symbols(address: Address, bytes: &[u8], symbols: &Symbols) -> String {
+  do_instruction(bytes, operand_at(address, symbols))
+}
+
+#[test]
+fn symbols()
+{
+  let mut symbols = Symbols::new();
+  symbols.parse("OSWRCH = &FFEE\nvduQueue = &26A\nzp = &70\n.loop = &1902").unwrap();
+  let at = Address::from(0x1900);
+  let with_symbols = |bytes: &[u8]| instruction_with_symbols(at, bytes, &symbols);
+  assert_eq!(with_symbols(&[0x20, 0xEE, 0xFF]), "JSR OSWRCH");
+  assert_eq!(with_symbols(&[0x6C, 0xEE, 0xFF]), "JMP (OSWRCH)");
+  assert_eq!(with_symbols(&[0xA1, 0x70]), "LDA (zp + X)");
+  assert_eq!(with_symbols(&[0xD0, 0xFE]), "BNE &0x1900");
+  assert_eq!(with_symbols(&[0xBD, 0x6A, 0x02]), "LDA vduQueue + X");
+  assert_eq!(with_symbols(&[0xB1, 0x70]), "LDA (zp) + Y");
+  assert_eq!(with_symbols(&[0xD0, 0x00]), "BNE .loop");
+  assert_eq!(with_symbols(&[0xA9, 0x70]), "LDA #0x70");
+  assert_eq!(with_symbols(&[0x8D, 0x6B, 0x02]), "STA &0x026b");
+  assert_eq!(disassemble_with_symbols(at, &[0x20, 0xEE, 0xFF], &symbols),
+             "&0x1900  20 ee ff JSR OSWRCH");
 }
 
 #[test]
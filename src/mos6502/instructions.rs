@@ -27,7 +27,10 @@ use crate::mos6502::registers::Registers;
 
 pub enum Mnemonic {
   ADC, // ADd with Carry
+  ALR, // AND then Logical shift Right (undocumented)
   AND, // logical AND (bitwise)
+  ANC, // AND, copy N into Carry (undocumented)
+  ARR, // AND then Rotate Right (undocumented)
   ASL, // Arithmetic Shift Left
   BCC, // Branch if Carry Clear
   BCS, // Branch if Carry Set
@@ -47,6 +50,7 @@ pub enum Mnemonic {
   CMP, // Compare
   CPX, // Compare X register
   CPY, // Compare Y register
+  DCP, // DeCrement memory then comPare (undocumented)
   DEC, // DECrement memory
   DEX, // DEcrement X register
   DEY, // DEcrement Y register
@@ -54,8 +58,10 @@ pub enum Mnemonic {
   INC, // INCrement memory
   INX, // INcrement X register
   INY, // INcrement Y register
+  ISB, // Increment memory then SuBtract with carry (undocumented)
   JMP, // JuMP
   JSR, // Jump to SubRoutine
+  LAX, // LoaD Accumulator and X register (undocumented)
   LDA, // LoaD Accumulator
   LDX, // LoaD X register
   LDY, // LoaD Y register
@@ -70,14 +76,20 @@ pub enum Mnemonic {
   PLP, // PuLl Processor status
   _PLX, // PuLl X
   _PLY, // PuLl Y
+  RLA, // Rotate Left then AND (undocumented)
   ROL, // ROtate Left
   ROR, // ROtate Right
+  RRA, // Rotate Right then Add with carry (undocumented)
   RTI, // ReTurn from Interrupt
   RTS, // ReTurn from Subroutine
+  SAX, // Store Accumulator AND X register (undocumented)
   SBC, // SuBtract with Carry
+  SBX, // Subtract from (A AND X) into X register (undocumented)
   SEC, // SEt Carry flag
   SED, // SEt Decimal flag
   SEI, // SEt Interrupt disable
+  SLO, // Shift Left then OR (undocumented)
+  SRE, // Shift Right then Exclusive or (undocumented)
   STA, // STore Accumulator
   STX, // STore X register
   STY, // STore Y register
@@ -97,7 +109,10 @@ impl Mnemonic {
   pub const fn to_str(&self) -> &'static str {
     match self {
       Self::ADC => "ADC",
+      Self::ALR => "ALR",
       Self::AND => "AND",
+      Self::ANC => "ANC",
+      Self::ARR => "ARR",
       Self::ASL => "ASL",
       Self::BCC => "BCC",
       Self::BCS => "BCS",
@@ -117,6 +132,7 @@ impl Mnemonic {
       Self::CMP => "CMP",
       Self::CPX => "CPX",
       Self::CPY => "CPY",
+      Self::DCP => "DCP",
       Self::DEC => "DEC",
       Self::DEX => "DEX",
       Self::DEY => "DEY",
@@ -124,8 +140,10 @@ impl Mnemonic {
       Self::INC => "INC",
       Self::INX => "INX",
       Self::INY => "INY",
+      Self::ISB => "ISB",
       Self::JMP => "JMP",
       Self::JSR => "JSR",
+      Self::LAX => "LAX",
       Self::LDA => "LDA",
       Self::LDX => "LDX",
       Self::LDY => "LDY",
@@ -140,14 +158,20 @@ impl Mnemonic {
       Self::PLP => "PLP",
       Self::_PLX => "PLX",
       Self::_PLY => "PLY",
+      Self::RLA => "RLA",
       Self::ROL => "ROL",
       Self::ROR => "ROR",
+      Self::RRA => "RRA",
       Self::RTI => "RTI",
       Self::RTS => "RTS",
+      Self::SAX => "SAX",
       Self::SBC => "SBC",
+      Self::SBX => "SBX",
       Self::SEC => "SEC",
       Self::SED => "SED",
       Self::SEI => "SEI",
+      Self::SLO => "SLO",
+      Self::SRE => "SRE",
       Self::STA => "STA",
       Self::STX => "STX",
       Self::STY => "STY",
@@ -258,7 +282,19 @@ macro_rules! static_dispatch_addressing_mode {
 impl AddressingMode {
   static_dispatch_addressing_mode!(get_size() -> u8);
   static_dispatch_addressing_mode!(get_operand(bytes: &[u8]) -> String);
+  static_dispatch_addressing_mode!(get_operand_named(name: &str) -> String);
   static_dispatch_addressing_mode!(get_name() -> &'static str);
+
+  // Indexed modes take an extra cycle to fix up the high byte of the effective
+  // address when adding the index carries into the next page
+  fn crosses_page(&self, registers: &Registers, memory: &dyn MemoryBus) -> bool {
+    match self {
+      AddressingMode::AbsoluteX => UseAbsoluteWith::<'X'>::crosses_page(registers, memory),
+      AddressingMode::AbsoluteY => UseAbsoluteWith::<'Y'>::crosses_page(registers, memory),
+      AddressingMode::IndirectIndexedY => UseIndirectIndexedY::crosses_page(registers, memory),
+      _ => false,
+    }
+  }
 }
 
 type Instr = fn(registers: &mut Registers, memory: &mut dyn MemoryBus);
@@ -416,6 +452,63 @@ fn test_sbc_d0_minus_70() {
   assert!(!status.has::<'Z'>());
 }
 
+// Compare with accumulator, for DCP. Like CMP, V (overflow flag) not affected
+struct Cmp;
+impl AccOp for Cmp {
+  fn call(accumulator: &mut u8, status: &mut Status, value: u8) {
+    let (result, carry, _overflow) = alu::sub_with_carry(*accumulator, value, true);
+    status.set::<'C'>(carry);
+    status.set_nz_from_u8(result);
+  }
+}
+
+struct Anc;
+impl AccOp for Anc {
+  fn call(accumulator: &mut u8, status: &mut Status, value: u8) {
+    let result = alu::and(*accumulator, value);
+    status.set_nz_from_u8(result);
+    status.set::<'C'>(status.has::<'N'>());
+    *accumulator = result;
+  }
+}
+
+struct Alr;
+impl AccOp for Alr {
+  fn call(accumulator: &mut u8, status: &mut Status, value: u8) {
+    let (result, carry) = alu::lsr(alu::and(*accumulator, value));
+    status.set::<'C'>(carry);
+    status.set_nz_from_u8(result);
+    *accumulator = result;
+  }
+}
+
+struct Arr;
+impl AccOp for Arr {
+  fn call(accumulator: &mut u8, status: &mut Status, value: u8) {
+    assert!(!status.has::<'D'>()); // not implemented
+    let (result, _) = alu::ror(alu::and(*accumulator, value), status.has::<'C'>());
+    // C is bit 6 of the result, V is bit 6 exclusive-or bit 5
+    let bit6 = result & 0b0100_0000 != 0;
+    let bit5 = result & 0b0010_0000 != 0;
+    status.set::<'C'>(bit6);
+    status.set::<'V'>(bit6 != bit5);
+    status.set_nz_from_u8(result);
+    *accumulator = result;
+  }
+}
+
+#[test]
+fn test_arr() {
+  let mut accumulator = 0xFF;
+  let mut status = Status::new();
+  status.set_flag::<'C', true>();
+  Arr::call(&mut accumulator, &mut status, 0xC0); // (0xC0 >> 1) | 0x80
+  assert_eq!(accumulator, 0xE0);
+  assert!(status.has::<'C'>());
+  assert!(!status.has::<'V'>());
+  assert!(status.has::<'N'>());
+}
+
 fn by_acc<AO: AccOp, AM: UseMode + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
   let value = AM::get_value(registers, memory);
   registers.pc.inc_by(AM::get_size());
@@ -479,6 +572,15 @@ fn by_ref<RO: RefOp, AM: UseMode + UseReference + UseValue>(registers: &mut Regi
   registers.pc.inc_by(AM::get_size());
 }
 
+// read-modify-write memory, then combine result with accumulator
+fn by_ref_acc<RO: RefOp, AO: AccOp, AM: UseMode + UseReference + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
+  let mut value = AM::get_value(registers, memory);
+  RO::call(&mut value, &mut registers.p);
+  AM::write(registers, memory, value);
+  AO::call(&mut registers.a, &mut registers.p, value);
+  registers.pc.inc_by(AM::get_size());
+}
+
 fn compare<const REGISTER: char, AM: UseMode + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
   let lhs = match REGISTER {
     'a'|'A' => registers.a,
@@ -557,6 +659,17 @@ fn test_cpx() {
   assert!(regs.p.has::<'V'>()); // not affected
 }
 
+// X = (A & X) - value, setting flags like CMP (no borrow in, V not affected)
+fn sbx<AM: UseMode + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
+  let value = AM::get_value(registers, memory);
+  let (result, carry, _overflow) =
+    alu::sub_with_carry(registers.a & registers.x, value, true);
+  registers.p.set::<'C'>(carry);
+  registers.p.set_nz_from_u8(result);
+  registers.x = result;
+  registers.pc.inc_by(AM::get_size());
+}
+
 fn bit<AM: UseMode + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
   let value = AM::get_value(registers, memory);
   let status = alu::bit(registers.a, value, registers.p);
@@ -651,6 +764,14 @@ fn load<const REGISTER: char, AM: UseMode + UseValue>(registers: &mut Registers,
   registers.pc.inc_by(AM::get_size());
 }
 
+fn load_ax<AM: UseMode + UseValue>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
+  let value = AM::get_value(registers, memory);
+  registers.p.set_nz_from_u8(value);
+  registers.a = value;
+  registers.x = value;
+  registers.pc.inc_by(AM::get_size());
+}
+
 fn transfer<const FROM: char, const TO: char, AM: UseMode>(registers: &mut Registers, _: &mut dyn MemoryBus) {
   const fn value<const FROM: char>(registers: &Registers) -> u8 {
     match FROM {
@@ -730,6 +851,14 @@ fn store<const REGISTER: char, AM: UseMode + UseAddress>(registers: &mut Registe
   registers.pc.inc_by(AM::get_size());
 }
 
+// store A & X, flags not affected
+fn store_ax<AM: UseMode + UseAddress>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
+  let value = registers.a & registers.x;
+  let address = AM::get_address(registers, memory);
+  memory.write(address, value);
+  registers.pc.inc_by(AM::get_size());
+}
+
 fn undefined<AM: UseMode>(registers: &mut Registers, memory: &mut dyn MemoryBus) {
   // skip back one byte before operand
   let mut address = registers.pc;
@@ -762,24 +891,65 @@ fn handle_brk(registers: &mut Registers, memory: &mut dyn MemoryBus) {
   handle_interrupt::<0xFFFE>(registers, memory);
 }
 
+// Branch opcodes are encoded as `ffv1_0000`, where `ff` selects the flag to test
+// (N, V, C or Z) and `v` the value it must have for the branch to be taken
+const fn is_branch_taken(opcode: u8, status: Status) -> bool {
+  let set = match opcode >> 6 {
+    0b00 => status.has::<'N'>(),
+    0b01 => status.has::<'V'>(),
+    0b10 => status.has::<'C'>(),
+    _    => status.has::<'Z'>(),
+  };
+  set == (opcode & 0b0010_0000 != 0)
+}
+
 pub struct Instruction {
   pub mnemonic: Mnemonic,
   pub addressing_mode: AddressingMode,
   pub instr: Instr,
+  pub cycles: u8,         // base cycle count
+  pub page_penalty: bool, // +1 cycle if indexing crosses a page boundary
 }
 
 impl Instruction {
-  pub const fn new(mnemonic: Mnemonic, addressing_mode: AddressingMode, instr: Instr) -> Instruction {
-    Instruction { mnemonic, addressing_mode, instr }
+  pub const fn new(mnemonic: Mnemonic, addressing_mode: AddressingMode, instr: Instr, cycles: u8) -> Instruction {
+    Instruction { mnemonic, addressing_mode, instr, cycles, page_penalty: false }
+  }
+
+  pub const fn page_crossing_penalty(mut self) -> Instruction {
+    self.page_penalty = true;
+    self
   }
 
   pub const fn lookup(byte: u8) -> &'static Instruction {
     &INSTRUCTIONS[byte as usize]
   }
 
-  pub fn execute(&self, registers: &mut Registers, memory: &mut dyn MemoryBus) {
+  // Execute instruction at PC and return the number of (2MHz) clock cycles it
+  // took, including penalties for page crossing and taken branches
+  pub fn execute(&self, registers: &mut Registers, memory: &mut dyn MemoryBus) -> u8 {
+    let opcode = memory.read(registers.pc);
     registers.pc = registers.pc.next();
+    let mut cycles = self.cycles;
+    if self.page_penalty && self.addressing_mode.crosses_page(registers, memory) {
+      cycles += 1;
+    }
+
+    if let AddressingMode::Relative = self.addressing_mode {
+      let mut next = registers.pc;
+      next.inc_by(self.addressing_mode.get_size());
+      if is_branch_taken(opcode, registers.p) {
+        cycles += 1;
+        (self.instr)(registers, memory);
+        if registers.pc.hi_u8() != next.hi_u8() {
+          cycles += 1;
+        }
+        return cycles;
+      }
+    }
+
     (self.instr)(registers, memory);
+    cycles
   }
 
   pub fn is_valid(&self) -> bool {
@@ -790,264 +960,264 @@ impl Instruction {
 use Mnemonic::*;
 const UND: Instruction = Instruction::new(Mnemonic::UND,
                                           AddressingMode::Implied,
-                                          undefined::<UseImplied>);
+                                          undefined::<UseImplied>, 2);
 const INSTRUCTIONS: [Instruction; 256] = [
-  Instruction::new(BRK, AddressingMode::Implied, handle_brk), //0x00
-  Instruction::new(ORA, AddressingMode::IndexedIndirectX, by_acc::<Ora, UseIndexedIndirectX>),
-  UND,
-  UND,
-  UND, // 0x04 TSB, ZeroPage
-  Instruction::new(ORA, AddressingMode::ZeroPage, by_acc::<Ora, UseZeroPage>),
-  Instruction::new(ASL, AddressingMode::ZeroPage, by_ref::<ShiftLeft<false>, UseZeroPage>),
-  UND,
-  Instruction::new(PHP, AddressingMode::Implied, push_register::<'p', UseImplied>), // 0x08
-  Instruction::new(ORA, AddressingMode::Immediate, by_acc::<Ora, UseImmediate>),
-  Instruction::new(ASL, AddressingMode::Accumulator, by_ref::<ShiftLeft<false>, UseAccumulator>),
-  UND,
-  UND, // 0x0c TSB, absolute
-  Instruction::new(ORA, AddressingMode::Absolute, by_acc::<Ora, UseAbsolute>),
-  Instruction::new(ASL, AddressingMode::Absolute, by_ref::<ShiftLeft<false>, UseAbsolute>),
-  UND,
-  Instruction::new(BPL, AddressingMode::Relative, branch::<'n', false, UseRelative>), // 0x10
-  Instruction::new(ORA, AddressingMode::IndirectIndexedY, by_acc::<Ora, UseIndirectIndexedY>),
-  UND,
-  UND,
-  UND, // 0x14 TRB, zero page
-  Instruction::new(ORA, AddressingMode::ZeroPageX, by_acc::<Ora, UseZeroPageWith<'X'>>),
-  Instruction::new(ASL, AddressingMode::ZeroPageX, by_ref::<ShiftLeft<false>, UseZeroPageWith::<'X'>>),
-  UND,
-  Instruction::new(CLC, AddressingMode::Implied, set_flag::<'C', false, UseImplied>), // 0x18
-  Instruction::new(ORA, AddressingMode::AbsoluteY, by_acc::<Ora, UseAbsoluteWith<'Y'>>),
-  UND, // 0x1a INC, Accumulator
-  UND,
-  UND, // 0x1c TRB, absolute
-  Instruction::new(ORA, AddressingMode::AbsoluteX, by_acc::<Ora, UseAbsoluteWith<'X'>>),
-  Instruction::new(ASL, AddressingMode::AbsoluteX, by_ref::<ShiftLeft<false>, UseAbsoluteWith::<'X'>>),
+  Instruction::new(BRK, AddressingMode::Implied, handle_brk, 7), //0x00
+  Instruction::new(ORA, AddressingMode::IndexedIndirectX, by_acc::<Ora, UseIndexedIndirectX>, 6),
   UND,
-  Instruction::new(JSR, AddressingMode::Absolute, jump_sub::<UseAbsolute>), // 0x20
-  Instruction::new(AND, AddressingMode::IndexedIndirectX, by_acc::<And, UseIndexedIndirectX>),
+  Instruction::new(SLO, AddressingMode::IndexedIndirectX, by_ref_acc::<ShiftLeft<false>, Ora, UseIndexedIndirectX>, 8), // 0x03 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPage, no_operation::<UseZeroPage>, 3), // 0x04 (undocumented)
+  Instruction::new(ORA, AddressingMode::ZeroPage, by_acc::<Ora, UseZeroPage>, 3),
+  Instruction::new(ASL, AddressingMode::ZeroPage, by_ref::<ShiftLeft<false>, UseZeroPage>, 5),
+  Instruction::new(SLO, AddressingMode::ZeroPage, by_ref_acc::<ShiftLeft<false>, Ora, UseZeroPage>, 5), // 0x07 (undocumented)
+  Instruction::new(PHP, AddressingMode::Implied, push_register::<'p', UseImplied>, 3), // 0x08
+  Instruction::new(ORA, AddressingMode::Immediate, by_acc::<Ora, UseImmediate>, 2),
+  Instruction::new(ASL, AddressingMode::Accumulator, by_ref::<ShiftLeft<false>, UseAccumulator>, 2),
+  Instruction::new(ANC, AddressingMode::Immediate, by_acc::<Anc, UseImmediate>, 2), // 0x0b (undocumented)
+  Instruction::new(NOP, AddressingMode::Absolute, no_operation::<UseAbsolute>, 4), // 0x0c (undocumented)
+  Instruction::new(ORA, AddressingMode::Absolute, by_acc::<Ora, UseAbsolute>, 4),
+  Instruction::new(ASL, AddressingMode::Absolute, by_ref::<ShiftLeft<false>, UseAbsolute>, 6),
+  Instruction::new(SLO, AddressingMode::Absolute, by_ref_acc::<ShiftLeft<false>, Ora, UseAbsolute>, 6), // 0x0f (undocumented)
+  Instruction::new(BPL, AddressingMode::Relative, branch::<'n', false, UseRelative>, 2), // 0x10
+  Instruction::new(ORA, AddressingMode::IndirectIndexedY, by_acc::<Ora, UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND,
+  Instruction::new(SLO, AddressingMode::IndirectIndexedY, by_ref_acc::<ShiftLeft<false>, Ora, UseIndirectIndexedY>, 8), // 0x13 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0x14 (undocumented)
+  Instruction::new(ORA, AddressingMode::ZeroPageX, by_acc::<Ora, UseZeroPageWith<'X'>>, 4),
+  Instruction::new(ASL, AddressingMode::ZeroPageX, by_ref::<ShiftLeft<false>, UseZeroPageWith::<'X'>>, 6),
+  Instruction::new(SLO, AddressingMode::ZeroPageX, by_ref_acc::<ShiftLeft<false>, Ora, UseZeroPageWith<'X'>>, 6), // 0x17 (undocumented)
+  Instruction::new(CLC, AddressingMode::Implied, set_flag::<'C', false, UseImplied>, 2), // 0x18
+  Instruction::new(ORA, AddressingMode::AbsoluteY, by_acc::<Ora, UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0x1a (undocumented)
+  Instruction::new(SLO, AddressingMode::AbsoluteY, by_ref_acc::<ShiftLeft<false>, Ora, UseAbsoluteWith<'Y'>>, 7), // 0x1b (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0x1c (undocumented)
+  Instruction::new(ORA, AddressingMode::AbsoluteX, by_acc::<Ora, UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(ASL, AddressingMode::AbsoluteX, by_ref::<ShiftLeft<false>, UseAbsoluteWith::<'X'>>, 7),
+  Instruction::new(SLO, AddressingMode::AbsoluteX, by_ref_acc::<ShiftLeft<false>, Ora, UseAbsoluteWith<'X'>>, 7), // 0x1f (undocumented)
+  Instruction::new(JSR, AddressingMode::Absolute, jump_sub::<UseAbsolute>, 6), // 0x20
+  Instruction::new(AND, AddressingMode::IndexedIndirectX, by_acc::<And, UseIndexedIndirectX>, 6),
   UND,
-  Instruction::new(BIT, AddressingMode::ZeroPage, bit::<UseZeroPage>),
-  Instruction::new(AND, AddressingMode::ZeroPage, by_acc::<And, UseZeroPage>),
-  Instruction::new(ROL, AddressingMode::ZeroPage, by_ref::<ShiftLeft<true>, UseZeroPage>),
+  Instruction::new(RLA, AddressingMode::IndexedIndirectX, by_ref_acc::<ShiftLeft<true>, And, UseIndexedIndirectX>, 8), // 0x23 (undocumented)
+  Instruction::new(BIT, AddressingMode::ZeroPage, bit::<UseZeroPage>, 3),
+  Instruction::new(AND, AddressingMode::ZeroPage, by_acc::<And, UseZeroPage>, 3),
+  Instruction::new(ROL, AddressingMode::ZeroPage, by_ref::<ShiftLeft<true>, UseZeroPage>, 5),
+  Instruction::new(RLA, AddressingMode::ZeroPage, by_ref_acc::<ShiftLeft<true>, And, UseZeroPage>, 5), // 0x27 (undocumented)
+  Instruction::new(PLP, AddressingMode::Implied, pull_status::<UseImplied>, 4), // 0x28
+  Instruction::new(AND, AddressingMode::Immediate, by_acc::<And, UseImmediate>, 2),
+  Instruction::new(ROL, AddressingMode::Accumulator, by_ref::<ShiftLeft<true>, UseAccumulator>, 2),
+  Instruction::new(ANC, AddressingMode::Immediate, by_acc::<Anc, UseImmediate>, 2), // 0x2b (undocumented)
+  Instruction::new(BIT, AddressingMode::Absolute, bit::<UseAbsolute>, 4),
+  Instruction::new(AND, AddressingMode::Absolute, by_acc::<And, UseAbsolute>, 4),
+  Instruction::new(ROL, AddressingMode::Absolute, by_ref::<ShiftLeft<true>, UseAbsolute>, 6),
+  Instruction::new(RLA, AddressingMode::Absolute, by_ref_acc::<ShiftLeft<true>, And, UseAbsolute>, 6), // 0x2f (undocumented)
+  Instruction::new(BMI, AddressingMode::Relative, branch::<'N', true, UseRelative>, 2), // 0x30
+  Instruction::new(AND, AddressingMode::IndirectIndexedY, by_acc::<And, UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND,
-  Instruction::new(PLP, AddressingMode::Implied, pull_status::<UseImplied>), // 0x28
-  Instruction::new(AND, AddressingMode::Immediate, by_acc::<And, UseImmediate>),
-  Instruction::new(ROL, AddressingMode::Accumulator, by_ref::<ShiftLeft<true>, UseAccumulator>),
-  UND,
-  Instruction::new(BIT, AddressingMode::Absolute, bit::<UseAbsolute>),
-  Instruction::new(AND, AddressingMode::Absolute, by_acc::<And, UseAbsolute>),
-  Instruction::new(ROL, AddressingMode::Absolute, by_ref::<ShiftLeft<true>, UseAbsolute>),
-  UND,
-  Instruction::new(BMI, AddressingMode::Relative, branch::<'N', true, UseRelative>), // 0x30
-  Instruction::new(AND, AddressingMode::IndirectIndexedY, by_acc::<And, UseIndirectIndexedY>),
-  UND,
-  UND,
-  UND,
-  Instruction::new(AND, AddressingMode::ZeroPageX, by_acc::<And, UseZeroPageWith<'X'>>),
-  Instruction::new(ROL, AddressingMode::ZeroPageX, by_ref::<ShiftLeft<true>, UseZeroPageWith<'X'>>),
-  UND,
-  Instruction::new(SEC, AddressingMode::Implied, set_flag::<'C', true, UseImplied>), // 0x38
-  Instruction::new(AND, AddressingMode::AbsoluteY, by_acc::<And, UseAbsoluteWith<'Y'>>),
-  UND, // DEC accumulator // 0x3a
-  UND,
-  UND,
-  Instruction::new(AND, AddressingMode::AbsoluteX, by_acc::<And, UseAbsoluteWith<'X'>>),
-  Instruction::new(ROL, AddressingMode::AbsoluteX, by_ref::<ShiftLeft<true>, UseAbsoluteWith<'X'>>), //0x3e
-  UND,
-  Instruction::new(RTI, AddressingMode::Implied, return_interrupt::<UseImplied>),
-  Instruction::new(EOR, AddressingMode::IndexedIndirectX, by_acc::<Eor, UseIndexedIndirectX>),
+  Instruction::new(RLA, AddressingMode::IndirectIndexedY, by_ref_acc::<ShiftLeft<true>, And, UseIndirectIndexedY>, 8), // 0x33 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0x34 (undocumented)
+  Instruction::new(AND, AddressingMode::ZeroPageX, by_acc::<And, UseZeroPageWith<'X'>>, 4),
+  Instruction::new(ROL, AddressingMode::ZeroPageX, by_ref::<ShiftLeft<true>, UseZeroPageWith<'X'>>, 6),
+  Instruction::new(RLA, AddressingMode::ZeroPageX, by_ref_acc::<ShiftLeft<true>, And, UseZeroPageWith<'X'>>, 6), // 0x37 (undocumented)
+  Instruction::new(SEC, AddressingMode::Implied, set_flag::<'C', true, UseImplied>, 2), // 0x38
+  Instruction::new(AND, AddressingMode::AbsoluteY, by_acc::<And, UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0x3a (undocumented)
+  Instruction::new(RLA, AddressingMode::AbsoluteY, by_ref_acc::<ShiftLeft<true>, And, UseAbsoluteWith<'Y'>>, 7), // 0x3b (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0x3c (undocumented)
+  Instruction::new(AND, AddressingMode::AbsoluteX, by_acc::<And, UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(ROL, AddressingMode::AbsoluteX, by_ref::<ShiftLeft<true>, UseAbsoluteWith<'X'>>, 7), //0x3e
+  Instruction::new(RLA, AddressingMode::AbsoluteX, by_ref_acc::<ShiftLeft<true>, And, UseAbsoluteWith<'X'>>, 7), // 0x3f (undocumented)
+  Instruction::new(RTI, AddressingMode::Implied, return_interrupt::<UseImplied>, 6),
+  Instruction::new(EOR, AddressingMode::IndexedIndirectX, by_acc::<Eor, UseIndexedIndirectX>, 6),
   UND, // 0x42
-  UND, // 0x43
-  UND, // 0x44
-  Instruction::new(EOR, AddressingMode::ZeroPage, by_acc::<Eor, UseZeroPage>),
-  Instruction::new(LSR, AddressingMode::ZeroPage, by_ref::<ShiftRight<false>, UseZeroPage>),
-  UND, // 0x47
-  Instruction::new(PHA, AddressingMode::Implied, push_register::<'A', UseImplied>),
-  Instruction::new(EOR, AddressingMode::Immediate, by_acc::<Eor, UseImmediate>),
-  Instruction::new(LSR, AddressingMode::Accumulator, by_ref::<ShiftRight<false>, UseAccumulator>),
-  UND, // 0x4b
-  Instruction::new(JMP, AddressingMode::Absolute, jump::<UseAbsolute>),
-  Instruction::new(EOR, AddressingMode::Absolute, by_acc::<Eor, UseAbsolute>),
-  Instruction::new(LSR, AddressingMode::Absolute, by_ref::<ShiftRight<false>, UseAbsolute>),
-  UND, // 0x4f
-  Instruction::new(BVC, AddressingMode::Relative, branch::<'V', false, UseRelative>),
-  Instruction::new(EOR, AddressingMode::IndirectIndexedY, by_acc::<Eor, UseIndirectIndexedY>),
+  Instruction::new(SRE, AddressingMode::IndexedIndirectX, by_ref_acc::<ShiftRight<false>, Eor, UseIndexedIndirectX>, 8), // 0x43 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPage, no_operation::<UseZeroPage>, 3), // 0x44 (undocumented)
+  Instruction::new(EOR, AddressingMode::ZeroPage, by_acc::<Eor, UseZeroPage>, 3),
+  Instruction::new(LSR, AddressingMode::ZeroPage, by_ref::<ShiftRight<false>, UseZeroPage>, 5),
+  Instruction::new(SRE, AddressingMode::ZeroPage, by_ref_acc::<ShiftRight<false>, Eor, UseZeroPage>, 5), // 0x47 (undocumented)
+  Instruction::new(PHA, AddressingMode::Implied, push_register::<'A', UseImplied>, 3),
+  Instruction::new(EOR, AddressingMode::Immediate, by_acc::<Eor, UseImmediate>, 2),
+  Instruction::new(LSR, AddressingMode::Accumulator, by_ref::<ShiftRight<false>, UseAccumulator>, 2),
+  Instruction::new(ALR, AddressingMode::Immediate, by_acc::<Alr, UseImmediate>, 2), // 0x4b (undocumented)
+  Instruction::new(JMP, AddressingMode::Absolute, jump::<UseAbsolute>, 3),
+  Instruction::new(EOR, AddressingMode::Absolute, by_acc::<Eor, UseAbsolute>, 4),
+  Instruction::new(LSR, AddressingMode::Absolute, by_ref::<ShiftRight<false>, UseAbsolute>, 6),
+  Instruction::new(SRE, AddressingMode::Absolute, by_ref_acc::<ShiftRight<false>, Eor, UseAbsolute>, 6), // 0x4f (undocumented)
+  Instruction::new(BVC, AddressingMode::Relative, branch::<'V', false, UseRelative>, 2),
+  Instruction::new(EOR, AddressingMode::IndirectIndexedY, by_acc::<Eor, UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND, // 0x52
-  UND, // 0x53
-  UND, // 0x54
-  Instruction::new(EOR, AddressingMode::ZeroPageX, by_acc::<Eor, UseZeroPageWith<'X'>>),
-  Instruction::new(LSR, AddressingMode::ZeroPageX, by_ref::<ShiftRight<false>, UseZeroPageWith<'X'>>),
-  UND, // 0x57
-  Instruction::new(CLI, AddressingMode::Implied, set_flag::<'I', false, UseImplied>),
-  Instruction::new(EOR, AddressingMode::AbsoluteY, by_acc::<Eor, UseAbsoluteWith<'Y'>>),
-  UND, // 0x5a
-  UND, // 0x5b
-  UND, // 0x5c
-  Instruction::new(EOR, AddressingMode::AbsoluteX, by_acc::<Eor, UseAbsoluteWith<'X'>>),
-  Instruction::new(LSR, AddressingMode::AbsoluteX, by_ref::<ShiftRight<false>, UseAbsoluteWith<'X'>>),
-  UND, // 0x5f
-  Instruction::new(RTS, AddressingMode::Implied, return_sub::<UseImplied>),
-  Instruction::new(ADC, AddressingMode::IndexedIndirectX, by_acc::<Adc, UseIndexedIndirectX>),
+  Instruction::new(SRE, AddressingMode::IndirectIndexedY, by_ref_acc::<ShiftRight<false>, Eor, UseIndirectIndexedY>, 8), // 0x53 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0x54 (undocumented)
+  Instruction::new(EOR, AddressingMode::ZeroPageX, by_acc::<Eor, UseZeroPageWith<'X'>>, 4),
+  Instruction::new(LSR, AddressingMode::ZeroPageX, by_ref::<ShiftRight<false>, UseZeroPageWith<'X'>>, 6),
+  Instruction::new(SRE, AddressingMode::ZeroPageX, by_ref_acc::<ShiftRight<false>, Eor, UseZeroPageWith<'X'>>, 6), // 0x57 (undocumented)
+  Instruction::new(CLI, AddressingMode::Implied, set_flag::<'I', false, UseImplied>, 2),
+  Instruction::new(EOR, AddressingMode::AbsoluteY, by_acc::<Eor, UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0x5a (undocumented)
+  Instruction::new(SRE, AddressingMode::AbsoluteY, by_ref_acc::<ShiftRight<false>, Eor, UseAbsoluteWith<'Y'>>, 7), // 0x5b (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0x5c (undocumented)
+  Instruction::new(EOR, AddressingMode::AbsoluteX, by_acc::<Eor, UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(LSR, AddressingMode::AbsoluteX, by_ref::<ShiftRight<false>, UseAbsoluteWith<'X'>>, 7),
+  Instruction::new(SRE, AddressingMode::AbsoluteX, by_ref_acc::<ShiftRight<false>, Eor, UseAbsoluteWith<'X'>>, 7), // 0x5f (undocumented)
+  Instruction::new(RTS, AddressingMode::Implied, return_sub::<UseImplied>, 6),
+  Instruction::new(ADC, AddressingMode::IndexedIndirectX, by_acc::<Adc, UseIndexedIndirectX>, 6),
   UND, // 0x62
-  UND, // 0x63
-  UND, // 0x64 STZ, ZeroPage
-  Instruction::new(ADC, AddressingMode::ZeroPage, by_acc::<Adc, UseZeroPage>),
-  Instruction::new(ROR, AddressingMode::ZeroPage, by_ref::<ShiftRight<true>, UseZeroPage>),
-  UND, // 0x67
-  Instruction::new(PLA, AddressingMode::Implied, pull_accumulator::<UseImplied>),
-  Instruction::new(ADC, AddressingMode::Immediate, by_acc::<Adc, UseImmediate>),
-  Instruction::new(ROR, AddressingMode::Accumulator, by_ref::<ShiftRight<true>, UseAccumulator>),
-  UND, // 0x6b
-  Instruction::new(JMP, AddressingMode::Indirect, jump::<UseIndirect>),
-  Instruction::new(ADC, AddressingMode::Absolute, by_acc::<Adc, UseAbsolute>),
-  Instruction::new(ROR, AddressingMode::Absolute, by_ref::<ShiftRight<true>, UseAbsolute>),
-  UND, // 0x6f
-  Instruction::new(BVS, AddressingMode::Relative, branch::<'V', true, UseRelative>),
-  Instruction::new(ADC, AddressingMode::IndirectIndexedY, by_acc::<Adc, UseIndirectIndexedY>),
+  Instruction::new(RRA, AddressingMode::IndexedIndirectX, by_ref_acc::<ShiftRight<true>, Adc, UseIndexedIndirectX>, 8), // 0x63 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPage, no_operation::<UseZeroPage>, 3), // 0x64 (undocumented)
+  Instruction::new(ADC, AddressingMode::ZeroPage, by_acc::<Adc, UseZeroPage>, 3),
+  Instruction::new(ROR, AddressingMode::ZeroPage, by_ref::<ShiftRight<true>, UseZeroPage>, 5),
+  Instruction::new(RRA, AddressingMode::ZeroPage, by_ref_acc::<ShiftRight<true>, Adc, UseZeroPage>, 5), // 0x67 (undocumented)
+  Instruction::new(PLA, AddressingMode::Implied, pull_accumulator::<UseImplied>, 4),
+  Instruction::new(ADC, AddressingMode::Immediate, by_acc::<Adc, UseImmediate>, 2),
+  Instruction::new(ROR, AddressingMode::Accumulator, by_ref::<ShiftRight<true>, UseAccumulator>, 2),
+  Instruction::new(ARR, AddressingMode::Immediate, by_acc::<Arr, UseImmediate>, 2), // 0x6b (undocumented)
+  Instruction::new(JMP, AddressingMode::Indirect, jump::<UseIndirect>, 5),
+  Instruction::new(ADC, AddressingMode::Absolute, by_acc::<Adc, UseAbsolute>, 4),
+  Instruction::new(ROR, AddressingMode::Absolute, by_ref::<ShiftRight<true>, UseAbsolute>, 6),
+  Instruction::new(RRA, AddressingMode::Absolute, by_ref_acc::<ShiftRight<true>, Adc, UseAbsolute>, 6), // 0x6f (undocumented)
+  Instruction::new(BVS, AddressingMode::Relative, branch::<'V', true, UseRelative>, 2),
+  Instruction::new(ADC, AddressingMode::IndirectIndexedY, by_acc::<Adc, UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND, // 0x72
-  UND, // 0x73
-  UND, // 0x74 STZ, ZeroPageX
-  Instruction::new(ADC, AddressingMode::ZeroPageX, by_acc::<Adc, UseZeroPageWith<'X'>>),
-  Instruction::new(ROR, AddressingMode::ZeroPageX, by_ref::<ShiftRight<true>, UseZeroPageWith<'X'>>),
-  UND, // 0x77
-  Instruction::new(SEI, AddressingMode::Implied, set_flag::<'I', true, UseImplied>),
-  Instruction::new(ADC, AddressingMode::AbsoluteY, by_acc::<Adc, UseAbsoluteWith<'Y'>>),
-  UND, // 0x7a (PLY, implied)
-  UND, // 0x7b
-  UND, // 0x7c
-  Instruction::new(ADC, AddressingMode::AbsoluteX, by_acc::<Adc, UseAbsoluteWith<'X'>>),
-  Instruction::new(ROR, AddressingMode::AbsoluteX, by_ref::<ShiftRight<true>, UseAbsoluteWith<'X'>>),
-  UND, // 0x7f
-  UND, // 0x80
-  Instruction::new(STA, AddressingMode::IndexedIndirectX, store::<'a', UseIndexedIndirectX>),
-  UND, // 0x82
-  UND, // 0x83
-  Instruction::new(STY, AddressingMode::ZeroPage, store::<'y', UseZeroPage>),
-  Instruction::new(STA, AddressingMode::ZeroPage, store::<'a', UseZeroPage>),
-  Instruction::new(STX, AddressingMode::ZeroPage, store::<'x', UseZeroPage>),
-  UND, // 0x87
-  Instruction::new(DEY, AddressingMode::Implied, dec_register::<'Y', UseImplied>),
-  UND, // 0x89
-  Instruction::new(TXA, AddressingMode::Implied, transfer::<'X', 'A', UseImplied>),
+  Instruction::new(RRA, AddressingMode::IndirectIndexedY, by_ref_acc::<ShiftRight<true>, Adc, UseIndirectIndexedY>, 8), // 0x73 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0x74 (undocumented)
+  Instruction::new(ADC, AddressingMode::ZeroPageX, by_acc::<Adc, UseZeroPageWith<'X'>>, 4),
+  Instruction::new(ROR, AddressingMode::ZeroPageX, by_ref::<ShiftRight<true>, UseZeroPageWith<'X'>>, 6),
+  Instruction::new(RRA, AddressingMode::ZeroPageX, by_ref_acc::<ShiftRight<true>, Adc, UseZeroPageWith<'X'>>, 6), // 0x77 (undocumented)
+  Instruction::new(SEI, AddressingMode::Implied, set_flag::<'I', true, UseImplied>, 2),
+  Instruction::new(ADC, AddressingMode::AbsoluteY, by_acc::<Adc, UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0x7a (undocumented)
+  Instruction::new(RRA, AddressingMode::AbsoluteY, by_ref_acc::<ShiftRight<true>, Adc, UseAbsoluteWith<'Y'>>, 7), // 0x7b (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0x7c (undocumented)
+  Instruction::new(ADC, AddressingMode::AbsoluteX, by_acc::<Adc, UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(ROR, AddressingMode::AbsoluteX, by_ref::<ShiftRight<true>, UseAbsoluteWith<'X'>>, 7),
+  Instruction::new(RRA, AddressingMode::AbsoluteX, by_ref_acc::<ShiftRight<true>, Adc, UseAbsoluteWith<'X'>>, 7), // 0x7f (undocumented)
+  Instruction::new(NOP, AddressingMode::Immediate, no_operation::<UseImmediate>, 2), // 0x80 (undocumented)
+  Instruction::new(STA, AddressingMode::IndexedIndirectX, store::<'a', UseIndexedIndirectX>, 6),
+  Instruction::new(NOP, AddressingMode::Immediate, no_operation::<UseImmediate>, 2), // 0x82 (undocumented)
+  Instruction::new(SAX, AddressingMode::IndexedIndirectX, store_ax::<UseIndexedIndirectX>, 6), // 0x83 (undocumented)
+  Instruction::new(STY, AddressingMode::ZeroPage, store::<'y', UseZeroPage>, 3),
+  Instruction::new(STA, AddressingMode::ZeroPage, store::<'a', UseZeroPage>, 3),
+  Instruction::new(STX, AddressingMode::ZeroPage, store::<'x', UseZeroPage>, 3),
+  Instruction::new(SAX, AddressingMode::ZeroPage, store_ax::<UseZeroPage>, 3), // 0x87 (undocumented)
+  Instruction::new(DEY, AddressingMode::Implied, dec_register::<'Y', UseImplied>, 2),
+  Instruction::new(NOP, AddressingMode::Immediate, no_operation::<UseImmediate>, 2), // 0x89 (undocumented)
+  Instruction::new(TXA, AddressingMode::Implied, transfer::<'X', 'A', UseImplied>, 2),
   UND, // 0x8b
-  Instruction::new(STY, AddressingMode::Absolute, store::<'y', UseAbsolute>),
-  Instruction::new(STA, AddressingMode::Absolute, store::<'a', UseAbsolute>),
-  Instruction::new(STX, AddressingMode::Absolute, store::<'x', UseAbsolute>),
-  UND, // 0x8f
-  Instruction::new(BCC, AddressingMode::Relative, branch::<'c', false, UseRelative>),
-  Instruction::new(STA, AddressingMode::IndirectIndexedY, store::<'a', UseIndirectIndexedY>),
+  Instruction::new(STY, AddressingMode::Absolute, store::<'y', UseAbsolute>, 4),
+  Instruction::new(STA, AddressingMode::Absolute, store::<'a', UseAbsolute>, 4),
+  Instruction::new(STX, AddressingMode::Absolute, store::<'x', UseAbsolute>, 4),
+  Instruction::new(SAX, AddressingMode::Absolute, store_ax::<UseAbsolute>, 4), // 0x8f (undocumented)
+  Instruction::new(BCC, AddressingMode::Relative, branch::<'c', false, UseRelative>, 2),
+  Instruction::new(STA, AddressingMode::IndirectIndexedY, store::<'a', UseIndirectIndexedY>, 6),
   UND, // 0x92
   UND, // 0x93
-  Instruction::new(STY, AddressingMode::ZeroPageX, store::<'y', UseZeroPageWith<'X'>>),
-  Instruction::new(STA, AddressingMode::ZeroPageX, store::<'a', UseZeroPageWith<'X'>>),
-  Instruction::new(STX, AddressingMode::ZeroPageY, store::<'x', UseZeroPageWith<'Y'>>),
-  UND, // 0x97
-  Instruction::new(TYA, AddressingMode::Implied, transfer::<'Y', 'A', UseImplied>),
-  Instruction::new(STA, AddressingMode::AbsoluteY, store::<'A', UseAbsoluteWith<'Y'>>),
-  Instruction::new(TXS, AddressingMode::Implied, transfer::<'X', 'S', UseImplied>),
+  Instruction::new(STY, AddressingMode::ZeroPageX, store::<'y', UseZeroPageWith<'X'>>, 4),
+  Instruction::new(STA, AddressingMode::ZeroPageX, store::<'a', UseZeroPageWith<'X'>>, 4),
+  Instruction::new(STX, AddressingMode::ZeroPageY, store::<'x', UseZeroPageWith<'Y'>>, 4),
+  Instruction::new(SAX, AddressingMode::ZeroPageY, store_ax::<UseZeroPageWith<'Y'>>, 4), // 0x97 (undocumented)
+  Instruction::new(TYA, AddressingMode::Implied, transfer::<'Y', 'A', UseImplied>, 2),
+  Instruction::new(STA, AddressingMode::AbsoluteY, store::<'A', UseAbsoluteWith<'Y'>>, 5),
+  Instruction::new(TXS, AddressingMode::Implied, transfer::<'X', 'S', UseImplied>, 2),
   UND, // 0x9b
   UND, // 0x9c STZ, Absolute
-  Instruction::new(STA, AddressingMode::AbsoluteX, store::<'a', UseAbsoluteWith<'X'>>),
+  Instruction::new(STA, AddressingMode::AbsoluteX, store::<'a', UseAbsoluteWith<'X'>>, 5),
   UND, // 0x9e STZ, AbsoluteX
   UND, // 0x9f
-  Instruction::new(LDY, AddressingMode::Immediate, load::<'y', UseImmediate>),
-  Instruction::new(LDA, AddressingMode::IndexedIndirectX, load::<'a', UseIndexedIndirectX>),
-  Instruction::new(LDX, AddressingMode::Immediate, load::<'x', UseImmediate>),
-  UND, // 0xa3
-  Instruction::new(LDY, AddressingMode::ZeroPage, load::<'y', UseZeroPage>),
-  Instruction::new(LDA, AddressingMode::ZeroPage, load::<'a', UseZeroPage>),
-  Instruction::new(LDX, AddressingMode::ZeroPage, load::<'x', UseZeroPage>),
-  UND, // 0xa7
-  Instruction::new(TAY, AddressingMode::Implied, transfer::<'A', 'Y', UseImplied>),
-  Instruction::new(LDA, AddressingMode::Immediate, load::<'a', UseImmediate>),
-  Instruction::new(TAX, AddressingMode::Implied, transfer::<'A', 'X', UseImplied>),
+  Instruction::new(LDY, AddressingMode::Immediate, load::<'y', UseImmediate>, 2),
+  Instruction::new(LDA, AddressingMode::IndexedIndirectX, load::<'a', UseIndexedIndirectX>, 6),
+  Instruction::new(LDX, AddressingMode::Immediate, load::<'x', UseImmediate>, 2),
+  Instruction::new(LAX, AddressingMode::IndexedIndirectX, load_ax::<UseIndexedIndirectX>, 6), // 0xa3 (undocumented)
+  Instruction::new(LDY, AddressingMode::ZeroPage, load::<'y', UseZeroPage>, 3),
+  Instruction::new(LDA, AddressingMode::ZeroPage, load::<'a', UseZeroPage>, 3),
+  Instruction::new(LDX, AddressingMode::ZeroPage, load::<'x', UseZeroPage>, 3),
+  Instruction::new(LAX, AddressingMode::ZeroPage, load_ax::<UseZeroPage>, 3), // 0xa7 (undocumented)
+  Instruction::new(TAY, AddressingMode::Implied, transfer::<'A', 'Y', UseImplied>, 2),
+  Instruction::new(LDA, AddressingMode::Immediate, load::<'a', UseImmediate>, 2),
+  Instruction::new(TAX, AddressingMode::Implied, transfer::<'A', 'X', UseImplied>, 2),
   UND, // 0xab
-  Instruction::new(LDY, AddressingMode::Absolute, load::<'Y', UseAbsolute>),
-  Instruction::new(LDA, AddressingMode::Absolute, load::<'A', UseAbsolute>),
-  Instruction::new(LDX, AddressingMode::Absolute, load::<'X', UseAbsolute>),
-  UND, // 0xaf
-  Instruction::new(BCS, AddressingMode::Relative, branch::<'c', true, UseRelative>),
-  Instruction::new(LDA, AddressingMode::IndirectIndexedY, load::<'a', UseIndirectIndexedY>),
+  Instruction::new(LDY, AddressingMode::Absolute, load::<'Y', UseAbsolute>, 4),
+  Instruction::new(LDA, AddressingMode::Absolute, load::<'A', UseAbsolute>, 4),
+  Instruction::new(LDX, AddressingMode::Absolute, load::<'X', UseAbsolute>, 4),
+  Instruction::new(LAX, AddressingMode::Absolute, load_ax::<UseAbsolute>, 4), // 0xaf (undocumented)
+  Instruction::new(BCS, AddressingMode::Relative, branch::<'c', true, UseRelative>, 2),
+  Instruction::new(LDA, AddressingMode::IndirectIndexedY, load::<'a', UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND, // 0xb2
-  UND, // 0xb3
-  Instruction::new(LDY, AddressingMode::ZeroPageX, load::<'Y', UseZeroPageWith<'X'>>),
-  Instruction::new(LDA, AddressingMode::ZeroPageX, load::<'a', UseZeroPageWith<'X'>>),
-  Instruction::new(LDX, AddressingMode::ZeroPageY, load::<'x', UseZeroPageWith<'Y'>>),
-  UND, // 0xb7
-  Instruction::new(CLV, AddressingMode::Implied, set_flag::<'V', false, UseImplied>),
-  Instruction::new(LDA, AddressingMode::AbsoluteY, load::<'a', UseAbsoluteWith<'Y'>>),
-  Instruction::new(TSX, AddressingMode::Implied, transfer::<'S', 'X', UseImplied>),
+  Instruction::new(LAX, AddressingMode::IndirectIndexedY, load_ax::<UseIndirectIndexedY>, 5).page_crossing_penalty(), // 0xb3 (undocumented)
+  Instruction::new(LDY, AddressingMode::ZeroPageX, load::<'Y', UseZeroPageWith<'X'>>, 4),
+  Instruction::new(LDA, AddressingMode::ZeroPageX, load::<'a', UseZeroPageWith<'X'>>, 4),
+  Instruction::new(LDX, AddressingMode::ZeroPageY, load::<'x', UseZeroPageWith<'Y'>>, 4),
+  Instruction::new(LAX, AddressingMode::ZeroPageY, load_ax::<UseZeroPageWith<'Y'>>, 4), // 0xb7 (undocumented)
+  Instruction::new(CLV, AddressingMode::Implied, set_flag::<'V', false, UseImplied>, 2),
+  Instruction::new(LDA, AddressingMode::AbsoluteY, load::<'a', UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(TSX, AddressingMode::Implied, transfer::<'S', 'X', UseImplied>, 2),
   UND, // 0xbb
-  Instruction::new(LDY, AddressingMode::AbsoluteX, load::<'Y', UseAbsoluteWith<'X'>>),
-  Instruction::new(LDA, AddressingMode::AbsoluteX, load::<'a', UseAbsoluteWith<'X'>>),
-  Instruction::new(LDX, AddressingMode::AbsoluteY, load::<'X', UseAbsoluteWith<'Y'>>),
-  UND, // 0xbf
-  Instruction::new(CPY, AddressingMode::Immediate, compare::<'Y', UseImmediate>),
-  Instruction::new(CMP, AddressingMode::IndexedIndirectX, compare::<'A', UseIndexedIndirectX>),
-  UND, // 0xc2
-  UND, // 0xc3
-  Instruction::new(CPY, AddressingMode::ZeroPage, compare::<'Y', UseZeroPage>),
-  Instruction::new(CMP, AddressingMode::ZeroPage, compare::<'A', UseZeroPage>),
-  Instruction::new(DEC, AddressingMode::ZeroPage, by_ref::<Decrement, UseZeroPage>),
-  UND, // 0xc7
-  Instruction::new(INY, AddressingMode::Implied, inc_register::<'Y', UseImplied>),
-  Instruction::new(CMP, AddressingMode::Immediate, compare::<'A', UseImmediate>),
-  Instruction::new(DEX, AddressingMode::Implied, dec_register::<'X', UseImplied>),
-  UND, // 0xcb
-  Instruction::new(CPY, AddressingMode::Absolute, compare::<'Y', UseAbsolute>),
-  Instruction::new(CMP, AddressingMode::Absolute, compare::<'A', UseAbsolute>),
-  Instruction::new(DEC, AddressingMode::Absolute, by_ref::<Decrement, UseAbsolute>),
-  UND, // 0xcf
-  Instruction::new(BNE, AddressingMode::Relative, branch::<'z', false, UseRelative>),
-  Instruction::new(CMP, AddressingMode::IndirectIndexedY, compare::<'A', UseIndirectIndexedY>),
+  Instruction::new(LDY, AddressingMode::AbsoluteX, load::<'Y', UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(LDA, AddressingMode::AbsoluteX, load::<'a', UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(LDX, AddressingMode::AbsoluteY, load::<'X', UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(LAX, AddressingMode::AbsoluteY, load_ax::<UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(), // 0xbf (undocumented)
+  Instruction::new(CPY, AddressingMode::Immediate, compare::<'Y', UseImmediate>, 2),
+  Instruction::new(CMP, AddressingMode::IndexedIndirectX, compare::<'A', UseIndexedIndirectX>, 6),
+  Instruction::new(NOP, AddressingMode::Immediate, no_operation::<UseImmediate>, 2), // 0xc2 (undocumented)
+  Instruction::new(DCP, AddressingMode::IndexedIndirectX, by_ref_acc::<Decrement, Cmp, UseIndexedIndirectX>, 8), // 0xc3 (undocumented)
+  Instruction::new(CPY, AddressingMode::ZeroPage, compare::<'Y', UseZeroPage>, 3),
+  Instruction::new(CMP, AddressingMode::ZeroPage, compare::<'A', UseZeroPage>, 3),
+  Instruction::new(DEC, AddressingMode::ZeroPage, by_ref::<Decrement, UseZeroPage>, 5),
+  Instruction::new(DCP, AddressingMode::ZeroPage, by_ref_acc::<Decrement, Cmp, UseZeroPage>, 5), // 0xc7 (undocumented)
+  Instruction::new(INY, AddressingMode::Implied, inc_register::<'Y', UseImplied>, 2),
+  Instruction::new(CMP, AddressingMode::Immediate, compare::<'A', UseImmediate>, 2),
+  Instruction::new(DEX, AddressingMode::Implied, dec_register::<'X', UseImplied>, 2),
+  Instruction::new(SBX, AddressingMode::Immediate, sbx::<UseImmediate>, 2), // 0xcb (undocumented)
+  Instruction::new(CPY, AddressingMode::Absolute, compare::<'Y', UseAbsolute>, 4),
+  Instruction::new(CMP, AddressingMode::Absolute, compare::<'A', UseAbsolute>, 4),
+  Instruction::new(DEC, AddressingMode::Absolute, by_ref::<Decrement, UseAbsolute>, 6),
+  Instruction::new(DCP, AddressingMode::Absolute, by_ref_acc::<Decrement, Cmp, UseAbsolute>, 6), // 0xcf (undocumented)
+  Instruction::new(BNE, AddressingMode::Relative, branch::<'z', false, UseRelative>, 2),
+  Instruction::new(CMP, AddressingMode::IndirectIndexedY, compare::<'A', UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND, // 0xd2
-  UND, // 0xd3
-  UND, // 0xd4
-  Instruction::new(CMP, AddressingMode::ZeroPageX, compare::<'A', UseZeroPageWith<'X'>>),
-  Instruction::new(DEC, AddressingMode::ZeroPageX, by_ref::<Decrement, UseZeroPageWith<'X'>>),
-  UND, // 0xd7
-  Instruction::new(CLD, AddressingMode::Implied, set_flag::<'D', false, UseImplied>),
-  Instruction::new(CMP, AddressingMode::AbsoluteY, compare::<'A', UseAbsoluteWith<'Y'>>),
-  UND, // 0xda
-  UND, // 0xdb
-  UND, // 0xdc
-  Instruction::new(CMP, AddressingMode::AbsoluteX, compare::<'A', UseAbsoluteWith<'X'>>),
-  Instruction::new(DEC, AddressingMode::AbsoluteX, by_ref::<Decrement, UseAbsoluteWith<'X'>>),
-  UND, // 0xdf
-  Instruction::new(CPX, AddressingMode::Immediate, compare::<'X', UseImmediate>),
-  Instruction::new(SBC, AddressingMode::IndexedIndirectX, by_acc::<Sbc, UseIndexedIndirectX>),
-  UND, // 0xe2
-  UND, // 0xe3
-  Instruction::new(CPX, AddressingMode::ZeroPage, compare::<'X', UseZeroPage>),
-  Instruction::new(SBC, AddressingMode::ZeroPage, by_acc::<Sbc, UseZeroPage>),
-  Instruction::new(INC, AddressingMode::ZeroPage, by_ref::<Increment, UseZeroPage>),
-  UND, // 0xe7
-  Instruction::new(INX, AddressingMode::Implied, inc_register::<'X', UseImplied>),
-  Instruction::new(SBC, AddressingMode::Immediate, by_acc::<Sbc, UseImmediate>),
-  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>),
-  UND, // 0xeb
-  Instruction::new(CPX, AddressingMode::Absolute, compare::<'X', UseAbsolute>),
-  Instruction::new(SBC, AddressingMode::Absolute, by_acc::<Sbc, UseAbsolute>),
-  Instruction::new(INC, AddressingMode::Absolute, by_ref::<Increment, UseAbsolute>),
-  UND, // 0xef
-  Instruction::new(BEQ, AddressingMode::Relative, branch::<'Z', true, UseRelative>),
-  Instruction::new(SBC, AddressingMode::IndirectIndexedY, by_acc::<Sbc, UseIndirectIndexedY>),
+  Instruction::new(DCP, AddressingMode::IndirectIndexedY, by_ref_acc::<Decrement, Cmp, UseIndirectIndexedY>, 8), // 0xd3 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0xd4 (undocumented)
+  Instruction::new(CMP, AddressingMode::ZeroPageX, compare::<'A', UseZeroPageWith<'X'>>, 4),
+  Instruction::new(DEC, AddressingMode::ZeroPageX, by_ref::<Decrement, UseZeroPageWith<'X'>>, 6),
+  Instruction::new(DCP, AddressingMode::ZeroPageX, by_ref_acc::<Decrement, Cmp, UseZeroPageWith<'X'>>, 6), // 0xd7 (undocumented)
+  Instruction::new(CLD, AddressingMode::Implied, set_flag::<'D', false, UseImplied>, 2),
+  Instruction::new(CMP, AddressingMode::AbsoluteY, compare::<'A', UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0xda (undocumented)
+  Instruction::new(DCP, AddressingMode::AbsoluteY, by_ref_acc::<Decrement, Cmp, UseAbsoluteWith<'Y'>>, 7), // 0xdb (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0xdc (undocumented)
+  Instruction::new(CMP, AddressingMode::AbsoluteX, compare::<'A', UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(DEC, AddressingMode::AbsoluteX, by_ref::<Decrement, UseAbsoluteWith<'X'>>, 7),
+  Instruction::new(DCP, AddressingMode::AbsoluteX, by_ref_acc::<Decrement, Cmp, UseAbsoluteWith<'X'>>, 7), // 0xdf (undocumented)
+  Instruction::new(CPX, AddressingMode::Immediate, compare::<'X', UseImmediate>, 2),
+  Instruction::new(SBC, AddressingMode::IndexedIndirectX, by_acc::<Sbc, UseIndexedIndirectX>, 6),
+  Instruction::new(NOP, AddressingMode::Immediate, no_operation::<UseImmediate>, 2), // 0xe2 (undocumented)
+  Instruction::new(ISB, AddressingMode::IndexedIndirectX, by_ref_acc::<Increment, Sbc, UseIndexedIndirectX>, 8), // 0xe3 (undocumented)
+  Instruction::new(CPX, AddressingMode::ZeroPage, compare::<'X', UseZeroPage>, 3),
+  Instruction::new(SBC, AddressingMode::ZeroPage, by_acc::<Sbc, UseZeroPage>, 3),
+  Instruction::new(INC, AddressingMode::ZeroPage, by_ref::<Increment, UseZeroPage>, 5),
+  Instruction::new(ISB, AddressingMode::ZeroPage, by_ref_acc::<Increment, Sbc, UseZeroPage>, 5), // 0xe7 (undocumented)
+  Instruction::new(INX, AddressingMode::Implied, inc_register::<'X', UseImplied>, 2),
+  Instruction::new(SBC, AddressingMode::Immediate, by_acc::<Sbc, UseImmediate>, 2),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2),
+  Instruction::new(SBC, AddressingMode::Immediate, by_acc::<Sbc, UseImmediate>, 2), // 0xeb (undocumented)
+  Instruction::new(CPX, AddressingMode::Absolute, compare::<'X', UseAbsolute>, 4),
+  Instruction::new(SBC, AddressingMode::Absolute, by_acc::<Sbc, UseAbsolute>, 4),
+  Instruction::new(INC, AddressingMode::Absolute, by_ref::<Increment, UseAbsolute>, 6),
+  Instruction::new(ISB, AddressingMode::Absolute, by_ref_acc::<Increment, Sbc, UseAbsolute>, 6), // 0xef (undocumented)
+  Instruction::new(BEQ, AddressingMode::Relative, branch::<'Z', true, UseRelative>, 2),
+  Instruction::new(SBC, AddressingMode::IndirectIndexedY, by_acc::<Sbc, UseIndirectIndexedY>, 5).page_crossing_penalty(),
   UND, // 0xf2 SBC, ZeroPageIndirect
-  UND, // 0xf3
-  UND, // 0xf4
-  Instruction::new(SBC, AddressingMode::ZeroPageX, by_acc::<Sbc, UseZeroPageWith<'X'>>),
-  Instruction::new(INC, AddressingMode::ZeroPageX, by_ref::<Increment, UseZeroPageWith<'X'>>),
-  UND, // 0xf7
-  Instruction::new(SED, AddressingMode::Implied, set_flag::<'D', true, UseImplied>),
-  Instruction::new(SBC, AddressingMode::AbsoluteY, by_acc::<Sbc, UseAbsoluteWith<'Y'>>),
-  UND, // 0xfa PLX, implied
-  UND, // 0xfb
-  UND, // 0xfc
-  Instruction::new(SBC, AddressingMode::AbsoluteX, by_acc::<Sbc, UseAbsoluteWith<'X'>>),
-  Instruction::new(INC, AddressingMode::AbsoluteX, by_ref::<Increment, UseAbsoluteWith<'X'>>),
-  UND, // 0xff
+  Instruction::new(ISB, AddressingMode::IndirectIndexedY, by_ref_acc::<Increment, Sbc, UseIndirectIndexedY>, 8), // 0xf3 (undocumented)
+  Instruction::new(NOP, AddressingMode::ZeroPageX, no_operation::<UseZeroPageWith<'X'>>, 4), // 0xf4 (undocumented)
+  Instruction::new(SBC, AddressingMode::ZeroPageX, by_acc::<Sbc, UseZeroPageWith<'X'>>, 4),
+  Instruction::new(INC, AddressingMode::ZeroPageX, by_ref::<Increment, UseZeroPageWith<'X'>>, 6),
+  Instruction::new(ISB, AddressingMode::ZeroPageX, by_ref_acc::<Increment, Sbc, UseZeroPageWith<'X'>>, 6), // 0xf7 (undocumented)
+  Instruction::new(SED, AddressingMode::Implied, set_flag::<'D', true, UseImplied>, 2),
+  Instruction::new(SBC, AddressingMode::AbsoluteY, by_acc::<Sbc, UseAbsoluteWith<'Y'>>, 4).page_crossing_penalty(),
+  Instruction::new(NOP, AddressingMode::Implied, no_operation::<UseImplied>, 2), // 0xfa (undocumented)
+  Instruction::new(ISB, AddressingMode::AbsoluteY, by_ref_acc::<Increment, Sbc, UseAbsoluteWith<'Y'>>, 7), // 0xfb (undocumented)
+  Instruction::new(NOP, AddressingMode::AbsoluteX, no_operation::<UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(), // 0xfc (undocumented)
+  Instruction::new(SBC, AddressingMode::AbsoluteX, by_acc::<Sbc, UseAbsoluteWith<'X'>>, 4).page_crossing_penalty(),
+  Instruction::new(INC, AddressingMode::AbsoluteX, by_ref::<Increment, UseAbsoluteWith<'X'>>, 7),
+  Instruction::new(ISB, AddressingMode::AbsoluteX, by_ref_acc::<Increment, Sbc, UseAbsoluteWith<'X'>>, 7), // 0xff (undocumented)
 ];
 
 #[test]
@@ -1100,4 +1270,91 @@ fn step_by_step() {
   assert_eq!(cpu.registers.pc.to_u16(), 2);
 }
 
+#[test]
+fn cycle_counts() {
+  use crate::memory::{Address, MemoryBus, ram::RAM};
+
+  let mut regs = Registers::new();
+  let mut mem = RAM::new();
+  let execute = |regs: &mut Registers, mem: &mut RAM, bytes: &[u8]| -> u8 {
+    regs.pc = Address::from(0x10F0);
+    mem.load_at(bytes, regs.pc);
+    Instruction::lookup(bytes[0]).execute(regs, mem)
+  };
+
+  assert_eq!(execute(&mut regs, &mut mem, &[0xA9, 0x00]), 2);       // LDA #0
+  assert_eq!(execute(&mut regs, &mut mem, &[0x20, 0x00, 0x20]), 6); // JSR &2000
+  assert_eq!(execute(&mut regs, &mut mem, &[0xFE, 0x00, 0x20]), 7); // INC &2000,X
+
+  // LDA &20F0,X: +1 only when crossing into the next page
+  regs.x = 0x0F;
+  assert_eq!(execute(&mut regs, &mut mem, &[0xBD, 0xF0, 0x20]), 4);
+  regs.x = 0x10;
+  assert_eq!(execute(&mut regs, &mut mem, &[0xBD, 0xF0, 0x20]), 5);
+  // STA &20F0,X: stores always take the fix-up cycle
+  assert_eq!(execute(&mut regs, &mut mem, &[0x9D, 0xF0, 0x20]), 5);
+
+  // LDA (&70),Y
+  mem.write(Address::from(0x70), 0xFF);
+  mem.write(Address::from(0x71), 0x20);
+  regs.y = 0;
+  assert_eq!(execute(&mut regs, &mut mem, &[0xB1, 0x70]), 5);
+  regs.y = 1;
+  assert_eq!(execute(&mut regs, &mut mem, &[0xB1, 0x70]), 6);
+
+  // BNE: not taken, taken, taken across page boundary
+  regs.p.set::<'Z'>(true);
+  assert_eq!(execute(&mut regs, &mut mem, &[0xD0, 0x02]), 2);
+  regs.p.set::<'Z'>(false);
+  assert_eq!(execute(&mut regs, &mut mem, &[0xD0, 0x02]), 3);
+  assert_eq!(regs.pc, Address::from(0x10F4));
+  assert_eq!(execute(&mut regs, &mut mem, &[0xD0, 0x20]), 4);
+  assert_eq!(regs.pc, Address::from(0x1112));
+}
+
+#[test]
+fn undocumented_opcodes() {
+  use crate::mos6502::CPU;
+  use crate::mos6502::disassemble::disassemble;
+  use crate::memory::{Address, MemoryBus, ram::RAM};
+
+  const PROGRAM: [u8; 18] = [
+    0xA7, 0x80,       // LAX &80      ; A = X = 0x81
+    0x87, 0x81,       // SAX &81      ; &81 = A & X
+    0xC7, 0x82,       // DCP &82      ; &82 = 0x80, compare with A
+    0xE7, 0x83,       // ISB &83      ; &83 = 0x02, A = 0x81 - 0x02
+    0x07, 0x84,       // SLO &84      ; &84 = 0x02, A |= 0x02
+    0x1C, 0x00, 0x20, // NOP &2000,X  ; 3 byte NOP
+    0x80, 0xFF,       // NOP #&FF     ; 2 byte NOP
+    0xCB, 0x01,       // SBX #1       ; X = (A & X) - 1
+    0x1A,             // NOP          ; 1 byte NOP
+  ];
+  let mut mem = RAM::new();
+  let start = Address::from(0x1000);
+  mem.load_at(&PROGRAM, start);
+  mem.load_at(&[0x81, 0x00, 0x81, 0x01, 0x01], Address::from(0x80));
 
+  let mut cpu = CPU::new();
+  cpu.registers.pc = start;
+  for _ in 0..4 {
+    cpu.step(&mut mem);
+  }
+  assert_eq!(mem.read(Address::from(0x81)), 0x81);
+  assert_eq!(mem.read(Address::from(0x82)), 0x80);
+  assert!(cpu.registers.p.has::<'C'>()); // 0x81 >= 0x80
+  assert_eq!(mem.read(Address::from(0x83)), 0x02);
+  assert_eq!(cpu.registers.a, 0x7F); // C set, no borrow
+  cpu.step(&mut mem);
+  assert_eq!(mem.read(Address::from(0x84)), 0x02);
+  assert_eq!(cpu.registers.a, 0x7F);
+  for _ in 0..3 {
+    cpu.step(&mut mem);
+  }
+  assert_eq!(cpu.registers.x, 0x00); // 0x7F & 0x81 - 1
+  assert!(cpu.registers.p.has::<'Z'>());
+  cpu.step(&mut mem);
+  assert_eq!(cpu.registers.pc, Address::from(0x1000 + PROGRAM.len() as u16));
+
+  assert_eq!(disassemble(&[0x1C, 0x00, 0x20]), "1c 00 20 NOP &0x2000 + X");
+  assert_eq!(disassemble(&[0xA7, 0x80]), "a7 80    LAX &0x80");
+}
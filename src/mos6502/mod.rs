@@ -3,15 +3,17 @@ mod alu;
 pub mod disassemble;
 mod instructions;
 pub mod registers;
+pub mod symbols; // Labels for disassembly
+pub mod trace; // Execution trace
 
 use std::rc::Rc;
 
-use disassemble::disassemble_with_address;
 use instructions::{Instruction, handle_interrupt};
 use registers::Registers;
 
-use crate::memory::{Address, MemoryBus, read_address, slice};
+use crate::memory::{Address, MemoryBus, read_address};
 use crate::devices::Signal;
+use crate::snapshot::{Reader, Snapshot, Writer};
 
 #[derive(Debug)]
 pub struct CPU {
@@ -39,6 +41,8 @@ pub fn stop_at<const ADDRESS: u16>(cpu: &CPU, mem: &dyn MemoryBus) -> bool {
 }
 
 impl CPU {
+  pub const CYCLES_PER_US: u64 = 2; // 2MHz
+  const INTERRUPT_CYCLES: u64 = 7;
   const NMI_VECTOR:     u16 = 0xFFFA;
   const RESET_VECTOR:   u16 = 0xFFFC;
   const IRQ_BRK_VECTOR: u16 = 0xFFFE;
@@ -58,22 +62,21 @@ impl CPU {
     } else {
       let opcode = memory.read(self.registers.pc);
       let instruction = Instruction::lookup(opcode);
-      instruction.execute(&mut self.registers, memory);
-      self.cycles += 1;
+      let cycles = instruction.execute(&mut self.registers, memory);
+      self.cycles += cycles as u64;
     }
-//  self.trace(memory);
   }
 
   fn handle_irq(&mut self, memory: &mut dyn MemoryBus) {
     self.registers.p.set_flag::<'B', false>();
     handle_interrupt::<{Self::IRQ_BRK_VECTOR}>(&mut self.registers, memory);
-    self.cycles += 1;
+    self.cycles += Self::INTERRUPT_CYCLES;
   }
 
   fn handle_nmi(&mut self, memory: &mut dyn MemoryBus) {
     self.registers.p.set_flag::<'B', false>();
     handle_interrupt::<{Self::NMI_VECTOR}>(&mut self.registers, memory);
-    self.cycles += 1;
+    self.cycles += Self::INTERRUPT_CYCLES;
   }
 
   #[allow(unused)]
@@ -87,18 +90,36 @@ impl CPU {
     self.cycles += 9;
   }
 
+  // Elapsed time in microseconds, the 1MHz clock driving `Clocked` devices
+  pub const fn micros(&self) -> u64 {
+    self.cycles / Self::CYCLES_PER_US
+  }
+
   pub fn run(&mut self, memory: &mut dyn MemoryBus, stop: &Breakpoint) {
     while !stop(&self, memory) {
       self.step(memory);
     }
   }
+}
+
+// Interrupt lines are left to the devices driving them
+impl Snapshot for CPU {
+  fn save(&self, state: &mut Writer) {
+    let registers = &self.registers;
+    state.bytes(&[registers.a, registers.x, registers.y, registers.p.to_u8(), registers.s.to_u8()]);
+    state.u16(registers.pc.to_u16());
+    state.u64(self.cycles);
+  }
 
-  #[allow(unused)]
-  fn trace(&self, memory: &dyn MemoryBus) {
-    let address = self.registers.pc;
-    let operand = &slice(memory, address, 3);
-    let disassembly = disassemble_with_address(address, operand);
-    log::trace!("{disassembly: <40} {self:?}");
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    let [a, x, y, p, s] = state.array()?;
+    let registers = &mut self.registers;
+    (registers.a, registers.x, registers.y) = (a, x, y);
+    registers.p = registers::Status::from(p);
+    *registers.s.borrow_mut() = s;
+    registers.pc = Address::from(state.u16()?);
+    self.cycles = state.u64()?;
+    Ok(())
   }
 }
 
@@ -0,0 +1,114 @@
+// Symbol tables: names for addresses, shown by the disassembler in place of
+// operands. One symbol a line, blank lines and ; or # comments skipped:
+//
+//   al 00E0A4 .oswrch      ld65 -Ln or VICE label file
+//   OSWRCH = &FFEE         assignments as in BeebAsm or ca65 source, with
+//                          hexadecimal values, & $ or 0x optional
+//   E0A4 .resetEntryPoint  hexadecimal address and name, as from a listing
+//
+// Names are kept as written, leading dot and all. Where an address has more
+// than one, the first loaded is shown.
+
+use std::collections::HashMap;
+use std::io;
+
+use crate::memory::parse_number;
+
+#[derive(Debug, Default)]
+pub struct Symbols(HashMap<u16, String>);
+
+fn invalid(line: usize, text: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, format!("line {line}: no symbol in {text}"))
+}
+
+fn address(text: &str) -> Option<u16> {
+  u16::try_from(parse_number(text.trim())?).ok()
+}
+
+// NAME and address from a line of any of the formats
+fn symbol(text: &str) -> Option<(&str, u16)> {
+  if let Some((name, value)) = text.split_once('=') {
+    return Some((name.trim(), address(value)?));
+  }
+  match text.split_whitespace().collect::<Vec<&str>>()[..] {
+    // VICE memory space C:
+    ["al", value, name] => Some((name, address(value.strip_prefix("C:").unwrap_or(value))?)),
+    [value, name] => Some((name, address(value)?)),
+    _ => None,
+  }
+}
+
+impl Symbols {
+  pub fn new() -> Self {
+    Symbols::default()
+  }
+
+  // keeping any name the address already has
+  pub fn insert(&mut self, address: u16, name: &str) {
+    self.0.entry(address).or_insert_with(|| name.to_string());
+  }
+
+  pub fn get(&self, address: u16) -> Option<&str> {
+    self.0.get(&address).map(String::as_str)
+  }
+
+  pub fn len(&self) -> usize {
+    self.0.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  // Add the symbols in a file's text
+  pub fn parse(&mut self, text: &str) -> io::Result<()> {
+    for (number, line) in text.lines().enumerate() {
+      let line = line.split([';', '#']).next().unwrap_or("").trim();
+      if line.is_empty() {
+        continue;
+      }
+      let (name, address) = symbol(line).ok_or_else(|| invalid(number + 1, line))?;
+      self.insert(address, name);
+    }
+    Ok(())
+  }
+
+  pub fn load(&mut self, filename: &str) -> io::Result<()> {
+    self.parse(&std::fs::read_to_string(filename)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn formats() {
+    let mut symbols = Symbols::new();
+    symbols.parse("; MOS 1.20\n\
+                   al 00E0A4 .oswrch\n\
+                   al C:fe40 .systemVIARegisterB\n\
+                   OSWRCH = &FFEE\n\
+                   OSBYTE=$FFF4   # vectored\n\
+                   userVIA = 0xFE60\n\
+                   brkv = 202\n\
+                   \n\
+                   D9CD .resetEntryPoint\n\
+                   &FE4F .systemVIARegisterANoHandshake\n\
+                   E0A4 .notShown").unwrap();
+    assert_eq!(symbols.len(), 8);
+    assert_eq!(symbols.get(0xE0A4), Some(".oswrch"));
+    assert_eq!(symbols.get(0xFE40), Some(".systemVIARegisterB"));
+    assert_eq!(symbols.get(0xFFEE), Some("OSWRCH"));
+    assert_eq!(symbols.get(0xFFF4), Some("OSBYTE"));
+    assert_eq!(symbols.get(0xFE60), Some("userVIA"));
+    assert_eq!(symbols.get(0x0202), Some("brkv"));
+    assert_eq!(symbols.get(0xD9CD), Some(".resetEntryPoint"));
+    assert_eq!(symbols.get(0xFE4F), Some(".systemVIARegisterANoHandshake"));
+    assert_eq!(symbols.get(0x1900), None);
+
+    let error = symbols.parse("OSWRCH = &FFEE\nLDX &40 ; not a symbol\n").unwrap_err();
+    assert_eq!(error.to_string(), "line 2: no symbol in LDX &40");
+    assert!(symbols.parse("big = &10000").is_err());
+  }
+}
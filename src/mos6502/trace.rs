@@ -0,0 +1,316 @@
+// Execution trace: a line per instruction, before it's executed, to diff a
+// run against another emulator or a log from a real machine. Starts and
+// stops on reaching an address or a cycle count.
+//
+//   bbc-b    cycles, disassembly with address, bytes and symbols,
+//            registers with flag letters
+//   nestest  the layout of nestest.log, written by many 6502 emulators,
+//            less its NES PPU column, which means nothing on a BBC:
+//            PC  BYTES  INSTRUCTION = VALUE  A:00 X:00 Y:00 P:24 SP:FD CYC:7
+//            operands show the address they work out to and what's there
+//            before the instruction runs. No symbols
+//
+// Values in FRED, JIM and SHEILA are shown as ?? rather than read, as reading
+// a device register has side effects.
+
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use super::CPU;
+use super::addressing_modes::UseRelative;
+use super::disassemble::{disassemble_with_symbols, instruction_size};
+use super::instructions::{AddressingMode, Instruction, Mnemonic};
+use super::symbols::Symbols;
+use crate::memory::{Address, MemoryBus, slice};
+
+const IO: RangeInclusive<u16> = 0xFC00..=0xFEFF;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+  Address(u16), // PC reaches it
+  Cycle(u64),   // at or after
+}
+
+impl Trigger {
+  fn hit(&self, cpu: &CPU) -> bool {
+    match self {
+      Trigger::Address(address) => cpu.registers.pc.to_u16() == *address,
+      Trigger::Cycle(cycle) => cpu.cycles >= *cycle,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+  BbcB,
+  Nestest,
+}
+
+// Memory without side effects, None for devices
+fn peek(memory: &dyn MemoryBus, address: u16) -> Option<u8> {
+  (!IO.contains(&address)).then(|| memory.read(Address::from(address)))
+}
+
+// Little endian, with the high byte from its own address
+fn peek_word(memory: &dyn MemoryBus, lo: u16, hi: u16) -> Option<u16> {
+  Some(u16::from_le_bytes([peek(memory, lo)?, peek(memory, hi)?]))
+}
+
+fn hex(value: Option<impl Into<u16>>, digits: usize) -> String {
+  match value {
+    Some(value) => format!("{:0digits$X}", value.into()),
+    None => "?".repeat(digits),
+  }
+}
+
+// marked * in nestest.log
+const fn undocumented(opcode: u8, mnemonic: &Mnemonic) -> bool {
+  match mnemonic {
+    Mnemonic::ALR | Mnemonic::ANC | Mnemonic::ARR | Mnemonic::DCP | Mnemonic::ISB |
+    Mnemonic::LAX | Mnemonic::RLA | Mnemonic::RRA | Mnemonic::SAX | Mnemonic::SBX |
+    Mnemonic::SLO | Mnemonic::SRE => true,
+    Mnemonic::NOP => opcode != 0xEA,
+    Mnemonic::SBC => opcode == 0xEB,
+    _ => false,
+  }
+}
+
+// Operand in nestest.log's $ syntax: where it points, and the value there
+fn nestest_operand(instruction: &Instruction, pc: Address, bytes: &[u8], cpu: &CPU,
+                   memory: &dyn MemoryBus) -> String {
+  use AddressingMode::*;
+  let (x, y) = (cpu.registers.x, cpu.registers.y);
+  let byte = bytes.get(1).copied().unwrap_or(0);
+  let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+  let at = |address: u16| hex(peek(memory, address), 2);
+  // pointers in zero page wrap around in it
+  let pointer = |zp: u8| peek_word(memory, zp as u16, zp.wrapping_add(1) as u16);
+  match instruction.addressing_mode {
+    Implied => String::new(),
+    Accumulator => "A".to_string(),
+    Immediate => format!("#${byte:02X}"),
+    ZeroPage => format!("${byte:02X} = {}", at(byte as u16)),
+    ZeroPageX | ZeroPageY => {
+      let (index, register) = if matches!(instruction.addressing_mode, ZeroPageX) {
+        ('X', x)
+      } else {
+        ('Y', y)
+      };
+      let address = byte.wrapping_add(register);
+      format!("${byte:02X},{index} @ {address:02X} = {}", at(address as u16))
+    },
+    Relative => format!("${:04X}", UseRelative::get_target(pc, &bytes[1..2]).to_u16()),
+    Absolute => match instruction.mnemonic {
+      Mnemonic::JMP | Mnemonic::JSR => format!("${word:04X}"),
+      _ => format!("${word:04X} = {}", at(word)),
+    },
+    AbsoluteX | AbsoluteY => {
+      let (index, register) = if matches!(instruction.addressing_mode, AbsoluteX) {
+        ('X', x)
+      } else {
+        ('Y', y)
+      };
+      let address = word.wrapping_add(register as u16);
+      format!("${word:04X},{index} @ {address:04X} = {}", at(address))
+    },
+    Indirect => {
+      // the high byte doesn't carry into the next page
+      let hi = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+      format!("(${word:04X}) = {}", hex(peek_word(memory, word, hi), 4))
+    },
+    IndexedIndirectX => {
+      let zp = byte.wrapping_add(x);
+      let address = pointer(zp);
+      format!("(${byte:02X},X) @ {zp:02X} = {} = {}", hex(address, 4),
+              address.map_or("??".to_string(), at))
+    },
+    IndirectIndexedY => {
+      let base = pointer(byte);
+      let address = base.map(|base| base.wrapping_add(y as u16));
+      format!("(${byte:02X}),Y = {} @ {} = {}", hex(base, 4), hex(address, 4),
+              address.map_or("??".to_string(), at))
+    },
+    _ZeroPageIndirect => format!("(${byte:02X})"), // 65C02
+  }
+}
+
+pub struct Tracer<W: Write> {
+  output: W,
+  format: Format,
+  symbols: Rc<Symbols>,
+  start: Option<Trigger>, // from the first instruction if none
+  stop: Option<Trigger>,
+  tracing: bool,
+  stopped: bool,
+}
+
+impl<W: Write> Tracer<W> {
+  pub fn new(output: W, format: Format) -> Self {
+    Tracer { output, format, symbols: Rc::new(Symbols::new()), start: None, stop: None,
+             tracing: false, stopped: false }
+  }
+
+  pub fn set_symbols(&mut self, symbols: Rc<Symbols>) {
+    self.symbols = symbols;
+  }
+
+  pub fn start_at(&mut self, trigger: Trigger) {
+    self.start = Some(trigger);
+  }
+
+  pub fn stop_at(&mut self, trigger: Trigger) {
+    self.stop = Some(trigger);
+  }
+
+  pub const fn tracing(&self) -> bool {
+    self.tracing
+  }
+
+  pub fn line(&self, cpu: &CPU, memory: &dyn MemoryBus) -> String {
+    let registers = &cpu.registers;
+    let pc = registers.pc;
+    let bytes = slice(memory, pc, instruction_size(memory.read(pc)));
+    match self.format {
+      Format::BbcB => format!("{:>10} {: <40} a:{:02x} x:{:02x} y:{:02x} s:{:02x} p:{:?}",
+                              cpu.cycles, disassemble_with_symbols(pc, &bytes, &self.symbols),
+                              registers.a, registers.x, registers.y, registers.s.to_u8(),
+                              registers.p),
+      Format::Nestest => {
+        let instruction = Instruction::lookup(bytes[0]);
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        let mark = if undocumented(bytes[0], &instruction.mnemonic) { '*' } else { ' ' };
+        let operand = nestest_operand(instruction, pc, &bytes, cpu, memory);
+        let text = format!("{} {operand}", instruction.mnemonic.to_str());
+        format!("{:04X}  {: <8} {mark}{: <32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+                pc.to_u16(), hex.join(" "), text.trim_end(), registers.a, registers.x,
+                registers.y, registers.p.to_u8(), registers.s.to_u8(), cpu.cycles)
+      },
+    }
+  }
+
+  // Before each instruction
+  pub fn trace(&mut self, cpu: &CPU, memory: &dyn MemoryBus) -> io::Result<()> {
+    if !self.tracing && !self.stopped && self.start.is_none_or(|start| start.hit(cpu)) {
+      self.tracing = true;
+    }
+    if self.tracing && self.stop.is_some_and(|stop| stop.hit(cpu)) {
+      self.tracing = false;
+      self.stopped = true;
+      return self.output.flush();
+    }
+    if self.tracing {
+      let line = self.line(cpu, memory);
+      writeln!(self.output, "{line}")?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::memory::ram::RAM;
+  use crate::mos6502::registers::Status;
+
+  // LDX #3 / loop: DEX / BNE loop / BRK
+  fn machine() -> (CPU, RAM) {
+    let mut ram = RAM::new();
+    for (offset, byte) in [0xA2, 0x03, 0xCA, 0xD0, 0xFD, 0x00].iter().enumerate() {
+      ram.write(Address::from(0x1900 + offset as u16), *byte);
+    }
+    let mut cpu = CPU::new();
+    cpu.registers.pc = Address::from(0x1900);
+    (cpu, ram)
+  }
+
+  fn run(tracer: &mut Tracer<Vec<u8>>, cpu: &mut CPU, ram: &mut RAM, steps: usize) -> Vec<String> {
+    for _ in 0..steps {
+      tracer.trace(cpu, ram).unwrap();
+      cpu.step(ram);
+    }
+    let output = std::mem::take(&mut tracer.output);
+    String::from_utf8(output).unwrap().lines().map(str::to_string).collect()
+  }
+
+  #[test]
+  fn formats() {
+    let (mut cpu, mut ram) = machine();
+    let mut tracer = Tracer::new(Vec::new(), Format::BbcB);
+    let lines = run(&mut tracer, &mut cpu, &mut ram, 2);
+    assert_eq!(lines[0], format!("         0 {: <40} a:00 x:00 y:00 s:ff p:Status(nv_bdizc)",
+                                 "&0x1900  a2 03    LDX #0x03"));
+    assert_eq!(lines[1], format!("         2 {: <40} a:00 x:03 y:00 s:ff p:Status(nv_bdizc)",
+                                 "&0x1902  ca       DEX"));
+
+    let mut symbols = Symbols::new();
+    symbols.insert(0x1902, ".loop");
+    tracer.set_symbols(Rc::new(symbols));
+    let lines = run(&mut tracer, &mut cpu, &mut ram, 1);
+    assert_eq!(lines[0], format!("         4 {: <40} a:00 x:02 y:00 s:ff p:Status(nv_bdizc)",
+                                 "&0x1903  d0 fd    BNE .loop"));
+  }
+
+  // The start of nestest.log, running nestest.nes from &C000, less the PPU column
+  #[test]
+  fn nestest_log() {
+    let log = "\
+C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD CYC:7
+C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD CYC:10
+C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:12
+C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:15
+C5FB  86 11     STX $11 = 00                    A:00 X:00 Y:00 P:26 SP:FD CYC:18
+C5FD  20 2D C7  JSR $C72D                       A:00 X:00 Y:00 P:26 SP:FD CYC:21
+C72D  EA        NOP                             A:00 X:00 Y:00 P:26 SP:FB CYC:27
+C72E  38        SEC                             A:00 X:00 Y:00 P:26 SP:FB CYC:29
+C72F  B0 04     BCS $C735                       A:00 X:00 Y:00 P:27 SP:FB CYC:31";
+    let mut ram = RAM::new();
+    ram.load_at(&[0x4C, 0xF5, 0xC5], Address::from(0xC000));
+    ram.load_at(&[0xA2, 0x00, 0x86, 0x00, 0x86, 0x10, 0x86, 0x11, 0x20, 0x2D, 0xC7],
+                Address::from(0xC5F5));
+    ram.load_at(&[0xEA, 0x38, 0xB0, 0x04], Address::from(0xC72D));
+    let mut cpu = CPU::new();
+    cpu.registers.pc = Address::from(0xC000);
+    cpu.registers.p = Status::from(0x24);
+    *cpu.registers.s.borrow_mut() = 0xFD;
+    cpu.cycles = 7;
+    let mut tracer = Tracer::new(Vec::new(), Format::Nestest);
+    assert_eq!(run(&mut tracer, &mut cpu, &mut ram, 9).join("\n"), log);
+  }
+
+  #[test]
+  fn nestest_operands() {
+    let mut ram = RAM::new();
+    ram.load_at(&[0x00, 0x03], Address::from(0x0089)); // (&89) = &0300
+    ram.load_at(&[0x5A, 0x7E, 0xDB], Address::from(0x02FF));
+    ram.write(Address::from(0x0200), 0xDB);
+    let mut cpu = CPU::new();
+    (cpu.registers.x, cpu.registers.y) = (0x10, 0xFF);
+    let tracer = Tracer::new(Vec::new(), Format::Nestest);
+    let mut operand = |bytes: &[u8]| {
+      ram.load_at(bytes, Address::from(0x1900));
+      cpu.registers.pc = Address::from(0x1900);
+      tracer.line(&cpu, &ram)[15..48].trim().to_string() // * marks undocumented
+    };
+    assert_eq!(operand(&[0xB5, 0xF5]), "LDA $F5,X @ 05 = 00");
+    assert_eq!(operand(&[0xBD, 0xEF, 0x02]), "LDA $02EF,X @ 02FF = 5A");
+    assert_eq!(operand(&[0x6C, 0xFF, 0x02]), "JMP ($02FF) = DB5A");
+    assert_eq!(operand(&[0xA1, 0x79]), "LDA ($79,X) @ 89 = 0300 = 7E");
+    assert_eq!(operand(&[0xB1, 0x89]), "LDA ($89),Y = 0300 @ 03FF = 00");
+    assert_eq!(operand(&[0xAD, 0x4D, 0xFE]), "LDA $FE4D = ??");
+    assert_eq!(operand(&[0x4A]), "LSR A");
+    assert_eq!(operand(&[0x04, 0x8A]), "*NOP $8A = 03");
+  }
+
+  #[test]
+  fn triggers() {
+    let (mut cpu, mut ram) = machine();
+    let mut tracer = Tracer::new(Vec::new(), Format::Nestest);
+    tracer.start_at(Trigger::Address(0x1903));
+    tracer.stop_at(Trigger::Cycle(12));
+    let lines = run(&mut tracer, &mut cpu, &mut ram, 10);
+    let pcs: Vec<&str> = lines.iter().map(|line| &line[..4]).collect();
+    assert_eq!(pcs, ["1903", "1902", "1903"]); // until cycle 12, not again
+    assert!(!tracer.tracing());
+  }
+}
@@ -1,11 +1,13 @@
 pub mod alt_via; // delegate to B-em backend
 pub mod system_via;
+pub mod user_via;
 
 use std::cell::Cell;
 use std::rc::Rc;
 
 use crate::devices::{Clocked, Device, Signal};
 use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
 
 pub trait Port: std::fmt::Debug {
   // Control lines CA1-2 / CB1-2
@@ -13,6 +15,13 @@ pub trait Port: std::fmt::Debug {
   // I/O lines PA0-7 / PB0-7
   fn read(&self, ddr_mask: u8) -> u8;
   fn write(&mut self, value: u8, ddr_mask: u8);
+  // Control line CA2 / CB2 driven as output
+  fn write_control(&mut self, _c2: bool) {}
+  // Peripherals generating signals of their own, us - absolute clock time
+  fn step(&mut self, _us: u64) {}
+  // Latched pin values, for snapshots. Not the peripherals beyond
+  fn save_state(&self, _state: &mut Writer) {}
+  fn restore_state(&mut self, _state: &mut Reader) -> std::io::Result<()> { Ok(()) }
 }
 
 #[derive(Debug)]
@@ -39,15 +48,6 @@ impl<const ID: char> Port for BogusPort<ID> {
   }
 }
 
-pub type UserPortA = BogusPort<'a'>;
-pub type UserPortB = BogusPort<'b'>;
-
-//  &60–&7F 6522 VIA USER VIA 24
-pub type UserVIA = VIA<UserPortA, UserPortB>;
-impl Device for UserVIA {
-  fn name(&self) -> &'static str { "6522 User VIA" }
-}
-
 #[derive(Debug)]
 struct ActiveEdge<const PCR_OUTPUT_MASK: u8, const PCR_EDGE_MASK: u8>(bool);
 
@@ -62,6 +62,19 @@ impl<const PCR_OUTPUT_MASK: u8, const PCR_EDGE_MASK: u8>
   }
 }
 
+// Registers, timers and control line levels, as other emulators' savestates
+// hold them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct State {
+  pub iora: u8, pub iorb: u8,
+  pub ddra: u8, pub ddrb: u8,
+  pub t1l: u16, pub t2l: u16,
+  pub t1c: u16, pub t2c: u16,
+  pub sr: u8, pub acr: u8, pub pcr: u8, pub ifr: u8, pub ier: u8,
+  pub ca1: bool, pub ca2: bool, pub cb1: bool, pub cb2: bool,
+  pub t1_active: bool, pub t2_active: bool,
+}
+
 #[derive(Debug)]
 pub struct VIA<PA: Port, PB: Port> {
   pub irq: Rc<Signal>,// shared, hard-wired to other IRQ sources for logic "OR"
@@ -81,6 +94,8 @@ pub struct VIA<PA: Port, PB: Port> {
   port_a: PA, port_b: PB,
   ca1: ActiveEdge<0,           0b0000_0001>,
   ca2: ActiveEdge<0b0000_1000, 0b0000_0100>,
+  cb1: ActiveEdge<0,           0b0001_0000>,
+  cb2: ActiveEdge<0b1000_0000, 0b0100_0000>,
   clock_ms: Cell<u64>,
   t1_active: Cell<bool>, t2_active: Cell<bool>,
 }
@@ -120,6 +135,8 @@ impl<PA: Port, PB: Port> VIA<PA, PB> {
       port_a, port_b,
       ca1: ActiveEdge(false),
       ca2: ActiveEdge(false),
+      cb1: ActiveEdge(false),
+      cb2: ActiveEdge(false),
       clock_ms: Cell::new(0),
       t1_active: Cell::new(false), 
       t2_active: Cell::new(false),
@@ -184,9 +201,9 @@ impl<PA: Port, PB: Port> VIA<PA, PB> {
 
       self.set_ifr_bits(Self::IFR_CA1_BIT);
 
-      if self.pcr & 0b1100 == 0b1000 {
-        // Handshaking mode
-        self.update_ca2(true); // ?
+      if self.pcr & 0b1110 == 0b1000 {
+        // Handshake output mode: data taken, CA2 back high
+        self.port_a.write_control(true);
       }
     }
   }
@@ -196,6 +213,48 @@ impl<PA: Port, PB: Port> VIA<PA, PB> {
       self.set_ifr_bits(Self::IFR_CA2_BIT);
     }
   }
+
+  fn update_cb1(&mut self, level: bool) {
+    if self.cb1.change(level, self.pcr) {
+      if self.acr & Self::ACR_PB_LATCH_BIT != 0 {
+        // Read latch, output bits keep ORB value
+        let irb = self.port_b.read(self.ddrb);
+        self.iorb = Self::mask_bits(self.iorb, irb, !self.ddrb);
+      }
+
+      self.set_ifr_bits(Self::IFR_CB1_BIT);
+
+      if self.pcr & 0b1110_0000 == 0b1000_0000 {
+        // Handshake output mode: data taken, CB2 back high
+        self.port_b.write_control(true);
+      }
+    }
+  }
+
+  // CA2 / CB2 output modes: handshake low until C1 active edge, pulse low
+  // for a cycle, or manual level
+  fn handshake(port: &mut impl Port, c2_control: u8) {
+    match c2_control {
+      0b100 => port.write_control(false),
+      0b101 => {
+        port.write_control(false);
+        port.write_control(true);
+      },
+      _ => {},
+    }
+  }
+
+  fn manual_output(port: &mut impl Port, c2_control: u8) {
+    if c2_control & 0b110 == 0b110 {
+      port.write_control(c2_control & 1 != 0);
+    }
+  }
+
+  fn update_cb2(&mut self, level: bool) {
+    if self.cb2.change(level, self.pcr) {
+      self.set_ifr_bits(Self::IFR_CB2_BIT);
+    }
+  }
 }
 
 impl<PA: Port, PB: Port> MemoryBus for VIA<PA, PB> {
@@ -336,6 +395,7 @@ impl<PA: Port, PB: Port> MemoryBus for VIA<PA, PB> {
           false => Self::IFR_CB1_BIT | Self::IFR_CB2_BIT
         };
         self.clear_ifr_bits(bits);
+        Self::handshake(&mut self.port_b, cb2_control);
       },
       0b0001 => {
         log::trace!("write {value:#04x} -> {address:?} IORA");
@@ -351,6 +411,7 @@ impl<PA: Port, PB: Port> MemoryBus for VIA<PA, PB> {
           false => Self::IFR_CA1_BIT | Self::IFR_CA2_BIT
         };
         self.clear_ifr_bits(bits);
+        Self::handshake(&mut self.port_a, ca2_control);
       },
       0b0010 => {
         log::trace!("write {value:#04x} -> {address:?} DDRB");
@@ -419,6 +480,8 @@ impl<PA: Port, PB: Port> MemoryBus for VIA<PA, PB> {
       0b1100 => {
         log::trace!("write {value:#04x} -> {address:?} PCR");
         self.pcr = value;
+        Self::manual_output(&mut self.port_a, (value & 0b0000_1110) >> 1);
+        Self::manual_output(&mut self.port_b, (value & 0b1110_0000) >> 5);
       },
       0b1101 => {
         log::trace!("write {value:#04x} -> {address:?} IFR");
@@ -467,9 +530,14 @@ impl<PA: Port, PB: Port> Clocked for VIA<PA, PB> {
       ticks as u16
     };
 
+    self.port_a.step(ms);
+    self.port_b.step(ms);
     let (ca1, ca2) = self.port_a.control();
     self.update_ca1(ca1);
     self.update_ca2(ca2);
+    let (cb1, cb2) = self.port_b.control();
+    self.update_cb1(cb1);
+    self.update_cb2(cb2);
 
     // per W65c22 datasheet: PB7, IRQB output in N+2 cycles
     fn expires(timer: u16, ticks: u16) -> bool {
@@ -510,6 +578,71 @@ impl<PA: Port, PB: Port> Clocked for VIA<PA, PB> {
       // one-shot just continues counting (from 0xffff)
       self.t2c = self.t2c.wrapping_sub(ticks);
     }
+
+    // Peripherals hold their control line flags until serviced, so keep IRQB
+    // asserted: an edge while the CPU masks IRQs must not get lost
+    const CONTROL_BITS: u8 = 0b0001_1011; // CB1, CB2, CA1, CA2
+    if self.ifr.get() & self.ier & CONTROL_BITS != 0 {
+      self.irq.raise();
+    }
+  }
+}
+
+// Timing (the clock) and the ports are left alone
+impl<PA: Port, PB: Port> VIA<PA, PB> {
+  pub fn state(&self) -> State {
+    State { iora: self.iora, iorb: self.iorb, ddra: self.ddra, ddrb: self.ddrb,
+            t1l: self.t1l, t2l: self.t2l, t1c: self.t1c, t2c: self.t2c,
+            sr: self.sr, acr: self.acr, pcr: self.pcr, ifr: self.ifr.get(), ier: self.ier,
+            ca1: self.ca1.0, ca2: self.ca2.0, cb1: self.cb1.0, cb2: self.cb2.0,
+            t1_active: self.t1_active.get(), t2_active: self.t2_active.get(),
+    }
+  }
+
+  pub fn set_state(&mut self, state: State) {
+    (self.iora, self.iorb, self.ddra, self.ddrb) = (state.iora, state.iorb, state.ddra, state.ddrb);
+    (self.t1l, self.t2l, self.t1c, self.t2c) = (state.t1l, state.t2l, state.t1c, state.t2c);
+    (self.sr, self.acr, self.pcr, self.ier) = (state.sr, state.acr, state.pcr, state.ier);
+    self.ifr.set(state.ifr);
+    (self.ca1.0, self.ca2.0, self.cb1.0, self.cb2.0) = (state.ca1, state.ca2, state.cb1, state.cb2);
+    self.t1_active.set(state.t1_active);
+    self.t2_active.set(state.t2_active);
+  }
+}
+
+impl<PA: Port, PB: Port> Snapshot for VIA<PA, PB> {
+  fn save(&self, state: &mut Writer) {
+    state.bytes(&[self.iora, self.iorb, self.ddra, self.ddrb]);
+    for timer in [self.t1l, self.t2l, self.t1c, self.t2c] {
+      state.u16(timer);
+    }
+    state.bytes(&[self.sr, self.acr, self.pcr, self.ifr.get(), self.ier]);
+    for level in [self.ca1.0, self.ca2.0, self.cb1.0, self.cb2.0] {
+      state.bool(level);
+    }
+    state.u64(self.clock_ms.get());
+    state.bool(self.t1_active.get());
+    state.bool(self.t2_active.get());
+    self.port_a.save_state(state);
+    self.port_b.save_state(state);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    [self.iora, self.iorb, self.ddra, self.ddrb] = state.array()?;
+    for timer in [&mut self.t1l, &mut self.t2l, &mut self.t1c, &mut self.t2c] {
+      *timer = state.u16()?;
+    }
+    let [sr, acr, pcr, ifr, ier] = state.array()?;
+    (self.sr, self.acr, self.pcr, self.ier) = (sr, acr, pcr, ier);
+    self.ifr.set(ifr);
+    for level in [&mut self.ca1.0, &mut self.ca2.0, &mut self.cb1.0, &mut self.cb2.0] {
+      *level = state.bool()?;
+    }
+    self.clock_ms.set(state.u64()?);
+    self.t1_active.set(state.bool()?);
+    self.t2_active.set(state.bool()?);
+    self.port_a.restore_state(state)?;
+    self.port_b.restore_state(state)
   }
 }
 
@@ -562,6 +695,23 @@ fn via_ca2() {
   assert!(!via.irq.sense());
 }
 
+#[test]
+fn via_cb1() {
+  let pa = BogusPort::<'A'>::new(0);
+  let pb = BogusPort::<'B'>::new(1); // CB1 is high
+  let mut via = VIA::new(pa, pb);
+  via.write(Address::from(12), 0b0000_0000); // PCR cb1 negative active edge
+  via.write(Address::from(14), BIT7 | 1 << 4); // set IER_CB1_BIT
+  via.step(1); // positive edge does not trigger
+  assert!(!via.irq.sense());
+  via.port_b.0 = 0;
+  via.step(2);
+  assert!(via.irq.sense());
+  assert_eq!(via.read(Address::from(13)) & (1 << 4), 1 << 4);
+  via.read(Address::from(0)); // clear by reading from IRB
+  assert_eq!(via.read(Address::from(13)), 0);
+}
+
 #[test]
 fn via_timer1() {
   let pa = BogusPort::<'A'>::new(0);
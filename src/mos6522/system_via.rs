@@ -1,4 +1,3 @@
-use std::borrow::BorrowMut;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -6,6 +5,9 @@ use super::{Port, VIA};
 use crate::devices::{Device, Signal};
 use crate::devices::ic32::IC32;
 use crate::devices::keyboard::Keyboard;
+use crate::snapshot::{Reader, Writer};
+use crate::sn76489::SN76489;
+use crate::upd7002::ADC;
 
 //  &40–&5F 6522 VIA SYSTEM VIA
 pub type SystemVIA = VIA<SystemPortA, SystemPortB>;
@@ -13,9 +15,21 @@ impl Device for SystemVIA {
   fn name(&self) -> &'static str { "6522 System VIA" }
 }
 
+impl SystemVIA {
+  // Latched PA0-7 and PB0-7 pin values
+  pub const fn latches(&self) -> (u8, u8) {
+    (self.port_a.pa, self.port_b.pb)
+  }
+
+  pub fn set_latches(&mut self, pa: u8, pb: u8) {
+    (self.port_a.pa, self.port_b.pb) = (pa, pb);
+  }
+}
+
 #[derive(Debug)]
 pub struct SystemPortA {
   pub crtc_vsync: Rc<Signal>, // 6845 video controller for 50Hz vsync signal
+  pub sound: Rc<RefCell<SN76489>>, // on slow data bus
   pa: u8,                     // latched PA0-7 pin value written or read
   ic32: Rc<IC32>,             // addressable latch
   keyboard: Rc<RefCell<Keyboard>>,
@@ -24,7 +38,8 @@ pub struct SystemPortA {
 impl SystemPortA {
   pub fn new(ic32: Rc<IC32>, keyboard: Rc<RefCell<Keyboard>>) -> Self {
     let crtc_vsync = Rc::new(Signal::new());
-    SystemPortA { pa: 0, crtc_vsync, ic32, keyboard }
+    let sound = Rc::new(RefCell::new(SN76489::new()));
+    SystemPortA { pa: 0, crtc_vsync, sound, ic32, keyboard }
   }
 }
 
@@ -80,9 +95,12 @@ impl Port for SystemPortA {
     self.pa &= !ddr_mask;
     self.pa |= value & ddr_mask;
 
+    // sound chip latches slow data bus while its write enable is low
+    let mut sound = self.sound.borrow_mut();
+    sound.set_data_bus(self.pa);
     let sound_disabled = self.ic32.has::<{IC32::SOUND}>();
     if !sound_disabled {
-      // send self.pa value over slow data bus to sound chip
+      sound.write_enable();
     }
 
     let speech_disabled = self.ic32.has::<{IC32::SPEECH_W}>();
@@ -90,6 +108,15 @@ impl Port for SystemPortA {
       // send self.pa value over slow data bus to speech chip
     }
   }
+
+  fn save_state(&self, state: &mut Writer) {
+    state.u8(self.pa);
+  }
+
+  fn restore_state(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.pa = state.u8()?;
+    Ok(())
+  }
 }
 
 #[derive(Debug)]
@@ -99,12 +126,15 @@ pub struct SystemPortB {
   // PB6, PB7: input from speech processor
   pb: u8, // Latched value written to / read from PB0-7
   ic32: Rc<IC32>,
-  joybuttons: (bool, bool), // TODO
+  pub sound: Rc<RefCell<SN76489>>, // write enable from IC32 B0
+  pub adc: Rc<RefCell<ADC>>, // analogue port: fire buttons and EOC
 }
 
 impl SystemPortB {
   pub fn new(ic32: Rc<IC32>) -> Self {
-    SystemPortB { pb: 0, ic32, joybuttons: (false, false) }
+    let sound = Rc::new(RefCell::new(SN76489::new()));
+    let adc = Rc::new(RefCell::new(ADC::new()));
+    SystemPortB { pb: 0, ic32, sound, adc }
   }
 
   const fn decode(value: u8) -> (u8, bool) {
@@ -116,9 +146,9 @@ impl SystemPortB {
 
 impl Port for SystemPortB {
   fn control(&self) -> (bool, bool) {
-    // TODO The CB1 input is the end of conversion (EOC) signal from the 7002
-    // analogue to digital converter
-    let cb1 = false;
+    // The CB1 input is the end of conversion (EOC) signal from the 7002
+    // analogue to digital converter, falling when a conversion completes
+    let cb1 = self.adc.borrow().busy();
     // CB2 input is the light pen strobe signal sent by 6845 video processor
     let cb2 = false;
 
@@ -130,8 +160,9 @@ impl Port for SystemPortB {
     let mut result = 0xFF & !ddr_mask;
 
     // PB4 and PB5: joystick buttons
-    if self.joybuttons.0 { result &= !(1 << 4); } // PB4
-    if self.joybuttons.1 { result &= !(1 << 5); } // PB5
+    let adc = self.adc.borrow();
+    if adc.fire_button(0) { result &= !(1 << 4); } // PB4
+    if adc.fire_button(1) { result &= !(1 << 5); } // PB5
 
     // PB6 and PB7: inputs from speech processor (interrupt & ready, resp)
     result
@@ -143,7 +174,24 @@ impl Port for SystemPortB {
     let (address, value) = Self::decode(value);
     let msg = IC32::get_message(address, value);
     log::trace!("System VIA port B: {address}={value}: {msg}");
-    self.ic32.borrow_mut().write(address, value);
+    // falling edge of sound write enable latches slow data bus
+    let sound_enable = address == IC32::SOUND && !value && self.ic32.has::<{IC32::SOUND}>();
+    self.ic32.write(address, value);
+    if sound_enable {
+      self.sound.borrow_mut().write_enable();
+    }
+  }
+
+  // with the addressable latch it drives
+  fn save_state(&self, state: &mut Writer) {
+    state.u8(self.pb);
+    state.u8(self.ic32.latch());
+  }
+
+  fn restore_state(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.pb = state.u8()?;
+    self.ic32.set_latch(state.u8()?);
+    Ok(())
   }
 }
 
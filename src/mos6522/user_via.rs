@@ -0,0 +1,286 @@
+use std::cell::Cell;
+use std::io::Write;
+
+use super::{Port, VIA};
+use crate::devices::Device;
+use crate::snapshot::{Reader, Writer};
+
+//  &60–&7F 6522 VIA USER VIA 24
+pub type UserVIA = VIA<PrinterPort, UserPort>;
+impl Device for UserVIA {
+  fn name(&self) -> &'static str { "6522 User VIA" }
+}
+
+impl UserVIA {
+  pub fn connect_printer(&mut self, output: Box<dyn Write>) {
+    self.port_a.output = Some(output);
+  }
+
+  pub fn connect_user_port(&mut self, device: Box<dyn Port>) {
+    self.port_b.0 = Some(device);
+  }
+
+  pub fn disconnect_user_port(&mut self) -> Option<Box<dyn Port>> {
+    self.port_b.0.take()
+  }
+
+  // Latched PA0-7, the byte on the printer's data lines
+  pub const fn printer_data(&self) -> u8 {
+    self.port_a.data
+  }
+
+  pub fn set_printer_data(&mut self, data: u8) {
+    self.port_a.data = data;
+  }
+}
+
+// PA0-7 is the Centronics parallel printer port
+// - CA2 output: data strobe, printer takes byte on falling edge
+// - CA1 input: acknowledge, pulsed low once the byte is taken
+pub struct PrinterPort {
+  data: u8,               // latched PA0-7
+  strobe: bool,           // CA2 level
+  acknowledge: Cell<bool>, // ACK pulse pending
+  output: Option<Box<dyn Write>>,
+}
+
+impl PrinterPort {
+  pub fn new() -> Self {
+    PrinterPort { data: 0, strobe: true, acknowledge: Cell::new(false), output: None }
+  }
+
+  fn print(&mut self) {
+    match &mut self.output {
+      Some(output) => {
+        if let Err(error) = output.write_all(&[self.data]).and_then(|_| output.flush()) {
+          log::warn!("printer: {error}");
+        }
+      },
+      None => log::debug!("printer: {:#04x} printed to nowhere", self.data),
+    }
+    self.acknowledge.set(true);
+  }
+}
+
+impl std::fmt::Debug for PrinterPort {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.debug_struct("PrinterPort")
+      .field("data", &self.data)
+      .field("strobe", &self.strobe)
+      .field("output", &self.output.is_some())
+      .finish()
+  }
+}
+
+impl Port for PrinterPort {
+  fn control(&self) -> (bool, bool) {
+    let ca1 = !self.acknowledge.replace(false);
+    (ca1, self.strobe)
+  }
+
+  fn read(&self, ddr_mask: u8) -> u8 {
+    self.data & ddr_mask | !ddr_mask // inputs pulled up
+  }
+
+  fn write(&mut self, value: u8, ddr_mask: u8) {
+    self.data = value & ddr_mask;
+  }
+
+  fn write_control(&mut self, c2: bool) {
+    if self.strobe && !c2 {
+      self.print();
+    }
+    self.strobe = c2;
+  }
+
+  fn save_state(&self, state: &mut Writer) {
+    state.u8(self.data);
+    state.bool(self.strobe);
+    state.bool(self.acknowledge.get());
+  }
+
+  fn restore_state(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.data = state.u8()?;
+    self.strobe = state.bool()?;
+    self.acknowledge.set(state.bool()?);
+    Ok(())
+  }
+}
+
+// PB0-7 and CB1-2 on the user port connector, to plug in a peripheral. Lines
+// float high with nothing connected
+#[derive(Debug, Default)]
+pub struct UserPort(Option<Box<dyn Port>>);
+
+impl UserPort {
+  pub fn new() -> Self {
+    UserPort(None)
+  }
+}
+
+impl Port for UserPort {
+  fn control(&self) -> (bool, bool) {
+    self.0.as_ref().map_or((true, true), |device| device.control())
+  }
+
+  fn read(&self, ddr_mask: u8) -> u8 {
+    self.0.as_ref().map_or(0xFF, |device| device.read(ddr_mask))
+  }
+
+  fn write(&mut self, value: u8, ddr_mask: u8) {
+    if let Some(device) = &mut self.0 {
+      device.write(value, ddr_mask);
+    }
+  }
+
+  fn write_control(&mut self, c2: bool) {
+    if let Some(device) = &mut self.0 {
+      device.write_control(c2);
+    }
+  }
+
+  fn step(&mut self, us: u64) {
+    if let Some(device) = &mut self.0 {
+      device.step(us);
+    }
+  }
+}
+
+// AMX mouse on the user port
+// - CB1 and PB0: X quadrature pair
+// - CB2 and PB2: Y quadrature pair
+// - PB5, PB6, PB7: left, middle and right buttons, low when pressed
+#[derive(Debug, Default)]
+pub struct AmxMouse {
+  pending: (i32, i32),  // movement still to be sent, right and up positive
+  phase: (u8, u8),      // quadrature 0-3
+  buttons: [bool; 3],
+  next_us: u64,
+}
+
+// quadrature steps per second, well within the AMX driver's reach
+const AMX_STEP_US: u64 = 500;
+
+impl AmxMouse {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn move_by(&mut self, dx: i32, dy: i32) {
+    self.pending.0 += dx;
+    self.pending.1 += dy;
+  }
+
+  pub fn set_button(&mut self, button: usize, pressed: bool) {
+    self.buttons[button] = pressed;
+  }
+
+  // (clock, direction) lines of a quadrature phase; forwards the clock leads
+  const fn lines(phase: u8) -> (bool, bool) {
+    match phase & 3 {
+      0 => (false, false),
+      1 => (true, false),
+      2 => (true, true),
+      _ => (false, true),
+    }
+  }
+
+  fn advance(pending: &mut i32, phase: &mut u8) {
+    match (*pending).signum() {
+      1 => *phase = phase.wrapping_add(1),
+      -1 => *phase = phase.wrapping_sub(1),
+      _ => return,
+    }
+    *pending -= (*pending).signum();
+  }
+}
+
+impl Port for AmxMouse {
+  fn control(&self) -> (bool, bool) {
+    (Self::lines(self.phase.0).0, Self::lines(self.phase.1).0)
+  }
+
+  fn read(&self, _ddr_mask: u8) -> u8 {
+    let mut result = 0xFF;
+    if !Self::lines(self.phase.0).1 { result &= !(1 << 0); } // PB0
+    if !Self::lines(self.phase.1).1 { result &= !(1 << 2); } // PB2
+    for (bit, pressed) in [5, 6, 7].iter().zip(self.buttons) {
+      if pressed { result &= !(1 << bit); }
+    }
+    result
+  }
+
+  fn write(&mut self, _value: u8, _ddr_mask: u8) {
+    // input only
+  }
+
+  fn step(&mut self, us: u64) {
+    if us < self.next_us {
+      return;
+    }
+    self.next_us = us + AMX_STEP_US;
+    Self::advance(&mut self.pending.0, &mut self.phase.0);
+    Self::advance(&mut self.pending.1, &mut self.phase.1);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::devices::Clocked;
+  use crate::host::Capture;
+  use crate::memory::{Address, MemoryBus};
+
+  #[test]
+  fn printer_handshake() {
+    let capture = Capture::default();
+    let mut via = UserVIA::new(PrinterPort::new(), UserPort::new());
+    via.connect_printer(Box::new(capture.clone()));
+    via.write(Address::from(0xFE63), 0xFF); // DDRA all output
+    via.write(Address::from(0xFE6C), 0b0000_1110); // CA2 high, CA1 negative edge
+    via.write(Address::from(0xFE6E), 0b1000_0010); // enable CA1 interrupt
+    via.step(1);
+    assert!(!via.irq.sense());
+
+    // MOS 1.20 strobes by hand
+    for &byte in b"OK" {
+      via.write(Address::from(0xFE61), byte);
+      via.write(Address::from(0xFE6C), 0b0000_1100); // CA2 low
+      via.write(Address::from(0xFE6C), 0b0000_1110); // CA2 high
+      via.step(via.clock_ms.get() + 1); // acknowledge
+      assert!(via.irq.sense());
+      via.step(via.clock_ms.get() + 1);
+    }
+    assert_eq!(capture.bytes(), b"OK");
+
+    // pulse output mode strobes on write to ORA
+    via.write(Address::from(0xFE6C), 0b0000_1010);
+    via.write(Address::from(0xFE61), b'!');
+    assert_eq!(capture.bytes(), b"OK!");
+  }
+
+  #[test]
+  fn amx_mouse() {
+    let mut via = UserVIA::new(PrinterPort::new(), UserPort::new());
+    assert_eq!(via.read(Address::from(0xFE60)), 0xFF); // nothing connected
+    let mut mouse = AmxMouse::new();
+    mouse.move_by(2, 0);
+    mouse.set_button(0, true);
+    via.connect_user_port(Box::new(mouse));
+    via.write(Address::from(0xFE6C), 0b0001_0000); // CB1 positive edge
+    via.write(Address::from(0xFE6E), 0b1001_0000); // enable CB1 interrupt
+
+    // first step to the right raises CB1, direction PB0 low
+    via.step(1);
+    assert!(via.irq.sense());
+    assert_eq!(via.read(Address::from(0xFE60)) & 0b1110_0001, 0b1100_0000);
+    via.step(100); // too soon for the next step
+    assert_eq!(via.read(Address::from(0xFE6D)) & 0b0001_0000, 0);
+    via.step(501);
+    assert_eq!(via.read(Address::from(0xFE60)) & 1, 1);
+    via.step(1001); // all sent
+    via.step(1501);
+    assert!(!via.irq.sense());
+    assert!(via.disconnect_user_port().is_some());
+  }
+}
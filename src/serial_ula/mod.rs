@@ -0,0 +1,214 @@
+// Serial ULA
+// Generates transmit and receive clocks for the 6850 ACIA and routes its
+// serial data either to the RS423 port or to the cassette interface. Also
+// drives the cassette motor relay.
+
+use std::collections::VecDeque;
+
+use crate::cassette::Cassette;
+use crate::devices::{Clocked, Device};
+use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+// Host side of a serial line: non-blocking byte stream
+pub trait SerialLink {
+  fn read(&mut self) -> Option<u8>;
+  fn write(&mut self, byte: u8);
+}
+
+// RS423 output looped back to input, handy for tests
+#[derive(Debug, Default)]
+pub struct Loopback {
+  buffer: VecDeque<u8>,
+}
+
+impl SerialLink for Loopback {
+  fn read(&mut self) -> Option<u8> {
+    self.buffer.pop_front()
+  }
+
+  fn write(&mut self, byte: u8) {
+    self.buffer.push_back(byte);
+  }
+}
+
+// b2-0 transmit and b5-3 receive baud rate
+const BAUD_RATES: [u32; 8] = [19200, 1200, 4800, 150, 9600, 300, 2400, 75];
+
+// ACIA clock from cassette: 1200 baud divided by 16, 300 baud by 64
+const CASSETTE_CLOCK_HZ: u32 = 19200;
+
+// high tone before carrier is detected, ~240 cycles
+const CARRIER_DETECT_US: u64 = 100_000;
+
+//  &10–&1F Serial ULA Serial system chip
+pub struct SerialULA {
+  control: u8,
+  rs423: Option<Box<dyn SerialLink>>,
+  pub cassette: Cassette,
+  high_tone_us: u64,
+  clock_us: u64,
+}
+
+impl SerialULA {
+  pub fn new() -> Self {
+    SerialULA { control: 0, rs423: None, cassette: Cassette::new(),
+                high_tone_us: 0, clock_us: 0 }
+  }
+
+  pub fn connect_rs423(&mut self, link: Box<dyn SerialLink>) {
+    self.rs423 = Some(link);
+  }
+
+  pub fn disconnect_rs423(&mut self) -> Option<Box<dyn SerialLink>> {
+    self.rs423.take()
+  }
+
+  pub const fn control(&self) -> u8 {
+    self.control
+  }
+
+  pub const fn tx_baud(&self) -> u32 {
+    BAUD_RATES[(self.control & 0b111) as usize]
+  }
+
+  pub const fn rx_baud(&self) -> u32 {
+    BAUD_RATES[((self.control >> 3) & 0b111) as usize]
+  }
+
+  // ACIA transmit clock, Hz
+  pub const fn tx_clock(&self) -> u32 {
+    if self.rs423() { 64 * self.tx_baud() } else { CASSETTE_CLOCK_HZ }
+  }
+
+  // ACIA receive clock, Hz
+  pub const fn rx_clock(&self) -> u32 {
+    if self.rs423() { 64 * self.rx_baud() } else { CASSETTE_CLOCK_HZ }
+  }
+
+  // b6: RS423 rather than cassette
+  pub const fn rs423(&self) -> bool {
+    self.control & 0b0100_0000 != 0
+  }
+
+  // b7: cassette motor relay
+  pub const fn motor(&self) -> bool {
+    self.control & 0b1000_0000 != 0
+  }
+
+  // ACIA data carrier detect input: high tone from cassette
+  pub fn carrier_detect(&self) -> bool {
+    !self.rs423() && self.motor() && self.high_tone_us >= CARRIER_DETECT_US
+  }
+
+  // ACIA transmit data output
+  pub fn transmit(&mut self, byte: u8) {
+    let rs423 = self.rs423();
+    match &mut self.rs423 {
+      Some(link) if rs423 => link.write(byte),
+      _ => log::debug!("serial ULA: {byte:#04x} transmitted to nowhere"),
+    }
+  }
+
+  // ACIA receive data input. Only RS423 honours request to send, tape
+  // doesn't wait
+  pub fn receive(&mut self, request_to_send: bool) -> Option<u8> {
+    if !self.rs423() {
+      return self.cassette.take_byte();
+    }
+    match &mut self.rs423 {
+      Some(link) if request_to_send => link.read(),
+      _ => None,
+    }
+  }
+}
+
+impl Clocked for SerialULA {
+  fn step(&mut self, us: u64) {
+    if self.motor() {
+      self.cassette.play(us - self.clock_us);
+      if self.cassette.carrier() {
+        self.high_tone_us += us - self.clock_us;
+      } else {
+        self.high_tone_us = 0;
+      }
+    }
+    self.clock_us = us;
+  }
+}
+
+// Not the tape position, nor the RS423 link
+impl Snapshot for SerialULA {
+  fn save(&self, state: &mut Writer) {
+    state.u8(self.control);
+    state.u64(self.high_tone_us);
+    state.u64(self.clock_us);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.control = state.u8()?;
+    self.high_tone_us = state.u64()?;
+    self.clock_us = state.u64()?;
+    Ok(())
+  }
+}
+
+impl std::fmt::Debug for SerialULA {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.debug_struct("SerialULA")
+      .field("control", &self.control)
+      .field("rs423", &self.rs423.is_some())
+      .finish()
+  }
+}
+
+impl Device for SerialULA {
+  fn name(&self) -> &'static str { "Serial ULA" }
+}
+
+impl MemoryBus for SerialULA {
+  fn read(&self, _address: Address) -> u8 {
+    0x00 // write only
+  }
+
+  fn write(&mut self, _address: Address, value: u8) {
+    log::trace!("serial ULA control {value:#010b}");
+    if (value ^ self.control) & 0b1000_0000 != 0 {
+      log::info!("cassette motor {}", if value & 0b1000_0000 != 0 { "on" } else { "off" });
+    }
+    self.control = value;
+  }
+}
+
+#[test]
+fn control_register() {
+  let mut ula = SerialULA::new();
+  ula.write(Address::from(0xFE10), 0b0110_0100); // RS423, 9600 baud
+  assert_eq!(ula.tx_baud(), 9600);
+  assert_eq!(ula.rx_baud(), 9600);
+  assert!(ula.rs423());
+  assert!(!ula.motor());
+
+  ula.connect_rs423(Box::new(Loopback::default()));
+  ula.transmit(0x42);
+  assert_eq!(ula.receive(false), None); // not ready
+  assert_eq!(ula.receive(true), Some(0x42));
+  assert_eq!(ula.rx_clock(), 614_400);
+
+  use crate::cassette::Tone;
+  ula.cassette.insert(vec![Tone::Carrier(150_000), Tone::Byte(0x2A, 8333)]);
+  ula.write(Address::from(0xFE10), 0b1000_0000); // cassette, motor on
+  ula.transmit(0x42);
+  assert!(ula.motor());
+  assert_eq!(ula.rx_clock(), 19200);
+  ula.step(50_000);
+  assert!(!ula.carrier_detect());
+  ula.step(100_000);
+  assert!(ula.carrier_detect());
+  ula.step(150_000);
+  assert!(!ula.carrier_detect());
+  ula.write(Address::from(0xFE10), 0b0000_0000); // motor off
+  ula.step(200_000);
+  assert_eq!(ula.receive(false), Some(0x2A));
+  assert!(!ula.cassette.at_end());
+}
@@ -0,0 +1,284 @@
+// Texas Instruments SN76489 sound generator
+// Three square wave tone channels and a noise channel, each with a 4 bit
+// attenuator. Written over the slow data bus (system VIA port A) when IC32
+// B0 enables it. Clocked at 4MHz, divided by 16 internally: 250kHz.
+// Renders mono samples at a chosen rate, which can be saved as WAV file.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::devices::Clocked;
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+const CLOCK_HZ: u64 = 250_000; // 4MHz / 16
+const CHANNELS: usize = 4;
+const NOISE: usize = 3;
+const LFSR_RESET: u16 = 0x4000; // 15 bit shift register
+
+// 2dB per attenuation step, 15 is off
+const VOLUMES: [i16; 16] = [
+  8191, 6506, 5168, 4105, 3261, 2590, 2057, 1642,
+  1298, 1031,  819,  650,  516,  410,  326,    0,
+];
+
+#[derive(Debug)]
+pub struct SN76489 {
+  bus: u8,                        // slow data bus value
+  latched: usize,                 // register selected by last latch byte
+  tone: [u16; 3],                 // 10 bit periods
+  noise: u8,                      // b2: white noise, b1-0: shift rate
+  attenuation: [u8; CHANNELS],
+  counters: [u16; CHANNELS],
+  outputs: [bool; CHANNELS],      // flip flops
+  lfsr: u16,
+  sample_rate: Option<u32>,       // recording?
+  phase: u64,                     // sample_rate per tick, towards CLOCK_HZ
+  sum: i32,                       // mix of ticks since last sample
+  ticks: i32,
+  samples: Vec<i16>,
+  clock: u64,                     // 250kHz ticks
+}
+
+impl SN76489 {
+  pub fn new() -> Self {
+    SN76489 { bus: 0, latched: 0, tone: [0; 3], noise: 0,
+              attenuation: [0x0F; CHANNELS],
+              counters: [0; CHANNELS], outputs: [false; CHANNELS],
+              lfsr: LFSR_RESET,
+              sample_rate: None, phase: 0, sum: 0, ticks: 0,
+              samples: Vec::new(), clock: 0,
+    }
+  }
+
+  // Start collecting samples, at given rate (Hz)
+  pub fn record(&mut self, sample_rate: u32) {
+    assert!(0 < sample_rate && sample_rate as u64 <= CLOCK_HZ);
+    self.sample_rate = Some(sample_rate);
+  }
+
+  pub fn samples(&self) -> &[i16] {
+    &self.samples
+  }
+
+  pub fn take_samples(&mut self) -> Vec<i16> {
+    std::mem::take(&mut self.samples)
+  }
+
+  // Value driven on slow data bus
+  pub fn set_data_bus(&mut self, value: u8) {
+    self.bus = value;
+  }
+
+  // WE pulled low: latch value on slow data bus
+  pub fn write_enable(&mut self) {
+    self.write(self.bus);
+  }
+
+  pub fn write(&mut self, value: u8) {
+    log::trace!("sn76489 write {value:#04x}");
+    if value & 0b1000_0000 != 0 {
+      // latch: b6-5 channel, b4 attenuation, b3-0 data
+      self.latched = ((value >> 4) & 0b111) as usize;
+    }
+
+    let channel = self.latched >> 1;
+    let latch = value & 0b1000_0000 != 0;
+    match (self.latched & 1 == 1, channel) {
+      (true, _) => self.attenuation[channel] = value & 0x0F,
+      (false, NOISE) => {
+        self.noise = value & 0b111;
+        self.lfsr = LFSR_RESET;
+      },
+      (false, _) if latch => {
+        let tone = &mut self.tone[channel];
+        *tone = (*tone & 0x3F0) | (value & 0x0F) as u16;
+      },
+      (false, _) => {
+        let tone = &mut self.tone[channel];
+        *tone = (*tone & 0x00F) | ((value & 0x3F) as u16) << 4;
+      },
+    }
+  }
+
+  pub const fn tone(&self, channel: usize) -> u16 {
+    self.tone[channel]
+  }
+
+  pub const fn attenuation(&self, channel: usize) -> u8 {
+    self.attenuation[channel]
+  }
+
+  fn noise_period(&self) -> u16 {
+    match self.noise & 0b11 {
+      0b00 => 0x10,
+      0b01 => 0x20,
+      0b10 => 0x40,
+      _    => self.tone[2],
+    }
+  }
+
+  fn tick(&mut self) {
+    for channel in 0..CHANNELS {
+      let period = if channel == NOISE { self.noise_period() } else { self.tone[channel] };
+      let period = if period == 0 { 0x400 } else { period };
+      if self.counters[channel] > 1 {
+        self.counters[channel] -= 1;
+        continue;
+      }
+      self.counters[channel] = period;
+      self.outputs[channel] = !self.outputs[channel];
+      if channel == NOISE && self.outputs[channel] {
+        // shift on rising edge: white noise taps b0 and b1, periodic b0
+        let white = self.noise & 0b100 != 0;
+        let bit = if white {
+          (self.lfsr ^ (self.lfsr >> 1)) & 1
+        } else {
+          self.lfsr & 1
+        };
+        self.lfsr = (self.lfsr >> 1) | (bit << 14);
+      }
+    }
+
+    if let Some(sample_rate) = self.sample_rate {
+      self.sum += self.level() as i32;
+      self.ticks += 1;
+      self.phase += sample_rate as u64;
+      if self.phase >= CLOCK_HZ {
+        self.phase -= CLOCK_HZ;
+        self.samples.push((self.sum / self.ticks) as i16);
+        self.sum = 0;
+        self.ticks = 0;
+      }
+    }
+  }
+
+  // mix of all channels, each driving +/- volume
+  fn level(&self) -> i16 {
+    let mut level = 0;
+    for channel in 0..CHANNELS {
+      let high = if channel == NOISE {
+        self.lfsr & 1 != 0
+      } else {
+        // period 1 is steady output, as used for sample playback
+        self.outputs[channel] || self.tone[channel] == 1
+      };
+      let volume = VOLUMES[self.attenuation[channel] as usize];
+      level += if high { volume } else { -volume };
+    }
+    level
+  }
+
+  // Mono, 16 bit PCM
+  pub fn write_wav<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+    let sample_rate = self.sample_rate.unwrap_or(CLOCK_HZ as u32);
+    let data_size = (self.samples.len() * 2) as u32;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;          // chunk size
+    writer.write_all(&1u16.to_le_bytes())?;           // PCM
+    writer.write_all(&1u16.to_le_bytes())?;           // mono
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * 2).to_le_bytes())?; // bytes per second
+    writer.write_all(&2u16.to_le_bytes())?;           // block align
+    writer.write_all(&16u16.to_le_bytes())?;          // bits per sample
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in self.samples.iter() {
+      writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+  }
+
+  pub fn save_wav(&self, filename: &str) -> std::io::Result<()> {
+    let file = File::create(Path::new(filename))?;
+    let mut writer = BufWriter::new(file);
+    self.write_wav(&mut writer)?;
+    writer.flush()
+  }
+}
+
+impl Clocked for SN76489 {
+  fn step(&mut self, us: u64) {
+    let clock = us * CLOCK_HZ / 1_000_000;
+    while self.clock < clock {
+      self.tick();
+      self.clock += 1;
+    }
+  }
+}
+
+// Generator state only, recording is up to the host
+impl Snapshot for SN76489 {
+  fn save(&self, state: &mut Writer) {
+    state.bytes(&[self.bus, self.latched as u8, self.noise]);
+    self.tone.iter().for_each(|period| state.u16(*period));
+    state.bytes(&self.attenuation);
+    self.counters.iter().for_each(|counter| state.u16(*counter));
+    self.outputs.iter().for_each(|output| state.bool(*output));
+    state.u16(self.lfsr);
+    state.u64(self.clock);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    let [bus, latched, noise] = state.array()?;
+    (self.bus, self.latched, self.noise) = (bus, (latched & 0b111) as usize, noise);
+    for period in self.tone.iter_mut() {
+      *period = state.u16()?;
+    }
+    self.attenuation = state.array()?;
+    for counter in self.counters.iter_mut() {
+      *counter = state.u16()?;
+    }
+    for output in self.outputs.iter_mut() {
+      *output = state.bool()?;
+    }
+    self.lfsr = state.u16()?;
+    self.clock = state.u64()?;
+    Ok(())
+  }
+}
+
+#[test]
+fn registers() {
+  let mut sound = SN76489::new();
+  sound.write(0b1010_1110); // latch tone 2, low bits 0xE
+  sound.write(0b0011_1111); // high bits 0x3F
+  assert_eq!(sound.tone(1), 0x3FE);
+  sound.write(0b1101_0011); // attenuation 2 = 3
+  assert_eq!(sound.attenuation(2), 3);
+  sound.write(0b0000_0101); // data byte after attenuation latch
+  assert_eq!(sound.attenuation(2), 5);
+  sound.write(0b1110_0101); // noise: white, rate 1
+  assert_eq!(sound.noise_period(), 0x20);
+}
+
+#[test]
+fn tone_frequency() {
+  // 250kHz / (2 x 125) = 1kHz, at full volume
+  let mut sound = SN76489::new();
+  sound.record(50_000);
+  sound.write(0b1000_1101); // tone 0: 125 = 0x7D
+  sound.write(0b0000_0111);
+  sound.write(0b1001_0000); // attenuation 0: 0
+  sound.step(1_000_000);
+  let samples = sound.take_samples();
+  assert_eq!(samples.len(), 50_000);
+  let rising = samples.windows(2).filter(|pair| pair[0] < 0 && pair[1] >= 0).count();
+  assert!((999..=1001).contains(&rising), "{rising}");
+}
+
+#[test]
+fn wav_header() {
+  let mut sound = SN76489::new();
+  sound.record(8000);
+  sound.step(1000);
+  let mut wav = Vec::new();
+  sound.write_wav(&mut wav).unwrap();
+  assert_eq!(&wav[0..4], b"RIFF");
+  assert_eq!(&wav[8..16], b"WAVEfmt ");
+  assert_eq!(u32::from_le_bytes(wav[24..28].try_into().unwrap()), 8000);
+  assert_eq!(wav.len(), 44 + 2 * 8);
+}
@@ -0,0 +1,329 @@
+// B-em savestates (.snp), format 3 as written by B-em's savestate.c
+// "BEMSNAP3", then sections of a key byte and little endian size: 2 bytes, or
+// 3 when the key has bit 7 set, marking a zlib compressed section.
+//
+// Mapped here: 6502 ('6'), memory ('M': ROM select latches, RAM and the 16
+// ROM slots), system VIA with IC32 ('S'), user VIA ('U'), video ULA ('V') and
+// CRTC ('C'). Other sections are skipped on import and not written on export,
+// so B-em keeps its own model, sound and disc state.
+//
+// Import sets the state of the machine it goes into through its devices,
+// leaving their timing (cycles, device clocks) alone: B-em counts cycles
+// within a frame.
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use super::{invalid, select_each_bank, Reader, Writer, BANK_START, RAM_SIZE, ROM_SELECT};
+use crate::devices::ic32::IC32;
+use crate::devices::paged_rom::{RomSelect, BANKS, BANK_SIZE};
+use crate::mc6845::CRTC;
+use crate::memory::{Address, MemoryBus};
+use crate::mos6502::CPU;
+use crate::mos6502::registers::Status;
+use crate::mos6522::State;
+use crate::mos6522::system_via::SystemVIA;
+use crate::mos6522::user_via::UserVIA;
+use crate::video_ula::VideoULA;
+
+const MAGIC: &[u8] = b"BEMSNAP";
+const VERSION: u8 = b'3';
+const COMPRESSED: u8 = 0x80;
+const B_EM_RAM_SIZE: usize = 64 * 1024;
+const VIDEO_ULA_CONTROL: u16 = 0xFE20;
+const VIDEO_ULA_PALETTE: u16 = 0xFE21;
+
+const CPU_KEY: u8 = b'6';
+const MEMORY_KEY: u8 = b'M';
+const SYSTEM_VIA_KEY: u8 = b'S';
+const USER_VIA_KEY: u8 = b'U';
+const VIDEO_ULA_KEY: u8 = b'V';
+const CRTC_KEY: u8 = b'C';
+
+fn read_sections(data: &[u8]) -> io::Result<Vec<(u8, Vec<u8>)>> {
+  let mut data = Reader::new(data);
+  let magic = data.bytes(MAGIC.len() + 1).map_err(|_| invalid("not a B-em savestate"))?;
+  if &magic[..MAGIC.len()] != MAGIC {
+    return Err(invalid("not a B-em savestate"));
+  }
+  if magic[MAGIC.len()] != VERSION {
+    return Err(invalid(&format!("B-em savestate version {} not supported",
+                                magic[MAGIC.len()] as char)));
+  }
+
+  let mut sections = Vec::new();
+  while !data.0.is_empty() {
+    let key = data.u8()?;
+    let mut size = data.u16()? as usize;
+    if key & COMPRESSED != 0 {
+      size |= (data.u8()? as usize) << 16;
+    }
+    let bytes = data.bytes(size)?;
+    if key & COMPRESSED != 0 {
+      let mut inflated = Vec::new();
+      ZlibDecoder::new(bytes).read_to_end(&mut inflated)?;
+      sections.push((key & !COMPRESSED, inflated));
+    } else {
+      sections.push((key, bytes.to_vec()));
+    }
+  }
+  Ok(sections)
+}
+
+fn write_section<W: Write>(output: &mut W, key: u8, data: &[u8]) -> io::Result<()> {
+  output.write_all(&[key])?;
+  output.write_all(&(data.len() as u16).to_le_bytes())?;
+  output.write_all(data)
+}
+
+fn write_compressed<W: Write>(output: &mut W, key: u8, data: &[u8]) -> io::Result<()> {
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(data)?;
+  let data = encoder.finish()?;
+  output.write_all(&[key | COMPRESSED])?;
+  output.write_all(&(data.len() as u32).to_le_bytes()[..3])?;
+  output.write_all(&data)
+}
+
+// The devices B-em sections map onto, see `SheilaPage::get_b_em_devices`
+pub struct Devices {
+  pub crtc: Rc<RefCell<CRTC>>,
+  pub video_ula: Rc<RefCell<VideoULA>>,
+  pub rom_select: Rc<RefCell<RomSelect>>,
+  pub system_via: Rc<RefCell<SystemVIA>>,
+  pub user_via: Rc<RefCell<UserVIA>>,
+  pub ic32: Rc<IC32>,
+}
+
+fn cpu_from_b_em(cpu: &mut CPU, state: &mut Reader) -> io::Result<()> {
+  let [a, x, y, p, s] = state.array()?;
+  let pc = state.u16()?;
+  let registers = &mut cpu.registers;
+  (registers.a, registers.x, registers.y) = (a, x, y);
+  registers.p = Status::from(p);
+  *registers.s.borrow_mut() = s;
+  registers.pc = Address::from(pc);
+  Ok(()) // then pending NMI and IRQ, frame cycles
+}
+
+fn cpu_to_b_em(cpu: &CPU, state: &mut Writer) {
+  let registers = &cpu.registers;
+  state.bytes(&[registers.a, registers.x, registers.y,
+                registers.p.to_u8() | 0x30, // as pushed
+                registers.s.to_u8()]);
+  state.u16(registers.pc.to_u16());
+  state.bytes(&[0, 0, 0, 0, 0, 0]); // no pending NMI or IRQ, frame start
+}
+
+// RAM size differs between models: what is left after the select latches
+// and ROM slots
+fn memory_from_b_em(memory: &mut dyn MemoryBus, data: &[u8]) -> io::Result<()> {
+  let roms = BANKS * BANK_SIZE;
+  if data.len() < 2 + RAM_SIZE + roms {
+    return Err(invalid("B-em memory section too short"));
+  }
+  let (select, rest) = data.split_at(2);
+  let (ram, banks) = rest.split_at(rest.len() - roms);
+  for (address, value) in ram[..RAM_SIZE].iter().enumerate() {
+    memory.write(Address::from(address as u16), *value);
+  }
+  let mut banks = banks.chunks(BANK_SIZE);
+  select_each_bank(memory, |memory| {
+    for (offset, value) in banks.next().unwrap().iter().enumerate() {
+      memory.write(Address::from(BANK_START + offset as u16), *value);
+    }
+    Ok(())
+  })?;
+  memory.write(Address::from(ROM_SELECT), select[0]);
+  Ok(())
+}
+
+fn memory_to_b_em(memory: &mut dyn MemoryBus, selected: u8) -> io::Result<Vec<u8>> {
+  let mut data = vec![selected, 0];
+  data.extend((0..RAM_SIZE as u16).map(|address| memory.read(Address::from(address))));
+  data.resize(2 + B_EM_RAM_SIZE, 0);
+  select_each_bank(memory, |memory| {
+    data.extend((0..BANK_SIZE as u16).map(|offset| memory.read(Address::from(BANK_START + offset))));
+    Ok(())
+  })?;
+  memory.write(Address::from(ROM_SELECT), selected);
+  Ok(data)
+}
+
+// B-em counts timers in 2MHz cycles: latches doubled, counters doubled
+// less one
+fn via_from_b_em(mut via: State, state: &mut Reader) -> io::Result<State> {
+  let [ora, orb, _ira, _irb, _port_a, _port_b, ddra, ddrb, sr, acr, pcr, ifr, ier] =
+    state.array()?;
+  let mut timer = || -> io::Result<i32> { Ok(i32::from_le_bytes(state.array()?)) };
+  let [t1l, t2l, t1c, t2c] = [timer()?, timer()?, timer()?, timer()?];
+  let [t1hit, t2hit, ca1, ca2] = state.array()?;
+  let counter = |count: i32| if count < -1 { 0xFFFF } else { ((count + 1) >> 1) as u16 };
+  (via.iora, via.iorb, via.ddra, via.ddrb) = (ora, orb, ddra, ddrb);
+  (via.t1l, via.t2l) = ((t1l >> 1) as u16, (t2l >> 1) as u16);
+  (via.t1c, via.t2c) = (counter(t1c), counter(t2c));
+  (via.sr, via.acr, via.pcr, via.ifr, via.ier) = (sr, acr, pcr, ifr, ier & 0x7F);
+  (via.ca1, via.ca2) = (ca1 != 0, ca2 != 0);
+  (via.t1_active, via.t2_active) = (t1hit == 0, t2hit == 0);
+  Ok(via)
+}
+
+fn via_to_b_em(via: &State, port_a: u8, port_b: u8, state: &mut Writer) {
+  state.bytes(&[via.iora, via.iorb, port_a, port_b, port_a, port_b, via.ddra, via.ddrb,
+                via.sr, via.acr, via.pcr, via.ifr, via.ier]);
+  for timer in [via.t1l, via.t2l, via.t1c, via.t2c] {
+    state.bytes(&(timer as i32 * 2).to_le_bytes());
+  }
+  state.bytes(&[!via.t1_active as u8, !via.t2_active as u8, via.ca1 as u8, via.ca2 as u8]);
+}
+
+// Logical colours 0-15 as written to the palette register
+fn video_ula_from_b_em(video_ula: &mut VideoULA, state: &mut Reader) -> io::Result<()> {
+  let control = state.u8()?;
+  let palette: [u8; 16] = state.array()?;
+  video_ula.write(Address::from(VIDEO_ULA_CONTROL), control);
+  for (logical, colour) in palette.into_iter().enumerate() {
+    video_ula.write(Address::from(VIDEO_ULA_PALETTE), (logical as u8) << 4 | colour & 0x0F);
+  }
+  Ok(())
+}
+
+// Registers, then vertical, scan line and horizontal counters, memory
+// address of the character and of the row
+fn crtc_from_b_em(crtc: &mut CRTC, state: &mut Reader) -> io::Result<()> {
+  let registers = state.array()?;
+  let [row, line, character] = state.array()?;
+  let memory_address = state.u16()?;
+  let row_address = state.u16()?;
+  crtc.set_registers(registers);
+  crtc.set_counters((character, line, row));
+  crtc.set_addresses(row_address, memory_address);
+  Ok(())
+}
+
+fn crtc_to_b_em(crtc: &CRTC, state: &mut Writer) {
+  let (character, line, row) = crtc.counters();
+  state.bytes(&crtc.registers());
+  state.bytes(&[row, line, character]);
+  state.u16(crtc.memory_address());
+  state.u16(crtc.row_address());
+}
+
+pub fn import<R: Read>(mut input: R, cpu: &mut CPU, memory: &mut dyn MemoryBus,
+                       devices: &Devices) -> io::Result<()> {
+  let mut data = Vec::new();
+  input.read_to_end(&mut data)?;
+  for (key, data) in read_sections(&data)?.iter() {
+    let mut state = Reader::new(data);
+    match *key {
+      CPU_KEY => cpu_from_b_em(cpu, &mut state)?,
+      MEMORY_KEY => memory_from_b_em(memory, data)?,
+      SYSTEM_VIA_KEY => {
+        let mut system_via = devices.system_via.borrow_mut();
+        let via = via_from_b_em(system_via.state(), &mut state)?;
+        let ic32 = state.u8()?;
+        system_via.set_state(via);
+        system_via.set_latches(via.iora, via.iorb);
+        devices.ic32.set_latch(ic32);
+      },
+      USER_VIA_KEY => {
+        let mut user_via = devices.user_via.borrow_mut();
+        let via = via_from_b_em(user_via.state(), &mut state)?;
+        user_via.set_state(via);
+        user_via.set_printer_data(via.iora);
+      },
+      VIDEO_ULA_KEY => video_ula_from_b_em(&mut devices.video_ula.borrow_mut(), &mut state)?,
+      CRTC_KEY => crtc_from_b_em(&mut devices.crtc.borrow_mut(), &mut state)?,
+      _ => log::debug!("B-em savestate section {} skipped", *key as char),
+    }
+  }
+  Ok(())
+}
+
+pub fn export<W: Write>(mut output: W, cpu: &CPU, memory: &mut dyn MemoryBus,
+                        devices: &Devices) -> io::Result<()> {
+  output.write_all(MAGIC)?;
+  output.write_all(&[VERSION])?;
+
+  let mut state = Writer::default();
+  cpu_to_b_em(cpu, &mut state);
+  write_section(&mut output, CPU_KEY, &state.0)?;
+
+  let selected = devices.rom_select.borrow().bank();
+  write_compressed(&mut output, MEMORY_KEY, &memory_to_b_em(memory, selected)?)?;
+
+  let system_via = devices.system_via.borrow();
+  let (pa, pb) = system_via.latches();
+  let mut state = Writer::default();
+  via_to_b_em(&system_via.state(), pa, pb, &mut state);
+  state.u8(devices.ic32.latch());
+  write_section(&mut output, SYSTEM_VIA_KEY, &state.0)?;
+
+  let user_via = devices.user_via.borrow();
+  let mut state = Writer::default();
+  via_to_b_em(&user_via.state(), user_via.printer_data(), 0xFF, &mut state); // user port pulled up
+  write_section(&mut output, USER_VIA_KEY, &state.0)?;
+
+  let video_ula = devices.video_ula.borrow();
+  let mut state = Writer::default();
+  state.u8(video_ula.control());
+  state.bytes(&video_ula.palette());
+  write_section(&mut output, VIDEO_ULA_KEY, &state.0)?;
+
+  let mut state = Writer::default();
+  crtc_to_b_em(&devices.crtc.borrow(), &mut state);
+  write_section(&mut output, CRTC_KEY, &state.0)?;
+  output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn via_timers() {
+    let mut state = Writer::default();
+    state.bytes(&[0x12, 0x34, 0, 0, 0, 0, 0xFF, 0x0F, 0, 0x40, 0x04, 0xC0, 0x40]);
+    for timer in [0x4E1E_i32, 0x1FFFE, 0x2710, -2] {
+      state.bytes(&timer.to_le_bytes());
+    }
+    state.bytes(&[0, 1, 1, 0]);
+
+    let before = State { cb1: true, ..State::default() };
+    let imported = via_from_b_em(before, &mut Reader::new(&state.0)).unwrap();
+    assert_eq!([imported.iora, imported.iorb, imported.ddra, imported.ddrb], [0x12, 0x34, 0xFF, 0x0F]);
+    assert_eq!([imported.t1l, imported.t2l, imported.t1c, imported.t2c],
+               [0x270F, 0xFFFF, 0x1388, 0xFFFF]); // 9999: 50Hz
+    assert_eq!([imported.sr, imported.acr, imported.pcr, imported.ifr, imported.ier],
+               [0, 0x40, 0x04, 0xC0, 0x40]);
+    assert_eq!([imported.ca1, imported.ca2, imported.cb1, imported.cb2], [true, false, true, false]);
+    assert_eq!([imported.t1_active, imported.t2_active], [true, false]);
+
+    let mut exported = Writer::default();
+    via_to_b_em(&imported, 0x12, 0x34, &mut exported);
+    assert_eq!(exported.0.len(), 13 + 16 + 4);
+    let again = via_from_b_em(State::default(), &mut Reader::new(&exported.0)).unwrap();
+    assert_eq!([again.t1l, again.t2l, again.t1c], [imported.t1l, imported.t2l, imported.t1c]);
+    assert_eq!([again.t1_active, again.t2_active], [imported.t1_active, imported.t2_active]);
+  }
+
+  #[test]
+  fn sections() {
+    let mut file = b"BEMSNAP3".to_vec();
+    write_section(&mut file, b'6', &[1, 2, 3]).unwrap();
+    write_compressed(&mut file, b'M', &[0x55; 1000]).unwrap();
+    assert_eq!(file[8..14], [b'6', 3, 0, 1, 2, 3]);
+    assert_eq!(file[14], b'M' | COMPRESSED);
+    let sections = read_sections(&file).unwrap();
+    assert_eq!(sections, [(b'6', vec![1, 2, 3]), (b'M', vec![0x55; 1000])]);
+
+    let error = read_sections(b"BEMSNAP1").unwrap_err();
+    assert_eq!(error.to_string(), "B-em savestate version 1 not supported");
+    assert_eq!(read_sections(b"BBC").unwrap_err().to_string(), "not a B-em savestate");
+  }
+}
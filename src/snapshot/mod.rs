@@ -0,0 +1,281 @@
+// Machine state snapshots, to resume a session or skip booting in tests
+// A gzipped file: magic, 16 bit version, then sections of 4 byte tag, 32 bit
+// length and data, like UEF chunks. Sections this version doesn't know are
+// skipped, missing ones leave that part of the machine as it is.
+//
+// Saved: CPU registers and cycles, RAM, sideways banks, and the devices
+// SHEILA lists (CRTC, ACIA, serial and video ULAs, ROM select, VIAs with
+// IC32, sound, ADC, keyboard matrix). ROM images, media and host connections
+// are not: restore into a machine freshly built the same way, before stepping
+// it, as devices expect their clock never to go backwards.
+
+pub mod b_em; // B-em .snp savestates
+
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use crate::devices::paged_rom::{BANKS, BANK_SIZE};
+use crate::memory::{Address, MemoryBus};
+use crate::mos6502::CPU;
+
+const MAGIC: &[u8] = b"BBC-B snapshot\0";
+pub const VERSION: u16 = 1;
+
+pub type Tag = [u8; 4];
+const CPU_TAG: Tag = *b"CPU ";
+const RAM_TAG: Tag = *b"RAM ";
+const SIDEWAYS_TAG: Tag = *b"SWRB";
+pub const CRTC_TAG: Tag = *b"CRTC";
+pub const ACIA_TAG: Tag = *b"ACIA";
+pub const SERIAL_ULA_TAG: Tag = *b"SULA";
+pub const VIDEO_ULA_TAG: Tag = *b"VULA";
+pub const ROM_SELECT_TAG: Tag = *b"ROMS";
+pub const SYSTEM_VIA_TAG: Tag = *b"SVIA"; // and IC32
+pub const USER_VIA_TAG: Tag = *b"UVIA";
+pub const SOUND_TAG: Tag = *b"SND ";
+pub const ADC_TAG: Tag = *b"ADC ";
+pub const KEYBOARD_TAG: Tag = *b"KEYB";
+
+const RAM_SIZE: usize = 0x8000;
+const BANK_START: u16 = 0x8000;
+const ROM_SELECT: u16 = 0xFE30;
+
+fn invalid(message: &str) -> io::Error {
+  io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Little endian fields of a section
+#[derive(Debug, Default)]
+pub struct Writer(Vec<u8>);
+
+impl Writer {
+  pub fn u8(&mut self, value: u8) {
+    self.0.push(value);
+  }
+
+  pub fn bool(&mut self, value: bool) {
+    self.0.push(value as u8);
+  }
+
+  pub fn u16(&mut self, value: u16) {
+    self.0.extend_from_slice(&value.to_le_bytes());
+  }
+
+  pub fn u64(&mut self, value: u64) {
+    self.0.extend_from_slice(&value.to_le_bytes());
+  }
+
+  pub fn bytes(&mut self, bytes: &[u8]) {
+    self.0.extend_from_slice(bytes);
+  }
+}
+
+#[derive(Debug)]
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+  pub const fn new(data: &'a [u8]) -> Self {
+    Reader(data)
+  }
+
+  pub fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+    if self.0.len() < count {
+      return Err(invalid("snapshot section too short"));
+    }
+    let (bytes, rest) = self.0.split_at(count);
+    self.0 = rest;
+    Ok(bytes)
+  }
+
+  pub fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+    Ok(self.bytes(N)?.try_into().unwrap())
+  }
+
+  pub fn u8(&mut self) -> io::Result<u8> {
+    Ok(self.bytes(1)?[0])
+  }
+
+  pub fn bool(&mut self) -> io::Result<bool> {
+    Ok(self.u8()? != 0)
+  }
+
+  pub fn u16(&mut self) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(self.array()?))
+  }
+
+  pub fn u64(&mut self) -> io::Result<u64> {
+    Ok(u64::from_le_bytes(self.array()?))
+  }
+}
+
+pub trait Snapshot {
+  fn save(&self, state: &mut Writer);
+  fn restore(&mut self, state: &mut Reader) -> io::Result<()>;
+}
+
+pub type SnapshotDevices = Vec<(Tag, Rc<RefCell<dyn Snapshot>>)>;
+
+fn write_section<W: Write>(output: &mut W, tag: &Tag, data: &[u8]) -> io::Result<()> {
+  output.write_all(tag)?;
+  output.write_all(&(data.len() as u32).to_le_bytes())?;
+  output.write_all(data)
+}
+
+fn saved(device: &dyn Snapshot) -> Vec<u8> {
+  let mut state = Writer::default();
+  device.save(&mut state);
+  state.0
+}
+
+// Sideways banks are reached through the bus, selecting each in turn, so
+// that writes to ROM banks are ignored as the hardware would. Leaves the last
+// bank selected
+fn select_each_bank<F>(memory: &mut dyn MemoryBus, mut f: F) -> io::Result<()>
+where F: FnMut(&mut dyn MemoryBus) -> io::Result<()> {
+  for bank in 0..BANKS {
+    memory.write(Address::from(ROM_SELECT), bank as u8);
+    f(memory)?;
+  }
+  Ok(())
+}
+
+// and then the bank that was selected
+fn for_each_bank<F>(memory: &mut dyn MemoryBus, devices: &SnapshotDevices, f: F)
+                    -> io::Result<()>
+where F: FnMut(&mut dyn MemoryBus) -> io::Result<()> {
+  let select = devices.iter().find(|(tag, _)| *tag == ROM_SELECT_TAG)
+    .map(|(_, device)| (device, saved(&*device.borrow())));
+  let result = select_each_bank(memory, f);
+  if let Some((device, state)) = select {
+    device.borrow_mut().restore(&mut Reader::new(&state))?;
+  }
+  result
+}
+
+// Tagged state, as in the file
+type Sections = Vec<(Tag, Vec<u8>)>;
+
+fn save_sections(cpu: &CPU, memory: &mut dyn MemoryBus, devices: &SnapshotDevices)
+                 -> io::Result<Sections> {
+  let mut sections = vec![(CPU_TAG, saved(cpu))];
+  let ram = (0..RAM_SIZE as u16).map(|address| memory.read(Address::from(address))).collect();
+  sections.push((RAM_TAG, ram));
+
+  let mut banks = Vec::with_capacity(BANKS * BANK_SIZE);
+  for_each_bank(memory, devices, |memory| {
+    banks.extend((0..BANK_SIZE as u16).map(|offset| memory.read(Address::from(BANK_START + offset))));
+    Ok(())
+  })?;
+  sections.push((SIDEWAYS_TAG, banks));
+
+  for (tag, device) in devices.iter() {
+    sections.push((*tag, saved(&*device.borrow())));
+  }
+  Ok(sections)
+}
+
+fn restore_sections(sections: &Sections, cpu: &mut CPU, memory: &mut dyn MemoryBus,
+                    devices: &SnapshotDevices) -> io::Result<()> {
+  let section = |wanted: Tag| sections.iter().find(|(tag, _)| *tag == wanted)
+    .map(|(_, bytes)| Reader::new(bytes));
+
+  if let Some(mut state) = section(CPU_TAG) {
+    cpu.restore(&mut state)?;
+  }
+  if let Some(mut state) = section(RAM_TAG) {
+    for (address, value) in state.bytes(RAM_SIZE)?.iter().enumerate() {
+      memory.write(Address::from(address as u16), *value);
+    }
+  }
+  if let Some(mut state) = section(SIDEWAYS_TAG) {
+    for_each_bank(memory, devices, |memory| {
+      for (offset, value) in state.bytes(BANK_SIZE)?.iter().enumerate() {
+        memory.write(Address::from(BANK_START + offset as u16), *value);
+      }
+      Ok(())
+    })?;
+  }
+  for (tag, device) in devices.iter() {
+    if let Some(mut state) = section(*tag) {
+      device.borrow_mut().restore(&mut state)?;
+    }
+  }
+  Ok(())
+}
+
+pub fn save<W: Write>(output: W, cpu: &CPU, memory: &mut dyn MemoryBus,
+                      devices: &SnapshotDevices) -> io::Result<()> {
+  let mut output = GzEncoder::new(output, Compression::default());
+  output.write_all(MAGIC)?;
+  output.write_all(&VERSION.to_le_bytes())?;
+  for (tag, data) in save_sections(cpu, memory, devices)?.iter() {
+    write_section(&mut output, tag, data)?;
+  }
+  output.finish()?.flush()
+}
+
+pub fn restore<R: Read>(input: R, cpu: &mut CPU, memory: &mut dyn MemoryBus,
+                        devices: &SnapshotDevices) -> io::Result<()> {
+  let mut data = Vec::new();
+  GzDecoder::new(input).read_to_end(&mut data)?;
+  let mut data = Reader::new(&data);
+  if data.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+    return Err(invalid("not a BBC-B snapshot"));
+  }
+  let version = data.u16()?;
+  if version > VERSION {
+    return Err(invalid(&format!("snapshot version {version} is newer than {VERSION}")));
+  }
+
+  let mut sections = Sections::new();
+  while !data.0.is_empty() {
+    let tag: Tag = data.array()?;
+    let length = u32::from_le_bytes(data.array()?) as usize;
+    sections.push((tag, data.bytes(length)?.to_vec()));
+  }
+  restore_sections(&sections, cpu, memory, devices)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fields() {
+    let mut state = Writer::default();
+    state.u8(0x12);
+    state.bool(true);
+    state.u16(0x3456);
+    state.u64(0x0102_0304_0506_0708);
+    state.bytes(b"abc");
+    assert_eq!(state.0[..4], [0x12, 1, 0x56, 0x34]);
+
+    let mut state = Reader::new(&state.0);
+    assert_eq!(state.u8().unwrap(), 0x12);
+    assert!(state.bool().unwrap());
+    assert_eq!(state.u16().unwrap(), 0x3456);
+    assert_eq!(state.u64().unwrap(), 0x0102_0304_0506_0708);
+    assert_eq!(state.array::<3>().unwrap(), *b"abc");
+    assert_eq!(state.u8().unwrap_err().kind(), io::ErrorKind::InvalidData);
+  }
+
+  #[test]
+  fn bad_files() {
+    let mut cpu = CPU::new();
+    let mut ram = crate::memory::ram::RAM::new();
+    let error = restore(&b"not gzipped"[..], &mut cpu, &mut ram, &Vec::new()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+
+    let mut file = GzEncoder::new(Vec::new(), Compression::default());
+    file.write_all(MAGIC).unwrap();
+    file.write_all(&(VERSION + 1).to_le_bytes()).unwrap();
+    let file = file.finish().unwrap();
+    let error = restore(&file[..], &mut cpu, &mut ram, &Vec::new()).unwrap_err();
+    assert_eq!(error.to_string(), "snapshot version 2 is newer than 1");
+  }
+}
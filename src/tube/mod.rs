@@ -0,0 +1,410 @@
+// Tube ULA
+// Connects the BBC (host) to a second processor (parasite) through four
+// byte-wide FIFO registers in each direction. The host sees them at SHEILA
+// &E0–&E7, the parasite at &FEF8–&FEFF. Flags written by the host to the R1
+// status register enable interrupts on either side.
+//
+//  R1  parasite to host 24 bytes, host to parasite 1 byte; OS characters, events
+//  R2  1 byte each way; commands and replies
+//  R3  1 or 2 bytes each way; data transfers, parasite NMI
+//  R4  1 byte each way; transfer set up, host IRQ, parasite IRQ
+
+pub mod parasite;
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use crate::devices::{Clocked, Device, Signal};
+use crate::memory::{Address, MemoryBus};
+
+// Status registers
+const DATA_AVAILABLE: u8 = 0b1000_0000; // N
+const NOT_FULL: u8       = 0b0100_0000; // F
+
+// Flags, written to host R1 status register with S in b7 to set, clear to clear
+const Q: u8 = 0b0000_0001; // host IRQ from R4
+const I: u8 = 0b0000_0010; // parasite IRQ from R1
+const J: u8 = 0b0000_0100; // parasite IRQ from R4
+const M: u8 = 0b0000_1000; // parasite NMI from R3
+const V: u8 = 0b0001_0000; // two byte R3
+const P: u8 = 0b0010_0000; // parasite reset
+const T: u8 = 0b0100_0000; // clear all Tube registers
+const S: u8 = 0b1000_0000; // set flags
+
+const R1_PARASITE_TO_HOST: usize = 24;
+
+#[derive(Debug)]
+struct Registers {
+  host_status: [u8; 4],
+  parasite_status: [u8; 4],
+  r1_to_host: VecDeque<u8>,
+  r1_to_parasite: u8,
+  r2_to_host: u8,
+  r2_to_parasite: u8,
+  r3_to_host: VecDeque<u8>,
+  r3_to_parasite: VecDeque<u8>,
+  r4_to_host: u8,
+  r4_to_parasite: u8,
+}
+
+impl Registers {
+  fn new() -> Self {
+    Registers {
+      host_status: [NOT_FULL, NOT_FULL, DATA_AVAILABLE | NOT_FULL, NOT_FULL],
+      parasite_status: [NOT_FULL; 4],
+      r1_to_host: VecDeque::new(),
+      r1_to_parasite: 0,
+      r2_to_host: 0,
+      r2_to_parasite: 0,
+      // R3 comes out of reset holding a byte for the host
+      r3_to_host: VecDeque::from([0]),
+      r3_to_parasite: VecDeque::new(),
+      r4_to_host: 0,
+      r4_to_parasite: 0,
+    }
+  }
+}
+
+//  &E0–&FF Tube ULA Tube system interface
+#[derive(Debug)]
+pub struct TubeULA {
+  pub irq: Rc<Signal>,          // host, shared, hard-wired to other IRQ sources
+  pub parasite_irq: Rc<Signal>,
+  pub parasite_nmi: Rc<Signal>,
+  flags: Cell<u8>,
+  registers: RefCell<Registers>,
+  nmi: Cell<bool>,              // parasite NMI level, raised on edge
+}
+
+impl TubeULA {
+  pub fn new() -> Self {
+    TubeULA {
+      irq: Rc::new(Signal::new()),
+      parasite_irq: Rc::new(Signal::new()),
+      parasite_nmi: Rc::new(Signal::new()),
+      flags: Cell::new(0),
+      registers: RefCell::new(Registers::new()),
+      nmi: Cell::new(false),
+    }
+  }
+
+  pub fn flags(&self) -> u8 {
+    self.flags.get()
+  }
+
+  // P flag holds the parasite in reset
+  pub fn parasite_reset(&self) -> bool {
+    self.flags.get() & P != 0
+  }
+
+  fn r3_width(&self) -> usize {
+    if self.flags.get() & V != 0 { 2 } else { 1 }
+  }
+
+  pub fn update_interrupts(&self) {
+    let flags = self.flags.get();
+    let registers = self.registers.borrow();
+    let host = &registers.host_status;
+    let parasite = &registers.parasite_status;
+    if flags & Q != 0 && host[3] & DATA_AVAILABLE != 0 {
+      self.irq.raise();
+    }
+    if flags & I != 0 && parasite[0] & DATA_AVAILABLE != 0
+      || flags & J != 0 && parasite[3] & DATA_AVAILABLE != 0 {
+      self.parasite_irq.raise();
+    }
+    // R3 data to take, or room for data to send
+    let nmi = flags & M != 0 && (registers.r3_to_parasite.len() >= self.r3_width()
+                                 || registers.r3_to_host.is_empty());
+    if nmi && !self.nmi.get() {
+      self.parasite_nmi.raise();
+    }
+    self.nmi.set(nmi);
+  }
+
+  // Host side, SHEILA &E0–&E7
+  pub fn host_read(&self, register: u8) -> u8 {
+    let mut registers = self.registers.borrow_mut();
+    let r = &mut *registers;
+    let value = match register & 7 {
+      0 => r.host_status[0] & (DATA_AVAILABLE | NOT_FULL) | self.flags.get(),
+      1 => {
+        let value = r.r1_to_host.front().copied().unwrap_or(0);
+        if r.r1_to_host.pop_front().is_some() {
+          r.parasite_status[0] |= NOT_FULL;
+        }
+        if r.r1_to_host.is_empty() {
+          r.host_status[0] &= !DATA_AVAILABLE;
+        }
+        value
+      },
+      3 => {
+        if r.host_status[1] & DATA_AVAILABLE != 0 {
+          r.host_status[1] &= !DATA_AVAILABLE;
+          r.parasite_status[1] |= NOT_FULL;
+        }
+        r.r2_to_host
+      },
+      5 => {
+        let value = r.r3_to_host.front().copied().unwrap_or(0);
+        if r.r3_to_host.pop_front().is_some() && r.r3_to_host.is_empty() {
+          r.host_status[2] &= !DATA_AVAILABLE;
+          r.parasite_status[2] |= NOT_FULL;
+        }
+        value
+      },
+      7 => {
+        if r.host_status[3] & DATA_AVAILABLE != 0 {
+          r.host_status[3] &= !DATA_AVAILABLE;
+          r.parasite_status[3] |= NOT_FULL;
+        }
+        r.r4_to_host
+      },
+      status => r.host_status[status as usize / 2],
+    };
+    drop(registers);
+    self.update_interrupts();
+    value
+  }
+
+  pub fn host_write(&mut self, register: u8, value: u8) {
+    let width = self.r3_width();
+    let r = self.registers.get_mut();
+    match register & 7 {
+      0 => {
+        let bits = value & !(S | T);
+        let flags = self.flags.get();
+        self.flags.set(if value & S != 0 { flags | bits } else { flags & !bits });
+        if value & S != 0 && value & T != 0 {
+          log::debug!("Tube registers cleared");
+          *r = Registers::new();
+        }
+      },
+      1 => {
+        r.r1_to_parasite = value;
+        r.parasite_status[0] |= DATA_AVAILABLE;
+        r.host_status[0] &= !NOT_FULL;
+      },
+      3 => {
+        r.r2_to_parasite = value;
+        r.parasite_status[1] |= DATA_AVAILABLE;
+        r.host_status[1] &= !NOT_FULL;
+      },
+      5 => {
+        if width == 1 {
+          r.r3_to_parasite.clear();
+        }
+        if r.r3_to_parasite.len() < width {
+          r.r3_to_parasite.push_back(value);
+        }
+        if r.r3_to_parasite.len() == width {
+          r.parasite_status[2] |= DATA_AVAILABLE;
+          r.host_status[2] &= !NOT_FULL;
+        }
+      },
+      7 => {
+        r.r4_to_parasite = value;
+        r.parasite_status[3] |= DATA_AVAILABLE;
+        r.host_status[3] &= !NOT_FULL;
+      },
+      _ => {}, // status registers 2-4 read only
+    }
+    self.update_interrupts();
+  }
+
+  // Parasite side, &FEF8–&FEFF
+  pub fn parasite_read(&self, register: u8) -> u8 {
+    let mut registers = self.registers.borrow_mut();
+    let r = &mut *registers;
+    let value = match register & 7 {
+      0 => r.parasite_status[0] & (DATA_AVAILABLE | NOT_FULL) | self.flags.get(),
+      1 => {
+        if r.parasite_status[0] & DATA_AVAILABLE != 0 {
+          r.parasite_status[0] &= !DATA_AVAILABLE;
+          r.host_status[0] |= NOT_FULL;
+        }
+        r.r1_to_parasite
+      },
+      3 => {
+        if r.parasite_status[1] & DATA_AVAILABLE != 0 {
+          r.parasite_status[1] &= !DATA_AVAILABLE;
+          r.host_status[1] |= NOT_FULL;
+        }
+        r.r2_to_parasite
+      },
+      5 => {
+        let value = r.r3_to_parasite.front().copied().unwrap_or(0);
+        if r.r3_to_parasite.pop_front().is_some() && r.r3_to_parasite.is_empty() {
+          r.parasite_status[2] &= !DATA_AVAILABLE;
+          r.host_status[2] |= NOT_FULL;
+        }
+        value
+      },
+      7 => {
+        if r.parasite_status[3] & DATA_AVAILABLE != 0 {
+          r.parasite_status[3] &= !DATA_AVAILABLE;
+          r.host_status[3] |= NOT_FULL;
+        }
+        r.r4_to_parasite
+      },
+      status => r.parasite_status[status as usize / 2],
+    };
+    drop(registers);
+    self.update_interrupts();
+    value
+  }
+
+  pub fn parasite_write(&self, register: u8, value: u8) {
+    let mut registers = self.registers.borrow_mut();
+    let r = &mut *registers;
+    match register & 7 {
+      1 => {
+        if r.r1_to_host.len() < R1_PARASITE_TO_HOST {
+          r.r1_to_host.push_back(value);
+          r.host_status[0] |= DATA_AVAILABLE;
+        }
+        if r.r1_to_host.len() == R1_PARASITE_TO_HOST {
+          r.parasite_status[0] &= !NOT_FULL;
+        }
+      },
+      3 => {
+        r.r2_to_host = value;
+        r.host_status[1] |= DATA_AVAILABLE;
+        r.parasite_status[1] &= !NOT_FULL;
+      },
+      5 => {
+        let width = self.r3_width();
+        if width == 1 {
+          r.r3_to_host.clear();
+        }
+        if r.r3_to_host.len() < width {
+          r.r3_to_host.push_back(value);
+        }
+        if r.r3_to_host.len() == width {
+          r.host_status[2] |= DATA_AVAILABLE;
+          r.parasite_status[2] &= !NOT_FULL;
+        }
+      },
+      7 => {
+        r.r4_to_host = value;
+        r.host_status[3] |= DATA_AVAILABLE;
+        r.parasite_status[3] &= !NOT_FULL;
+      },
+      _ => {}, // status registers read only
+    }
+    drop(registers);
+    self.update_interrupts();
+  }
+}
+
+impl Device for TubeULA {
+  fn name(&self) -> &'static str { "Tube ULA Tube system interface" }
+}
+
+impl MemoryBus for TubeULA {
+  fn read(&self, address: Address) -> u8 {
+    self.host_read(address.lo_u8())
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    self.host_write(address.lo_u8(), value);
+  }
+}
+
+impl Clocked for TubeULA {
+  fn step(&mut self, _us: u64) {
+    self.update_interrupts(); // keep IRQs raised while data waits
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn status_and_flags() {
+    let mut tube = TubeULA::new();
+    assert_eq!(tube.host_read(0), NOT_FULL);
+    assert_eq!(tube.host_read(4), DATA_AVAILABLE | NOT_FULL); // R3 after reset
+    tube.host_write(0, S | Q | V);
+    assert_eq!(tube.host_read(0), NOT_FULL | Q | V);
+    assert_eq!(tube.parasite_read(0), NOT_FULL | Q | V);
+    tube.host_write(0, Q);
+    assert_eq!(tube.flags(), V);
+    tube.host_write(0, S | P);
+    assert!(tube.parasite_reset());
+  }
+
+  #[test]
+  fn r1_fifo() {
+    let mut tube = TubeULA::new();
+    for byte in 0..R1_PARASITE_TO_HOST as u8 {
+      assert_eq!(tube.parasite_read(0) & NOT_FULL, NOT_FULL);
+      tube.parasite_write(1, byte);
+    }
+    assert_eq!(tube.parasite_read(0) & NOT_FULL, 0);
+    for byte in 0..R1_PARASITE_TO_HOST as u8 {
+      assert_eq!(tube.host_read(0) & DATA_AVAILABLE, DATA_AVAILABLE);
+      assert_eq!(tube.host_read(1), byte);
+    }
+    assert_eq!(tube.host_read(0) & DATA_AVAILABLE, 0);
+
+    // host to parasite, with parasite IRQ
+    tube.host_write(0, S | I);
+    tube.host_write(1, 0x42);
+    assert_eq!(tube.host_read(0) & NOT_FULL, 0);
+    assert!(tube.parasite_irq.sense());
+    assert_eq!(tube.parasite_read(0) & DATA_AVAILABLE, DATA_AVAILABLE);
+    assert_eq!(tube.parasite_read(1), 0x42);
+    assert_eq!(tube.host_read(0) & NOT_FULL, NOT_FULL);
+    tube.parasite_irq.sense(); // still raised while data waited
+    tube.step(1);
+    assert!(!tube.parasite_irq.sense());
+  }
+
+  #[test]
+  fn r3_two_bytes_and_nmi() {
+    let mut tube = TubeULA::new();
+    tube.host_read(5); // discard reset byte
+    tube.host_write(0, S | M | V);
+    assert!(tube.parasite_nmi.sense()); // room to send
+    tube.parasite_write(5, 1);
+    tube.parasite_write(5, 2);
+    assert_eq!(tube.host_read(4) & DATA_AVAILABLE, DATA_AVAILABLE);
+    assert_eq!(tube.host_read(5), 1);
+    assert_eq!(tube.host_read(4) & DATA_AVAILABLE, DATA_AVAILABLE);
+    assert_eq!(tube.host_read(5), 2);
+    assert!(tube.parasite_nmi.sense()); // NMI again once emptied
+
+    tube.parasite_write(5, 3);
+    tube.parasite_write(5, 4); // hold R3, no NMI from parasite side
+    tube.host_write(5, 0xAA);
+    assert!(!tube.parasite_nmi.sense());
+    tube.host_write(5, 0xBB);
+    assert!(tube.parasite_nmi.sense());
+    assert_eq!(tube.parasite_read(5), 0xAA);
+    assert_eq!(tube.parasite_read(5), 0xBB);
+  }
+
+  #[test]
+  fn r4_host_irq() {
+    let mut tube = TubeULA::new();
+    tube.host_write(0, S | Q | J);
+    tube.parasite_write(7, 0x99);
+    assert!(tube.irq.sense());
+    tube.step(1);
+    assert!(tube.irq.sense()); // still waiting
+    assert_eq!(tube.host_read(7), 0x99);
+    tube.step(2);
+    assert!(!tube.irq.sense());
+
+    tube.host_write(7, 0x11);
+    assert!(tube.parasite_irq.sense());
+    assert_eq!(tube.parasite_read(6) & DATA_AVAILABLE, DATA_AVAILABLE);
+    assert_eq!(tube.parasite_read(7), 0x11);
+
+    tube.host_write(0, S | T);
+    assert_eq!(tube.host_read(6), NOT_FULL);
+  }
+}
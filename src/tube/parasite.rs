@@ -0,0 +1,114 @@
+// 6502 second processor
+// A 3MHz 6502 with 64K RAM of its own. The client ROM overlays the top of
+// memory from reset until the first access to the Tube registers, by which
+// time it has copied itself to RAM.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::TubeULA;
+use crate::devices::Clocked;
+use crate::memory::{Address, MemoryBus, ram::RAM};
+use crate::mos6502::CPU;
+
+const CYCLES_PER_US: u64 = 3; // 3MHz
+const TUBE_REGISTERS: u16 = 0xFEF8; // &FEF8–&FEFF
+const CLIENT_ROM_SIZE: usize = 0x1000; // 4K at most
+
+pub struct ParasiteMemory {
+  ram: Box<RAM>,
+  rom: Vec<u8>,
+  rom_in: Cell<bool>,
+  tube: Rc<RefCell<TubeULA>>,
+}
+
+impl ParasiteMemory {
+  fn rom_start(&self) -> usize {
+    0x10000 - self.rom.len()
+  }
+}
+
+impl MemoryBus for ParasiteMemory {
+  fn read(&self, address: Address) -> u8 {
+    let address16 = address.to_u16();
+    if address16 & 0xFFF8 == TUBE_REGISTERS {
+      self.rom_in.set(false);
+      return self.tube.borrow().parasite_read(address.lo_u8());
+    }
+    if self.rom_in.get() && address16 as usize >= self.rom_start() {
+      return self.rom[address16 as usize - self.rom_start()];
+    }
+    self.ram.read(address)
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    if address.to_u16() & 0xFFF8 == TUBE_REGISTERS {
+      self.rom_in.set(false);
+      self.tube.borrow().parasite_write(address.lo_u8(), value);
+    } else {
+      self.ram.write(address, value); // under ROM too
+    }
+  }
+}
+
+pub struct Parasite {
+  pub cpu: CPU,
+  pub memory: ParasiteMemory,
+  held: bool, // in reset by host
+}
+
+impl Parasite {
+  pub fn new(tube: Rc<RefCell<TubeULA>>, rom: &[u8]) -> Self {
+    assert!(!rom.is_empty() && rom.len() <= CLIENT_ROM_SIZE, "bad client ROM size");
+    let mut cpu = CPU::new();
+    cpu.irq_level = tube.borrow().parasite_irq.clone();
+    cpu.nmi_level = tube.borrow().parasite_nmi.clone();
+    let memory = ParasiteMemory {
+      ram: Box::new(RAM::new()),
+      rom: rom.to_vec(),
+      rom_in: Cell::new(true),
+      tube,
+    };
+    let mut parasite = Parasite { cpu, memory, held: false };
+    parasite.reset();
+    parasite
+  }
+
+  pub fn load_rom(tube: Rc<RefCell<TubeULA>>, filename: &str) -> std::io::Result<Self> {
+    let rom = std::fs::read(filename)?;
+    if rom.is_empty() || rom.len() > CLIENT_ROM_SIZE {
+      let message = format!("client ROM of {} bytes, not 1 to {CLIENT_ROM_SIZE}", rom.len());
+      return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, message));
+    }
+    Ok(Self::new(tube, &rom))
+  }
+
+  pub fn reset(&mut self) {
+    self.memory.rom_in.set(true);
+    self.cpu.handle_rst(&mut self.memory);
+  }
+
+  pub const fn micros(&self) -> u64 {
+    self.cpu.cycles / CYCLES_PER_US
+  }
+}
+
+// Runs the parasite in step with the host
+impl Clocked for Parasite {
+  fn step(&mut self, us: u64) {
+    let until = us * CYCLES_PER_US;
+    if self.memory.tube.borrow().parasite_reset() {
+      self.held = true;
+      self.cpu.cycles = self.cpu.cycles.max(until);
+      return;
+    }
+    if self.held {
+      log::debug!("Tube parasite reset");
+      self.held = false;
+      self.reset();
+    }
+    while self.cpu.cycles < until {
+      self.cpu.step(&mut self.memory);
+    }
+  }
+}
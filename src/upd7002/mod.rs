@@ -0,0 +1,154 @@
+// NEC uPD7002 analogue to digital converter
+// Four channels, converted one at a time to 8 bits (4ms) or 10 bits (10ms) of
+// a 12 bit result. The analogue port wires the channels to two joysticks,
+// whose fire buttons go to system VIA PB4/PB5. End of conversion drives
+// system VIA CB1.
+
+use crate::devices::{Clocked, Device};
+use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+const CONVERSION_8BIT_US: u64 = 4_000;
+const CONVERSION_10BIT_US: u64 = 10_000;
+
+// Status register
+// b1-0 channel, b2 flag, b3 10 bit conversion, as written to start it
+// b5-4 two most significant bits of result
+const NOT_BUSY: u8 = 0b0100_0000;
+const CONVERTING: u8 = 0b1000_0000; // end of conversion, inverted
+
+// Joystick at rest, half way between full left (up) 0xFFFF and right (down) 0
+pub const CENTRE: u16 = 0x8000;
+
+//  &C0–&DF uPD7002 Analogue to digital converter
+#[derive(Debug)]
+pub struct ADC {
+  channels: [u16; 4],             // input voltages, 0xFFFF full scale
+  fire_buttons: [bool; 2],        // pressed
+  status: u8,
+  result: u16,
+  converting: Option<u64>,        // until us
+  clock_us: u64,
+}
+
+impl ADC {
+  pub fn new() -> Self {
+    ADC { channels: [CENTRE; 4], fire_buttons: [false; 2],
+          status: NOT_BUSY, result: 0, converting: None, clock_us: 0 }
+  }
+
+  pub fn set_channel(&mut self, channel: usize, value: u16) {
+    self.channels[channel] = value;
+  }
+
+  pub const fn channel(&self, channel: usize) -> u16 {
+    self.channels[channel]
+  }
+
+  pub fn set_fire_button(&mut self, button: usize, pressed: bool) {
+    self.fire_buttons[button] = pressed;
+  }
+
+  pub const fn fire_button(&self, button: usize) -> bool {
+    self.fire_buttons[button]
+  }
+
+  // EOC output, inverted: high from start until end of conversion
+  pub const fn busy(&self) -> bool {
+    self.converting.is_some()
+  }
+
+  fn start(&mut self, value: u8) {
+    let precision_us = if value & 0b1000 != 0 {
+      CONVERSION_10BIT_US
+    } else {
+      CONVERSION_8BIT_US
+    };
+    self.status = value & 0b1111 | CONVERTING;
+    self.converting = Some(self.clock_us + precision_us);
+  }
+
+  fn complete(&mut self) {
+    let channel = (self.status & 0b11) as usize;
+    let mask = if self.status & 0b1000 != 0 { 0xFFC0 } else { 0xFF00 };
+    self.result = self.channels[channel] & mask;
+    let msbs = (self.result >> 10) as u8 & 0b0011_0000;
+    self.status = self.status & 0b1111 | msbs | NOT_BUSY;
+    self.converting = None;
+    log::trace!("uPD7002 channel {channel} -> {:#06x}", self.result);
+  }
+}
+
+impl Device for ADC {
+  fn name(&self) -> &'static str { "uPD7002 Analogue to digital converter" }
+}
+
+impl MemoryBus for ADC {
+  fn read(&self, address: Address) -> u8 {
+    match address.lo_u8() & 0b11 {
+      0 => self.status,
+      1 => (self.result >> 8) as u8,
+      2 => self.result as u8,
+      _ => 0x00, // test mode
+    }
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    if address.lo_u8() & 0b11 == 0 {
+      self.start(value);
+    }
+  }
+}
+
+impl Clocked for ADC {
+  fn step(&mut self, us: u64) {
+    self.clock_us = us;
+    if let Some(until) = self.converting {
+      if until <= us {
+        self.complete();
+      }
+    }
+  }
+}
+
+// Joystick positions and buttons belong to the host
+impl Snapshot for ADC {
+  fn save(&self, state: &mut Writer) {
+    state.u8(self.status);
+    state.u16(self.result);
+    state.bool(self.converting.is_some());
+    state.u64(self.converting.unwrap_or(0));
+    state.u64(self.clock_us);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.status = state.u8()?;
+    self.result = state.u16()?;
+    let converting = state.bool()?;
+    let until = state.u64()?;
+    self.converting = converting.then_some(until);
+    self.clock_us = state.u64()?;
+    Ok(())
+  }
+}
+
+#[test]
+fn conversion() {
+  let mut adc = ADC::new();
+  adc.set_channel(2, 0xABCD);
+  adc.write(Address::from(0xFEC0), 0b1010); // channel 2, 10 bit
+  assert!(adc.busy());
+  assert_eq!(adc.read(Address::from(0xFEC0)), CONVERTING | 0b1010);
+  adc.step(9_999);
+  assert!(adc.busy());
+  adc.step(10_000);
+  assert!(!adc.busy());
+  assert_eq!(adc.read(Address::from(0xFEC0)), NOT_BUSY | 0b10_1010);
+  assert_eq!(adc.read(Address::from(0xFED1)), 0xAB); // mirrored
+  assert_eq!(adc.read(Address::from(0xFEC2)), 0xC0);
+
+  adc.write(Address::from(0xFEC0), 0b0010); // 8 bit
+  adc.step(14_000);
+  assert_eq!(adc.read(Address::from(0xFEC0)), NOT_BUSY | 0b10_0010);
+  assert_eq!(adc.read(Address::from(0xFEC2)), 0x00);
+}
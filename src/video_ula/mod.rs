@@ -0,0 +1,150 @@
+// Video ULA
+// Serialises the bytes fetched at the addresses generated by the 6845 into
+// pixels, through a 16 entry palette. Also selects the 6845 character clock
+// and whether the SAA5050 teletext chip drives the video output.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::devices::Device;
+use crate::mc6845::CRTC;
+use crate::memory::{Address, MemoryBus};
+use crate::snapshot::{Reader, Snapshot, Writer};
+
+// Control register &FE20
+//   b7-b5 Cursor width (b7: 1st byte, b6: 2nd byte, b5: 3rd and 4th bytes)
+//   b4    6845 clock rate: 1 = 2MHz (MODE 0-3), 0 = 1MHz (MODE 4-7)
+//   b3-b2 Pixel rate: 00 = 2MHz, 01 = 4MHz, 10 = 8MHz, 11 = 16MHz
+//   b1    Teletext output select
+//   b0    Flash colour select
+//
+// Palette register &FE21
+//   b7-b4 Logical colour
+//   b3-b0 Physical colour, EOR 7. b3 set makes the colour flash
+#[derive(Debug)]
+pub struct VideoULA {
+  crtc: Rc<RefCell<CRTC>>,
+  control: u8,
+  palette: [u8; 16],
+}
+
+impl VideoULA {
+  pub fn new(crtc: Rc<RefCell<CRTC>>) -> Self {
+    // MODE 7, as left by MOS reset
+    VideoULA { crtc, control: 0x4B, palette: [0x07; 16] }
+  }
+
+  pub const fn control(&self) -> u8 {
+    self.control
+  }
+
+  // Physical colours as written, before flashing and EOR 7
+  pub const fn palette(&self) -> [u8; 16] {
+    self.palette
+  }
+
+  pub const fn teletext(&self) -> bool {
+    self.control & 0b0000_0010 != 0
+  }
+
+  pub const fn flash(&self) -> bool {
+    self.control & 0b0000_0001 != 0
+  }
+
+  pub const fn fast_clock(&self) -> bool {
+    self.control & 0b0001_0000 != 0
+  }
+
+  // Cursor bytes, as a mask: b0 for the 1st byte up to b3 for the 4th
+  pub const fn cursor_bytes(&self) -> u8 {
+    let width = self.control >> 5;
+    ((width & 0b100) >> 2) | (width & 0b010) | ((width & 0b001) * 0b1100)
+  }
+
+  // pixels serialised from each byte fetched by the 6845: 1, 2, 4 or 8
+  pub const fn pixels_per_byte(&self) -> usize {
+    let pixel_rate = 2 << ((self.control >> 2) & 0b11); // MHz
+    let byte_rate = if self.fast_clock() { 2 } else { 1 }; // MHz
+    pixel_rate / byte_rate
+  }
+
+  // Physical colour 0-7 (b0: red, b1: green, b2: blue) of logical colour,
+  // for current state of the flash select bit
+  pub const fn physical_colour(&self, logical: u8) -> u8 {
+    let entry = self.palette[logical as usize & 0x0F];
+    if entry & 0b1000 != 0 && self.flash() {
+      entry & 0b0111
+    } else {
+      (entry & 0b0111) ^ 0b0111
+    }
+  }
+}
+
+impl Device for VideoULA {
+  fn name(&self) -> &'static str { "Video ULA" }
+}
+
+impl MemoryBus for VideoULA {
+  fn read(&self, _address: Address) -> u8 {
+    0x00 // write only
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    if address.lo_u8() & 1 == 0 {
+      log::trace!("control {value:#010b}");
+      self.control = value;
+      self.crtc.borrow_mut().set_fast_clock(self.fast_clock());
+    } else {
+      let logical = value >> 4;
+      log::trace!("palette[{logical}] = {:#06b}", value & 0x0F);
+      self.palette[logical as usize] = value & 0x0F;
+    }
+  }
+}
+
+impl Snapshot for VideoULA {
+  fn save(&self, state: &mut Writer) {
+    state.u8(self.control);
+    state.bytes(&self.palette);
+  }
+
+  fn restore(&mut self, state: &mut Reader) -> std::io::Result<()> {
+    self.control = state.u8()?;
+    self.palette = state.array()?;
+    self.crtc.borrow_mut().set_fast_clock(self.fast_clock());
+    Ok(())
+  }
+}
+
+#[test]
+fn mode2_palette() {
+  let crtc = Rc::new(RefCell::new(CRTC::new()));
+  let mut ula = VideoULA::new(crtc);
+  let control = Address::from(0xFE20);
+  let palette = Address::from(0xFE21);
+  ula.write(control, 0xF4); // MODE 2
+  assert_eq!(ula.pixels_per_byte(), 2);
+  assert!(ula.fast_clock());
+  assert!(!ula.teletext());
+  assert_eq!(ula.cursor_bytes(), 0b1111);
+
+  ula.write(palette, 0x16); // logical 1: red
+  ula.write(palette, 0x9E); // logical 9: flashing red / cyan
+  assert_eq!(ula.physical_colour(1), 1);
+  assert_eq!(ula.physical_colour(9), 1);
+  ula.write(Address::from(0xFE2E), 0xF5); // mirrored, flash
+  assert_eq!(ula.physical_colour(1), 1);
+  assert_eq!(ula.physical_colour(9), 6);
+}
+
+#[test]
+fn pixels_per_byte() {
+  let crtc = Rc::new(RefCell::new(CRTC::new()));
+  let mut ula = VideoULA::new(crtc);
+  let control = Address::from(0xFE20);
+  for (mode, value, pixels) in [(0, 0x9C, 8), (1, 0xD8, 4), (4, 0x88, 8),
+                                (5, 0xC4, 4), (7, 0x4B, 8)] {
+    ula.write(control, value);
+    assert_eq!(ula.pixels_per_byte(), pixels, "MODE {mode}");
+  }
+}
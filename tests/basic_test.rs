@@ -42,7 +42,7 @@ const PROGRAM: [u8; 38] = [
         0x90, // BCC Relative
         0xFB, //     pc - 5
         0xEA, // NOP :)
-        0xFF, // Something invalid -- the end!
+        0x02, // Something invalid (JAM) -- the end!
 ];
 
 #[test]
@@ -6,7 +6,10 @@ use std::fs::File;
 use bbc_b::devices::{DevicePage, SheilaPage};
 use bbc_b::mos6502::{CPU, stop_after};
 use bbc_b::mos6502::disassemble::disassemble_with_address;
-use bbc_b::memory::{Address, PageDispatcher, ram::RAM, slice};
+use bbc_b::memory::{Address, MemoryBus, PageDispatcher, ram::RAM, slice};
+
+mod common;
+use common::Machine;
 
 fn dump(filename: &str, bytes: &[u8]) {
   let path = Path::new(filename);
@@ -80,3 +83,39 @@ fn os120_reset_with_sheila() {
   // capture (max) screen area (20kB)
   dump("dump.bin", &slice(&mem, Address::from(0x3000), 0x5000));
 }
+
+#[test]
+fn os120_finds_basic_in_sideways_rom() {
+  let Machine { mut mem, .. } = Machine::booted_with_basic(common::sheila());
+
+  // MOS ROM type table &02A1-&02B0 and current language ROM &028C
+  let rom_types = slice(&mem, Address::from(0x02A1), 16);
+  assert_eq!(rom_types[15], 0x60); // BASIC: language, 6502 code
+  assert!(rom_types[..15].iter().all(|&rom_type| rom_type == 0));
+  assert_eq!(mem.read(Address::from(0x028C)), 15);
+  assert_eq!(mem.read(Address::from(0x00F4)), 15); // MOS copy of &FE30
+
+  // MOS and BASIC are write protected
+  for address in [0x8000, 0xBFFF, 0xC000, 0xFFFF] {
+    let address = Address::from(address);
+    let value = mem.read(address);
+    mem.write(address, !value);
+    assert_eq!(mem.read(address), value);
+  }
+}
+
+#[test]
+fn os120_beeps_at_power_on() {
+  let sheila = common::sheila();
+  let sound = sheila.sound.clone();
+  sound.borrow_mut().record(8000);
+  let mut machine = Machine::booted(sheila);
+  machine.run_for(3_000_000); // 2 seconds in all
+
+  // VDU 7 in start up message rings the bell
+  let sound = sound.borrow();
+  let samples = sound.samples();
+  assert_eq!(samples.len(), 16_000);
+  let loud = samples.iter().filter(|sample| sample.abs() > 1000).count();
+  assert!(loud > 1000);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use bbc_b::cassette::uef;
+use bbc_b::memory::{Address, slice};
+use common::Machine;
+
+fn chunk(id: u16, data: &[u8]) -> Vec<u8> {
+  let mut chunk = id.to_le_bytes().to_vec();
+  chunk.extend((data.len() as u32).to_le_bytes());
+  chunk.extend(data);
+  chunk
+}
+
+// CRC as calculated by MOS, stored high byte first
+fn crc(bytes: &[u8]) -> [u8; 2] {
+  let mut crc: u16 = 0;
+  for &byte in bytes {
+    crc ^= (byte as u16) << 8;
+    for _ in 0..8 {
+      crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+    }
+  }
+  crc.to_be_bytes()
+}
+
+// Single block CFS file, as saved by MOS at 1200 baud
+fn cfs_file(name: &str, load: u32, data: &[u8]) -> Vec<u8> {
+  let mut header = name.as_bytes().to_vec();
+  header.push(0x00);
+  header.extend(load.to_le_bytes());
+  header.extend(load.to_le_bytes());           // execution address
+  header.extend(0u16.to_le_bytes());           // block number
+  header.extend((data.len() as u16).to_le_bytes());
+  header.push(0x80);                           // last block
+  header.extend(0u32.to_le_bytes());
+  let mut block = vec![b'*'];
+  block.extend(&header);
+  block.extend(crc(&header));
+  block.extend(data);
+  block.extend(crc(data));
+
+  let mut uef = b"UEF File!\0".to_vec();
+  uef.extend([10, 0]);
+  uef.extend(chunk(0x0110, &1200u16.to_le_bytes())); // 1/2 second carrier
+  uef.extend(chunk(0x0100, &block));
+  uef.extend(chunk(0x0110, &600u16.to_le_bytes()));
+  uef
+}
+
+#[test]
+fn os120_loads_from_tape() {
+  let sheila = common::sheila();
+  let data: Vec<u8> = (0..100u8).map(|n| n.wrapping_mul(7) ^ 0x55).collect();
+  let tape = uef::parse(&cfs_file("TEST", 0x3000, &data)).unwrap();
+  let serial_ula = sheila.serial_ula.clone();
+  serial_ula.borrow_mut().cassette.insert(tape);
+  let mut machine = Machine::booted_with_basic(sheila);
+
+  // *LOAD TEST then spin
+  machine.poke(0x2000, &[
+    0xA2, 0x00, 0xA0, 0x21, 0x20, 0xF7, 0xFF, // LDX #0: LDY #&21: JSR OSCLI
+    0x4C, 0x07, 0x20,                         // .done JMP done
+  ]);
+  machine.poke(0x2100, b"LOAD TEST\r");
+  machine.start(0x2000);
+  assert!(machine.run_to(0x2007, 10_000_000), "*LOAD didn't finish"); // 5 seconds
+  assert_eq!(slice(&machine.mem, Address::from(0x3000), data.len()), data);
+  assert!(!serial_ula.borrow().motor());
+}
@@ -0,0 +1,126 @@
+// Shared by the integration tests: MOS 1.20 booted, with devices such as
+// renderers and typists added, and programs poked into memory to run it, each
+// ending in a JMP to itself.
+
+#![allow(dead_code)] // each test uses what it needs
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bbc_b::devices::{ClockedDevices, DevicePage, SheilaPage};
+use bbc_b::devices::keyboard::Keyboard;
+use bbc_b::devices::paged_rom::SidewaysRoms;
+use bbc_b::memory::{Address, MemoryBus, PageDispatcher, ram::RAM};
+use bbc_b::memory::rom::ROM;
+use bbc_b::mos6502::CPU;
+use bbc_b::snapshot::{b_em, SnapshotDevices};
+
+const RESET_DONE: u64 = 1_000_000;   // cycles, MOS alone
+const BASIC_PROMPT: u64 = 4_000_000; // 2 seconds, BASIC waits for input
+
+// SHEILA and its devices, to plug things into before booting
+pub fn sheila() -> SheilaPage {
+  SheilaPage::new(Rc::new(RefCell::new(Keyboard::new())))
+}
+
+// RAM the CPU shares with renderers reading the screen
+struct SharedRAM(Rc<RefCell<RAM>>);
+
+impl MemoryBus for SharedRAM {
+  fn read(&self, address: Address) -> u8 {
+    self.0.borrow().read(address)
+  }
+
+  fn write(&mut self, address: Address, value: u8) {
+    self.0.borrow_mut().write(address, value);
+  }
+}
+
+pub struct Machine {
+  pub cpu: CPU,
+  pub mem: PageDispatcher,
+  pub ram: Rc<RefCell<RAM>>,
+  pub keyboard: Rc<RefCell<Keyboard>>,
+  pub clocked_devices: ClockedDevices, // add renderers and typists to these
+  pub snapshot_devices: SnapshotDevices,
+  pub b_em_devices: b_em::Devices,
+}
+
+impl Machine {
+  // MOS 1.20 alone, once its reset is done
+  pub fn booted(sheila: SheilaPage) -> Self {
+    let mut machine = Machine::new(sheila, false);
+    machine.run_until(|cpu| cpu.cycles >= RESET_DONE);
+    machine
+  }
+
+  // and BASIC in bank 15, sideways RAM in bank 4, at the BASIC prompt
+  pub fn booted_with_basic(sheila: SheilaPage) -> Self {
+    let mut machine = Machine::new(sheila, true);
+    machine.run_until(|cpu| cpu.cycles >= BASIC_PROMPT);
+    machine
+  }
+
+  // Just reset, to add devices before running it
+  pub fn new(sheila: SheilaPage, basic: bool) -> Self {
+    let mos = ROM::load_bin_at("images/os120.bin", Address::from(0xC000));
+    let ram = Rc::new(RefCell::new(RAM::new()));
+    let mut mem = PageDispatcher::new(Box::new(SharedRAM(ram.clone())));
+    mem.add_backend_range(mos.pages(), Box::new(mos));
+    if basic {
+      let mut roms = SidewaysRoms::new(sheila.rom_select.clone());
+      roms.load_bin_at("images/Basic2.rom", 15);
+      roms.add_ram(4);
+      mem.add_backend_range(SidewaysRoms::pages(), Box::new(roms));
+    }
+    let clocked_devices = sheila.get_clocked_devices();
+    let keyboard = sheila.keyboard.clone();
+    let snapshot_devices = sheila.get_snapshot_devices();
+    let b_em_devices = sheila.get_b_em_devices();
+    let mut cpu = CPU::new();
+    cpu.irq_level = sheila.irq.clone();
+    cpu.nmi_level = sheila.nmi.clone();
+    mem.add_backend(SheilaPage::page(), Box::new(sheila));
+    cpu.handle_rst(&mut mem);
+    Machine { cpu, mem, ram, keyboard, clocked_devices, snapshot_devices, b_em_devices }
+  }
+
+  pub fn run_until(&mut self, stop: impl Fn(&CPU) -> bool) {
+    while !stop(&self.cpu) {
+      self.cpu.step(&mut self.mem);
+      for device in self.clocked_devices.iter() {
+        device.borrow_mut().step(self.cpu.micros());
+      }
+    }
+  }
+
+  pub fn run_for(&mut self, cycles: u64) {
+    let until = self.cpu.cycles + cycles;
+    self.run_until(|cpu| cpu.cycles >= until);
+  }
+
+  pub fn poke(&mut self, address: u16, bytes: &[u8]) {
+    for (offset, byte) in bytes.iter().enumerate() {
+      self.mem.write(Address::from(address + offset as u16), *byte);
+    }
+  }
+
+  // as the CPU sees it
+  pub fn ram(&self) -> Vec<u8> {
+    (0..0x8000).map(|address| self.mem.read(Address::from(address))).collect()
+  }
+
+  // Jump to a program, once out of any interrupt handler
+  pub fn start(&mut self, address: u16) {
+    self.run_until(|cpu| !cpu.registers.p.has::<'I'>());
+    self.cpu.registers.pc = Address::from(address);
+  }
+
+  // Until the program's JMP to itself at DONE, or CYCLES have gone by;
+  // whether it got there
+  pub fn run_to(&mut self, done: u16, cycles: u64) -> bool {
+    let timeout = self.cpu.cycles + cycles;
+    self.run_until(|cpu| cpu.registers.pc == Address::from(done) || cpu.cycles > timeout);
+    self.cpu.registers.pc == Address::from(done)
+  }
+}
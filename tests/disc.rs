@@ -0,0 +1,56 @@
+mod common;
+
+use bbc_b::i8271::disc::DiscImage;
+use bbc_b::memory::{Address, MemoryBus, slice};
+use common::Machine;
+
+#[test]
+fn nmi_reads_catalogue_from_ssd() {
+  // DFS catalogue: title and first file name in sector 0
+  let mut image = vec![0; 2 * 256];
+  image[..8].copy_from_slice(b"MYDISC  ");
+  image[8..16].copy_from_slice(b"!BOOT  $");
+  image[0x105] = 8;            // one file
+  let filename = std::env::temp_dir().join("bbc-b-nmi-test.ssd");
+  std::fs::write(&filename, &image).unwrap();
+  let disc = DiscImage::load(filename.to_str().unwrap()).unwrap();
+  assert_eq!(disc.tracks(), 1);
+
+  let sheila = common::sheila();
+  sheila.fdc.borrow_mut().insert(0, disc);
+  let mut machine = Machine::booted(sheila);
+
+  // MOS vectors NMI to &0D00: copy data bytes to &3000, or fetch result
+  machine.poke(0x0D00, &[
+    0x48,                   // PHA
+    0xAD, 0x80, 0xFE,       // LDA &FE80      status
+    0x29, 0x04,             // AND #&04       data request?
+    0xF0, 0x0C,             // BEQ result
+    0xAD, 0x84, 0xFE,       // LDA &FE84
+    0x8D, 0x00, 0x30,       // STA &3000      incremented below
+    0xEE, 0x0C, 0x0D,       // INC &0D0C
+    0x68,                   // PLA
+    0x40,                   // RTI
+    0xEA,                   // NOP
+    0xAD, 0x81, 0xFE,       // .result LDA &FE81
+    0x85, 0x70,             // STA &70
+    0x68,                   // PLA
+    0x40,                   // RTI
+  ]);
+  // read track 0 sector 0, 256 bytes, from drive 0; wait for result
+  machine.poke(0x2000, &[
+    0xA9, 0xFF, 0x85, 0x70, // LDA #&FF: STA &70
+    0xA9, 0x53, 0x8D, 0x80, 0xFE, // LDA #&53: STA &FE80
+    0xA9, 0x00, 0x8D, 0x81, 0xFE, // LDA #0: STA &FE81  track
+    0x8D, 0x81, 0xFE,       // STA &FE81        sector
+    0xA9, 0x21, 0x8D, 0x81, 0xFE, // LDA #&21: STA &FE81 1 x 256 bytes
+    0xA5, 0x70,             // .wait LDA &70
+    0x30, 0xFC,             // BMI wait
+    0x4C, 0x1A, 0x20,       // .done JMP done
+  ]);
+  machine.start(0x2000);
+  assert!(machine.run_to(0x201A, 1_000_000), "no result");
+  assert_eq!(machine.mem.read(Address::from(0x0070)), 0x00); // OK
+  assert_eq!(slice(&machine.mem, Address::from(0x3000), 256), &image[..256]);
+  std::fs::remove_file(filename).unwrap();
+}
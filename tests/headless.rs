@@ -0,0 +1,45 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use bbc_b::host::{Headless, Typist};
+use bbc_b::memory::{Address, MemoryBus};
+use common::Machine;
+
+#[test]
+fn os120_basic_without_window() {
+  let sheila = common::sheila();
+  let (crtc, video_ula, ic32) = (sheila.crtc.clone(), sheila.video_ula.clone(), sheila.ic32.clone());
+  let mut machine = Machine::new(sheila, true);
+  let headless = Rc::new(RefCell::new(Headless::new(machine.ram.clone(), crtc, video_ula, ic32)));
+  let typist = Typist::new(machine.keyboard.clone(), Cursor::new(b"print 6*7\r".to_vec()));
+  let typist = Rc::new(RefCell::new(typist));
+  machine.clocked_devices.push(headless.clone());
+  machine.clocked_devices.push(typist.clone());
+
+  machine.run_until(|cpu| typist.borrow().finished() || cpu.micros() >= 5_000_000);
+  assert!(typist.borrow().finished(), "typing timed out");
+  machine.run_for(400_000); // 200ms
+
+  // MODE 7 screen: banner, BASIC prompt, command and its answer
+  let mem = &machine.mem;
+  let rows: Vec<String> = (0..25).map(|row| {
+    (0..40).map(|col| mem.read(Address::from(0x7C00 + row * 40 + col)) as char)
+      .collect::<String>().trim_end().to_string()
+  }).collect();
+  assert_eq!(&rows[1..7], ["BBC Computer 32K", "", "BASIC", "", ">PRINT 6*7", "        42"]);
+
+  let headless = headless.borrow();
+  assert!(headless.frames() > 50);
+  let lit = headless.framebuffer().pixels().iter().filter(|pixel| **pixel != 0).count();
+  assert!(lit > 1000);
+
+  let filename = std::env::temp_dir().join("bbc-b-headless.png");
+  let filename = filename.to_str().unwrap();
+  headless.framebuffer().save_png(filename).unwrap();
+  let png = std::fs::read(filename).unwrap();
+  assert_eq!(png[..8], [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n']);
+  assert_eq!(png[12..24], [b'I', b'H', b'D', b'R', 0, 0, 2, 128, 0, 0, 2, 0]); // 640 x 512
+}
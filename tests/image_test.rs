@@ -15,7 +15,7 @@ fn test_recurse() {
   assert_eq!(r.x, 0);
   assert_eq!(r.y, 0);
   assert!(r.p.has::<'Z'>());
-  assert_eq!(cpu.cycles, 1786);
+  assert_eq!(cpu.cycles, 6165); // 1786 instructions
   let b = slice(&mem, Address::from(0x915), 16); // start of b[16]
   assert_eq!(b, (0..16).collect::<Vec<u8>>());   // 0, 1, 2, 3, .. , 15
 }
@@ -83,7 +83,7 @@ fn test_no_interrupt() {
 fn test_interrupt() {
   let (mut ram, start, end, irq_entry) = load_program();
 
-  // Interrupt after 100 "cycles"
+  // Interrupt after 93 cycles
   // - stopped at END label (pc = 0xFF27)
   // - accumulator == 1 on exit -> success
   // - X != 0 -> watchdog not depleted
@@ -91,9 +91,9 @@ fn test_interrupt() {
   cpu.registers.pc = start;
 
   const BRK: u8 = 0x0;
-  cpu.run(&mut ram, &stop_after::<100>);
+  cpu.run(&mut ram, &stop_after::<93>);
 
-  // stopped arbitrarily aftes 100 "cycles", we're somewhere in spin loop
+  // stopped arbitrarily after 93 cycles (11 + 8 x 10 + 2), we're in spin loop
   assert_eq!(cpu.registers.pc, Address::from(0xFF0E));
   assert_eq!(cpu.registers.a, 0xFF);
 
@@ -118,7 +118,7 @@ fn test_interrupt() {
 
   assert_eq!(cpu.registers.pc, end);
   assert_eq!(cpu.registers.a, 1); // SUCCESS
-  assert_eq!(cpu.registers.x, 230); // not depleted
+  assert_eq!(cpu.registers.x, 246); // not depleted
   assert_eq!(cpu.registers.s.to_u8(), 0xFF); // Initial value
   assert!(!cpu.registers.p.has::<'I'>());
 }
@@ -127,7 +127,7 @@ fn test_interrupt() {
 fn test_nmi() {
   let (mut ram, start, end, irq_entry) = load_program();
 
-  // Interrupt after 100 "cycles"
+  // Interrupt after 93 cycles
   // - stopped at END label (pc = 0xFF27)
   // - accumulator == 1 on exit -> success
   // - X != 0 -> watchdog not depleted
@@ -137,9 +137,9 @@ fn test_nmi() {
   cpu.registers.p.set_flag::<'I', true>();
 
   const BRK: u8 = 0x0;
-  cpu.run(&mut ram, &stop_after::<100>);
+  cpu.run(&mut ram, &stop_after::<93>);
 
-  // stopped arbitrarily aftes 100 "cycles", we're somewhere in spin loop
+  // stopped arbitrarily after 93 cycles (11 + 8 x 10 + 2), we're in spin loop
   assert_eq!(cpu.registers.pc, Address::from(0xFF0E));
   assert_eq!(cpu.registers.a, 0xFF);
 
@@ -164,7 +164,7 @@ fn test_nmi() {
 
   assert_eq!(cpu.registers.pc, end);
   assert_eq!(cpu.registers.a, 1); // SUCCESS
-  assert_eq!(cpu.registers.x, 230); // not depleted
+  assert_eq!(cpu.registers.x, 246); // not depleted
   assert_eq!(cpu.registers.s.to_u8(), 0xFF); // Initial value
   assert!(cpu.registers.p.has::<'I'>()); // Initial value
 }
@@ -0,0 +1,39 @@
+mod common;
+
+use bbc_b::memory::{Address, MemoryBus};
+use common::Machine;
+
+#[test]
+fn os120_adval_reads_joysticks() {
+  let sheila = common::sheila();
+  let adc = sheila.adc.clone();
+  let mut machine = Machine::booted(sheila);
+
+  // left joystick full right, right joystick up, left fire button pressed
+  adc.borrow_mut().set_channel(0, 0x12C0);
+  adc.borrow_mut().set_channel(3, 0xFFFF);
+  adc.borrow_mut().set_fire_button(0, true);
+  // MOS converts channels 4 to 1 in turn, 10ms each
+  machine.run_until(|cpu| cpu.cycles >= 1_100_000);
+
+  // ADVAL(1), ADVAL(4) and ADVAL(0) with OSBYTE &80
+  machine.poke(0x2000, &[
+    0xA9, 0x80, 0xA2, 0x01, // LDA #&80: LDX #1
+    0x20, 0xF4, 0xFF,       // JSR OSBYTE
+    0x86, 0x70, 0x84, 0x71, // STX &70: STY &71
+    0xA9, 0x80, 0xA2, 0x04, // LDA #&80: LDX #4
+    0x20, 0xF4, 0xFF,       // JSR OSBYTE
+    0x86, 0x72, 0x84, 0x73, // STX &72: STY &73
+    0xA9, 0x80, 0xA2, 0x00, // LDA #&80: LDX #0
+    0x20, 0xF4, 0xFF,       // JSR OSBYTE
+    0x86, 0x74,             // STX &74
+    0x4C, 0x1F, 0x20,       // .done JMP done
+  ]);
+  machine.start(0x2000);
+  assert!(machine.run_to(0x201F, 100_000));
+  assert_eq!(machine.mem.read(Address::from(0x0070)), 0xC0);
+  assert_eq!(machine.mem.read(Address::from(0x0071)), 0x12);
+  assert_eq!(machine.mem.read(Address::from(0x0072)), 0xC0); // 10 bit conversions
+  assert_eq!(machine.mem.read(Address::from(0x0073)), 0xFF);
+  assert_eq!(machine.mem.read(Address::from(0x0074)) & 0b11, 0b01);
+}
@@ -67,7 +67,7 @@ fn interrogate_keyboard() {
       let slice = slice(mem, cpu.registers.pc, 3);
       let dump = disassemble_with_address(cpu.registers.pc, &slice);
       cpu.step(mem);
-      step(&cds, cpu.cycles);
+      step(&cds, cpu.micros());
       println!("{dump:<30} | a:{} x:{} y:{} p:{:?}", cpu.registers.a,
                cpu.registers.x, cpu.registers.y, cpu.registers.p,
       );
@@ -124,8 +124,8 @@ fn interrogate_keyboard() {
     cpu.registers.x = key_code;
     enable_keyboard_autoscan(&mut mem);
     cpu.registers.pc = start;
-    cpu.step(&mut mem); step(&cds, cpu.cycles);
-    cpu.step(&mut mem); step(&cds, cpu.cycles);
+    cpu.step(&mut mem); step(&cds, cpu.micros());
+    cpu.step(&mut mem); step(&cds, cpu.micros());
     assert!(cpu.registers.p.has::<'I'>());    // WHOOPS, 6522 interrupted CPU
     assert_eq!(cpu.registers.pc.to_u16(), 0); // No BRK/IRQ vector was set
 
@@ -248,7 +248,7 @@ fn loop_keyboard() {
       let dump = disassemble_with_address(cpu.registers.pc, &slice);
       cpu.step(mem);
       for cd in cds.iter() {
-        cd.borrow_mut().step(cpu.cycles);
+        cd.borrow_mut().step(cpu.micros());
       }
       println!("{dump:<30} | a:{} x:{} y:{} p:{:?}", cpu.registers.a,
                cpu.registers.x, cpu.registers.y, cpu.registers.p,
@@ -0,0 +1,29 @@
+mod common;
+
+use bbc_b::host::Capture;
+use common::Machine;
+
+#[test]
+fn os120_prints_to_centronics() {
+  let sheila = common::sheila();
+  let capture = Capture::default();
+  sheila.user_via.borrow_mut().connect_printer(Box::new(capture.clone()));
+  let mut machine = Machine::booted(sheila);
+
+  // VDU 2, text, VDU 3 through OSWRCH; *FX5,1 parallel printer is the default
+  machine.poke(0x2100, b"\x02Printed\r\n\x03\x00");
+  machine.poke(0x2000, &[
+    0xA2, 0x00,             // LDX #0
+    0xBD, 0x00, 0x21,       // .loop LDA &2100,X
+    0xF0, 0x06,             // BEQ done
+    0x20, 0xEE, 0xFF,       // JSR OSWRCH
+    0xE8,                   // INX
+    0xD0, 0xF5,             // BNE loop
+    0x4C, 0x0D, 0x20,       // .done JMP done
+  ]);
+  machine.start(0x2000);
+  assert!(machine.run_to(0x200D, 100_000));
+  machine.run_for(200_000); // printer buffer emptied by interrupts
+  // line feed is the default printer ignore character
+  assert_eq!(capture.bytes(), b"Printed\r");
+}
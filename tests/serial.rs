@@ -0,0 +1,29 @@
+mod common;
+
+use std::io::Cursor;
+
+use bbc_b::host::{Capture, SerialStream};
+use common::Machine;
+
+#[test]
+fn os120_echoes_rs423() {
+  let sheila = common::sheila();
+  let capture = Capture::default();
+  let input = Cursor::new(b"Hello RS423\r".to_vec());
+  let rs423 = SerialStream::new(input, capture.clone());
+  sheila.serial_ula.borrow_mut().connect_rs423(Box::new(rs423));
+  let mut machine = Machine::booted_with_basic(sheila);
+
+  // *FX2,1 *FX3,1 then echo OSRDCH to OSWRCH
+  machine.poke(0x2000, &[
+    0xA9, 0x02, 0xA2, 0x01, 0x20, 0xF4, 0xFF, // LDA #2: LDX #1: JSR OSBYTE
+    0xA9, 0x03, 0xA2, 0x01, 0x20, 0xF4, 0xFF, // LDA #3: LDX #1: JSR OSBYTE
+    0x20, 0xE0, 0xFF,                         // .loop JSR OSRDCH
+    0x20, 0xEE, 0xFF,                         // JSR OSWRCH
+    0x4C, 0x0E, 0x20,                         // JMP loop
+  ]);
+  machine.start(0x2000);
+  machine.run_until(|cpu| cpu.cycles >= 6_000_000); // another second, 9600 baud
+
+  assert!(capture.bytes().starts_with(b"Hello RS423\r"));
+}
@@ -0,0 +1,174 @@
+mod common;
+
+use std::cell::RefCell;
+use std::io::{Cursor, Write};
+use std::rc::Rc;
+
+use bbc_b::host::Typist;
+use bbc_b::memory::{Address, MemoryBus};
+use bbc_b::mos6522::State;
+use bbc_b::snapshot::{self, b_em};
+use common::Machine;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+
+#[test]
+fn resume_where_saved() {
+  let mut booted = Machine::new(common::sheila(), true);
+  let typist = Typist::new(booted.keyboard.clone(), Cursor::new(b"A%=6*7\r".to_vec()));
+  let typist = Rc::new(RefCell::new(typist));
+  booted.clocked_devices.push(typist.clone());
+  booted.run_until(|cpu| cpu.micros() >= 2_000_000);
+  assert!(typist.borrow().finished());
+  booted.mem.write(Address::from(0xFE30), 4); // fill sideways RAM, as MOS left it
+  booted.mem.write(Address::from(0x8123), 0x5A);
+  booted.mem.write(Address::from(0xFE30), 15);
+
+  let mut file = Vec::new();
+  snapshot::save(&mut file, &booted.cpu, &mut booted.mem, &booted.snapshot_devices).unwrap();
+  assert_eq!(file[..2], [0x1F, 0x8B]); // gzip
+
+  let mut resumed = Machine::new(common::sheila(), true);
+  snapshot::restore(&file[..], &mut resumed.cpu, &mut resumed.mem,
+                    &resumed.snapshot_devices).unwrap();
+  assert_eq!(resumed.cpu.cycles, booted.cpu.cycles);
+  assert_eq!(resumed.cpu.registers.pc, booted.cpu.registers.pc);
+  assert_eq!(resumed.ram(), booted.ram());
+  assert_eq!(resumed.mem.read(Address::from(0x8009)), b'B'); // BASIC title
+  resumed.mem.write(Address::from(0xFE30), 4);
+  assert_eq!(resumed.mem.read(Address::from(0x8123)), 0x5A);
+  resumed.mem.write(Address::from(0xFE30), 15);
+
+  // A% as typed, then both carry on alike: 50Hz interrupts, clock, cursor
+  let a_percent = |machine: &Machine| machine.mem.read(Address::from(0x0404));
+  assert_eq!(a_percent(&resumed), 42);
+  booted.run_until(|cpu| cpu.micros() >= 2_500_000);
+  resumed.run_until(|cpu| cpu.micros() >= 2_500_000);
+  assert_eq!(resumed.cpu.cycles, booted.cpu.cycles);
+  assert_eq!(resumed.cpu.registers.pc, booted.cpu.registers.pc);
+  assert_eq!(resumed.ram(), booted.ram());
+}
+
+#[test]
+fn b_em_savestate() {
+  let mut booted = Machine::new(common::sheila(), true);
+  let typist = Typist::new(booted.keyboard.clone(), Cursor::new(b"A%=6*7\r".to_vec()));
+  booted.clocked_devices.push(Rc::new(RefCell::new(typist)));
+  booted.run_until(|cpu| cpu.micros() >= 2_000_000);
+
+  let mut file = Vec::new();
+  b_em::export(&mut file, &booted.cpu, &mut booted.mem, &booted.b_em_devices).unwrap();
+  assert_eq!(file[..9], *b"BEMSNAP36");
+
+  let mut resumed = Machine::new(common::sheila(), true);
+  b_em::import(&file[..], &mut resumed.cpu, &mut resumed.mem, &resumed.b_em_devices).unwrap();
+  assert_eq!(resumed.cpu.registers.pc, booted.cpu.registers.pc);
+  assert_eq!(resumed.cpu.registers.s.to_u8(), booted.cpu.registers.s.to_u8());
+  assert_eq!(resumed.ram(), booted.ram());
+
+  // carries on from B-em's starting point, on its own clock
+  let typist = Typist::new(resumed.keyboard.clone(), Cursor::new(b"PRINT A%\r".to_vec()));
+  resumed.clocked_devices.push(Rc::new(RefCell::new(typist)));
+  resumed.run_until(|cpu| cpu.micros() >= 2_500_000);
+  let rows: Vec<String> = (0..25).map(|row| {
+    (0..40).map(|col| resumed.mem.read(Address::from(0x7C00 + row * 40 + col)) as char)
+      .collect::<String>().trim_end().to_string()
+  }).collect();
+  assert!(rows.contains(&">PRINT A%".to_string()), "{rows:?}");
+  assert!(rows.contains(&"        42".to_string()), "{rows:?}");
+}
+
+// A savestate laid out as B-em's savestate.c writes it: key, 16 bit size and
+// data, or key | 0x80 and 24 bit size of zlib data
+fn b_em_section(file: &mut Vec<u8>, key: u8, data: &[u8]) {
+  file.push(key);
+  file.extend_from_slice(&(data.len() as u16).to_le_bytes());
+  file.extend_from_slice(data);
+}
+
+fn b_em_compressed(file: &mut Vec<u8>, key: u8, data: &[u8]) {
+  let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+  encoder.write_all(data).unwrap();
+  let data = encoder.finish().unwrap();
+  file.push(key | 0x80);
+  file.extend_from_slice(&(data.len() as u32).to_le_bytes()[..3]);
+  file.extend_from_slice(&data);
+}
+
+// via_savestate: ora, orb, ira, irb, port A twice, ddra, ddrb, sr, acr, pcr,
+// ifr, ier, 32 bit t1l, t2l, t1c, t2c in 2MHz cycles, t1hit, t2hit, ca1, ca2
+fn b_em_via(registers: [u8; 13], timers: [i32; 4], flags: [u8; 4]) -> Vec<u8> {
+  let mut data = registers.to_vec();
+  timers.iter().for_each(|timer| data.extend_from_slice(&timer.to_le_bytes()));
+  data.extend_from_slice(&flags);
+  data
+}
+
+#[test]
+fn b_em_fixture() {
+  let mut file = b"BEMSNAP3".to_vec();
+  b_em_section(&mut file, b'm', &[3]); // model: not ours to change
+  // a, x, y, p, s, pc, NMI, IRQ, 32 bit cycles
+  b_em_section(&mut file, b'6', &[0x12, 0x34, 0x56, 0x31, 0xF0, 0xA4, 0xE0, 0, 0, 0x10, 0x27, 0, 0]);
+  // ROM select latches at &FE30 and &FE34, 64K RAM, 16 ROM slots
+  let mut memory = vec![0x04, 0x00];
+  memory.resize(2 + 0x10000 + 16 * 0x4000, 0);
+  memory[2 + 0x0404] = 42; // A%
+  memory[2 + 0x10000 + 4 * 0x4000 + 0x0123] = 0x5A; // sideways RAM
+  memory[2 + 0x10000 + 15 * 0x4000 + 0x0009] = b'X'; // over BASIC's title
+  b_em_compressed(&mut file, b'M', &memory);
+  let mut system_via = b_em_via([0x0F, 0x0C, 0x0F, 0x0C, 0x0F, 0x0F, 0x7F, 0x0F,
+                                 0x00, 0x60, 0x04, 0x00, 0x72],
+                                [0x4E1E, 0x1FFFE, 9999, -2], [0, 1, 1, 0]);
+  system_via.push(0x0F); // IC32
+  b_em_section(&mut file, b'S', &system_via);
+  b_em_section(&mut file, b'U', &b_em_via([0x41, 0xFF, 0x41, 0xFF, 0x41, 0x41, 0xFF, 0x00,
+                                           0x00, 0x00, 0x0C, 0x00, 0x02],
+                                          [0xFFFE, 0xFFFE, -4, -4], [1, 1, 0, 0]));
+  // control, palette as written to &FE21
+  let mut video_ula = vec![0xF4];
+  video_ula.extend((0..16).map(|logical| logical ^ 0x07));
+  b_em_section(&mut file, b'V', &video_ula);
+  // MODE 2 registers, vc, sc, hc, ma, maback
+  let mut crtc = vec![0x7F, 0x50, 0x62, 0x28, 0x26, 0x00, 0x20, 0x22,
+                      0x01, 0x07, 0x67, 0x08, 0x06, 0x00, 0x06, 0x00, 0x00, 0x00];
+  crtc.extend_from_slice(&[5, 3, 0x10, 0x50, 0x06, 0x40, 0x06]);
+  b_em_section(&mut file, b'C', &crtc);
+  b_em_section(&mut file, b's', &[0; 55]); // sound: B-em's own
+
+  let mut machine = Machine::new(common::sheila(), true);
+  b_em::import(&file[..], &mut machine.cpu, &mut machine.mem, &machine.b_em_devices).unwrap();
+  let registers = &machine.cpu.registers;
+  assert_eq!([registers.a, registers.x, registers.y, registers.s.to_u8()], [0x12, 0x34, 0x56, 0xF0]);
+  assert!(registers.p.has::<'C'>());
+  assert_eq!(registers.pc, Address::from(0xE0A4));
+  assert_eq!(machine.cpu.cycles, 9); // ours, from reset
+
+  let devices = &machine.b_em_devices;
+  assert_eq!(devices.rom_select.borrow().bank(), 4);
+  assert_eq!(machine.mem.read(Address::from(0x0404)), 42);
+  assert_eq!(machine.mem.read(Address::from(0x8123)), 0x5A);
+  machine.mem.write(Address::from(0xFE30), 15);
+  assert_eq!(machine.mem.read(Address::from(0x8009)), b'B'); // ROM kept
+
+  let system_via = devices.system_via.borrow();
+  let expected = State { iora: 0x0F, iorb: 0x0C, ddra: 0x7F, ddrb: 0x0F,
+                         t1l: 9999, t2l: 0xFFFF, t1c: 5000, t2c: 0xFFFF,
+                         sr: 0, acr: 0x60, pcr: 0x04, ifr: 0, ier: 0x72,
+                         ca1: true, t1_active: true, ..State::default() };
+  assert_eq!(system_via.state(), expected);
+  assert_eq!(system_via.latches(), (0x0F, 0x0C));
+  assert_eq!(devices.ic32.latch(), 0x0F);
+  let user_via = devices.user_via.borrow();
+  assert_eq!(user_via.printer_data(), 0x41);
+  assert_eq!((user_via.state().t1c, user_via.state().t1_active), (0xFFFF, false));
+
+  let video_ula = devices.video_ula.borrow();
+  assert_eq!(video_ula.control(), 0xF4);
+  assert_eq!(video_ula.pixels_per_byte(), 2);
+  assert_eq!(video_ula.physical_colour(1), 1); // red
+  let crtc = devices.crtc.borrow();
+  assert_eq!(crtc.start_address(), 0x0600);
+  assert_eq!(crtc.counters(), (0x10, 3, 5));
+  assert_eq!((crtc.row_address(), crtc.memory_address()), (0x0640, 0x0650));
+}
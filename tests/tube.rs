@@ -0,0 +1,98 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use bbc_b::devices::{DevicePage, SheilaPage};
+use bbc_b::devices::keyboard::Keyboard;
+use bbc_b::memory::{Address, MemoryBus, PageDispatcher, ram::RAM};
+use bbc_b::mos6502::CPU;
+use bbc_b::tube::parasite::Parasite;
+
+fn poke(mem: &mut dyn MemoryBus, address: u16, bytes: &[u8]) {
+  for (offset, byte) in bytes.iter().enumerate() {
+    mem.write(Address::from(address + offset as u16), *byte);
+  }
+}
+
+// Client ROM at &F800: copy first page to RAM underneath, before the Tube
+// access pages ROM out; count resets in &70, then answer each R2 byte with its
+// successor
+fn client_rom() -> Vec<u8> {
+  let mut rom = vec![0xEA; 0x800];
+  let code = [
+    0xA2, 0x00,             // LDX #0
+    0xBD, 0x00, 0xF8,       // .copy LDA &F800,X
+    0x9D, 0x00, 0xF8,       // STA &F800,X
+    0xE8,                   // INX
+    0xD0, 0xF7,             // BNE copy
+    0xE6, 0x70,             // INC &70
+    0xAD, 0xFA, 0xFE,       // .wait LDA &FEFA    R2 status
+    0x10, 0xFB,             // BPL wait
+    0xAD, 0xFB, 0xFE,       // LDA &FEFB
+    0x18, 0x69, 0x01,       // CLC: ADC #1
+    0x2C, 0xFA, 0xFE,       // .full BIT &FEFA
+    0x50, 0xFB,             // BVC full
+    0x8D, 0xFB, 0xFE,       // STA &FEFB
+    0x4C, 0x0D, 0xF8,       // JMP wait
+  ];
+  rom[..code.len()].copy_from_slice(&code);
+  rom[0x7FC] = 0x00; // reset vector &F800
+  rom[0x7FD] = 0xF8;
+  rom
+}
+
+#[test]
+fn host_and_parasite_talk_through_r2() {
+  let mut mem = PageDispatcher::new(Box::new(RAM::new()));
+  let mut sheila = SheilaPage::new(Rc::new(RefCell::new(Keyboard::new())));
+  let tube = sheila.attach_tube();
+  let parasite = Rc::new(RefCell::new(Parasite::new(tube, &client_rom())));
+  let mut clocked_devices = sheila.get_clocked_devices();
+  clocked_devices.push(parasite.clone());
+  mem.add_backend(SheilaPage::page(), Box::new(sheila));
+
+  // send 41, 42 and 43; reset parasite with P flag; send 44
+  poke(&mut mem, 0x2000, &[
+    0xA2, 0x00,             // LDX #0
+    0xA9, 0x41,             // LDA #&41
+    0x8D, 0xE3, 0xFE,       // .send STA &FEE3
+    0xAD, 0xE2, 0xFE,       // .wait LDA &FEE2
+    0x10, 0xFB,             // BPL wait
+    0xAD, 0xE3, 0xFE,       // LDA &FEE3
+    0x95, 0x70,             // STA &70,X
+    0xE8,                   // INX
+    0xE0, 0x03,             // CPX #3
+    0xD0, 0x0A,             // BNE skip
+    0xA0, 0xA0,             // LDY #&A0       S and P
+    0x8C, 0xE0, 0xFE,       // STY &FEE0
+    0xA0, 0x20,             // LDY #&20       clear P
+    0x8C, 0xE0, 0xFE,       // STY &FEE0
+    0xE0, 0x04,             // .skip CPX #4
+    0xD0, 0xE0,             // BNE send
+    0x4C, 0x24, 0x20,       // .done JMP done
+  ]);
+  let mut cpu = CPU::new();
+  cpu.registers.pc = Address::from(0x2000);
+  while cpu.registers.pc != Address::from(0x2024) && cpu.cycles < 100_000 {
+    cpu.step(&mut mem);
+    for device in clocked_devices.iter() {
+      device.borrow_mut().step(cpu.micros());
+    }
+  }
+
+  assert_eq!(cpu.registers.pc, Address::from(0x2024));
+  let replies: Vec<u8> = (0x70..0x74).map(|a| mem.read(Address::from(a))).collect();
+  assert_eq!(replies, [0x42, 0x43, 0x44, 0x45]);
+  let parasite = parasite.borrow();
+  assert!(parasite.micros() >= cpu.micros());
+  assert_eq!(parasite.memory.read(Address::from(0x0070)), 2); // reset once
+  assert_eq!(parasite.memory.read(Address::from(0xFFFD)), 0); // RAM, ROM is out
+}
+
+#[test]
+fn client_rom_too_big() {
+  let mut sheila = SheilaPage::new(Rc::new(RefCell::new(Keyboard::new())));
+  let tube = sheila.attach_tube();
+  let error = Parasite::load_rom(tube, "images/Basic2.rom").err().unwrap();
+  assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+  assert_eq!(error.to_string(), "client ROM of 16384 bytes, not 1 to 4096");
+}